@@ -0,0 +1,149 @@
+/*
+ * The chip8 emulator as a C library.
+ *
+ *     chip8_t *emulator = chip8_new();
+ *     if (chip8_load_rom(emulator, rom, rom_size) != CHIP8_OK) { ... }
+ *     chip8_set_key(emulator, 0x5, true);
+ *     chip8_run_frame(emulator);            // 60 times a second
+ *     const uint8_t *pixels = chip8_framebuffer(emulator);
+ *     chip8_free(emulator);
+ *
+ * Link against libchip8_capi (cargo build -p chip8-capi). An emulator must only be used
+ * by one thread at a time, different emulators are independent.
+ */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CHIP8_WIDTH 64
+#define CHIP8_HEIGHT 32
+#define CHIP8_MEMORY_SIZE 4096
+#define CHIP8_PROGRAM_START 0x200
+
+typedef struct chip8 chip8_t;
+
+typedef enum chip8_error {
+    CHIP8_OK = 0,
+    CHIP8_ERROR_EMPTY_ROM = 1,
+    CHIP8_ERROR_ROM_TOO_LARGE = 2,
+    CHIP8_ERROR_NOT_A_SAVE_STATE = 3,
+    CHIP8_ERROR_UNSUPPORTED_STATE_VERSION = 4,
+    CHIP8_ERROR_TRUNCATED_STATE = 5,
+    CHIP8_ERROR_STATE_MEMORY_MISMATCH = 6,
+    /* A NULL pointer, an unknown quirks preset or a buffer that is too small */
+    CHIP8_ERROR_INVALID_ARGUMENT = 7,
+    /* PC, SP or the stack of a save state are out of range */
+    CHIP8_ERROR_INVALID_STATE = 8,
+} chip8_error_t;
+
+typedef enum chip8_warning_kind {
+    CHIP8_WARNING_NONE = 0,
+    /* An opcode that isn't a chip8 instruction, it was skipped */
+    CHIP8_WARNING_UNKNOWN_OPCODE = 1,
+    /* RET with an empty stack */
+    CHIP8_WARNING_INVALID_RETURN = 2,
+    /* CALL with a full stack, the return address is lost */
+    CHIP8_WARNING_STACK_OVERFLOW = 3,
+    /* An access to memory past its end, reads give 0 and writes are dropped */
+    CHIP8_WARNING_OUT_OF_BOUNDS = 4,
+} chip8_warning_kind_t;
+
+/* A problem the program ran into, the emulator carries on after it */
+typedef struct chip8_warning {
+    chip8_warning_kind_t kind;
+    /* The address of the instruction */
+    uint16_t addr;
+    /* The opcode, for CHIP8_WARNING_UNKNOWN_OPCODE */
+    uint16_t opcode;
+    /* The address accessed, for CHIP8_WARNING_OUT_OF_BOUNDS */
+    uint32_t target;
+} chip8_warning_t;
+
+/* The registers of the CPU */
+typedef struct chip8_cpu {
+    uint8_t v[16];
+    uint16_t i;
+    uint16_t pc;
+    uint8_t sp;
+    uint8_t dt;
+    uint8_t st;
+    uint16_t stack[16];
+} chip8_cpu_t;
+
+/* A description of an error code, never NULL */
+const char *chip8_error_message(chip8_error_t error);
+
+/* An emulator with the chip8 quirks running 10 instructions per frame, free it with chip8_free */
+chip8_t *chip8_new(void);
+void chip8_free(chip8_t *emulator);
+
+/* Seeds the random number generator of CXNN, to make runs reproducible */
+void chip8_seed(chip8_t *emulator, uint64_t seed);
+
+/* Picks the quirks by preset name: chip8, vip, schip or xochip */
+chip8_error_t chip8_set_quirks(chip8_t *emulator, const char *preset);
+
+/* Instructions executed per frame */
+void chip8_set_speed(chip8_t *emulator, uint32_t instructions_per_frame);
+
+/* Resets the machine and loads a program at 0x200 */
+chip8_error_t chip8_load_rom(chip8_t *emulator, const uint8_t *data, size_t size);
+
+/* Puts the machine back into its power-on state */
+void chip8_reset(chip8_t *emulator);
+
+/* Executes a single instruction, returns its opcode */
+uint16_t chip8_step(chip8_t *emulator);
+
+/* Runs a frame worth of instructions and ticks the timers, to be called at 60Hz */
+void chip8_run_frame(chip8_t *emulator);
+
+/* Counts the delay and sound timers down, for callers that use chip8_step */
+void chip8_tick_timers(chip8_t *emulator);
+
+/* Presses or releases a key of the hexadecimal keypad (0x0 - 0xF) */
+void chip8_set_key(chip8_t *emulator, uint8_t key, bool pressed);
+
+/* CHIP8_WIDTH * CHIP8_HEIGHT bytes, row by row, 0 for pixels that are off. Valid until
+ * the emulator runs again. */
+const uint8_t *chip8_framebuffer(const chip8_t *emulator);
+
+/* CHIP8_MEMORY_SIZE bytes, valid until the emulator runs again */
+const uint8_t *chip8_memory(const chip8_t *emulator);
+
+void chip8_cpu(const chip8_t *emulator, chip8_cpu_t *cpu);
+
+/* True while the sound timer runs */
+bool chip8_sound_active(const chip8_t *emulator);
+
+uint64_t chip8_instructions_executed(const chip8_t *emulator);
+
+/* Takes the oldest warning that wasn't taken yet, returns false when there is none. The
+ * emulator keeps the first 16 until they are taken and never prints them. */
+bool chip8_next_warning(chip8_t *emulator, chip8_warning_t *warning);
+
+/* Drops the warnings that weren't taken yet */
+void chip8_clear_warnings(chip8_t *emulator);
+
+/* The size of a save state in bytes */
+size_t chip8_state_size(void);
+
+/* Writes a save state into data, which has to hold chip8_state_size() bytes */
+chip8_error_t chip8_save_state(const chip8_t *emulator, uint8_t *data, size_t size);
+
+/* Restores a state written by chip8_save_state, the emulator is left untouched on errors */
+chip8_error_t chip8_load_state(chip8_t *emulator, const uint8_t *data, size_t size);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
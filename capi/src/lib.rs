@@ -0,0 +1,278 @@
+// The C interface declared in include/chip8.h. Emulators are handed out as opaque
+// pointers, every function checks them for NULL and does nothing (or returns a neutral
+// value) when they are.
+
+// The functions follow the contract of include/chip8.h, there is nothing to add
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+use chip8::emulator::error::{Error, Warning};
+use chip8::emulator::quirks::Quirks;
+use chip8::emulator::{Emulator, STATE_SIZE};
+
+// chip8_error_t
+pub const CHIP8_OK: i32 = 0;
+pub const CHIP8_ERROR_EMPTY_ROM: i32 = 1;
+pub const CHIP8_ERROR_ROM_TOO_LARGE: i32 = 2;
+pub const CHIP8_ERROR_NOT_A_SAVE_STATE: i32 = 3;
+pub const CHIP8_ERROR_UNSUPPORTED_STATE_VERSION: i32 = 4;
+pub const CHIP8_ERROR_TRUNCATED_STATE: i32 = 5;
+pub const CHIP8_ERROR_STATE_MEMORY_MISMATCH: i32 = 6;
+pub const CHIP8_ERROR_INVALID_ARGUMENT: i32 = 7;
+pub const CHIP8_ERROR_INVALID_STATE: i32 = 8;
+
+// chip8_cpu_t
+#[repr(C)]
+pub struct Cpu {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+    pub stack: [u16; 16],
+}
+
+// chip8_warning_kind_t
+pub const CHIP8_WARNING_NONE: i32 = 0;
+pub const CHIP8_WARNING_UNKNOWN_OPCODE: i32 = 1;
+pub const CHIP8_WARNING_INVALID_RETURN: i32 = 2;
+pub const CHIP8_WARNING_STACK_OVERFLOW: i32 = 3;
+pub const CHIP8_WARNING_OUT_OF_BOUNDS: i32 = 4;
+
+// chip8_warning_t
+#[repr(C)]
+pub struct WarningInfo {
+    pub kind: i32,
+    pub addr: u16,
+    pub opcode: u16,
+    pub target: u32,
+}
+
+fn error_code(result: Result<(), Error>) -> i32 {
+    match result {
+        Ok(()) => CHIP8_OK,
+        Err(Error::EmptyRom) => CHIP8_ERROR_EMPTY_ROM,
+        Err(Error::RomTooLarge(_)) => CHIP8_ERROR_ROM_TOO_LARGE,
+        Err(Error::NotASaveState) => CHIP8_ERROR_NOT_A_SAVE_STATE,
+        Err(Error::UnsupportedStateVersion(_)) => CHIP8_ERROR_UNSUPPORTED_STATE_VERSION,
+        Err(Error::TruncatedState) => CHIP8_ERROR_TRUNCATED_STATE,
+        Err(Error::StateMemoryMismatch(_)) => CHIP8_ERROR_STATE_MEMORY_MISMATCH,
+        Err(Error::InvalidState) => CHIP8_ERROR_INVALID_STATE,
+        Err(Error::BufferTooSmall(_)) => CHIP8_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_error_message(error: i32) -> *const c_char {
+    let message: &'static [u8] = match error {
+        CHIP8_OK => b"no error\0",
+        CHIP8_ERROR_EMPTY_ROM => b"the ROM is empty\0",
+        CHIP8_ERROR_ROM_TOO_LARGE => b"the ROM doesn't fit into memory\0",
+        CHIP8_ERROR_NOT_A_SAVE_STATE => b"not a chip8 save state\0",
+        CHIP8_ERROR_UNSUPPORTED_STATE_VERSION => b"unsupported save state version\0",
+        CHIP8_ERROR_TRUNCATED_STATE => b"the save state is truncated\0",
+        CHIP8_ERROR_STATE_MEMORY_MISMATCH => b"the save state has a different amount of memory\0",
+        CHIP8_ERROR_INVALID_ARGUMENT => b"invalid argument\0",
+        CHIP8_ERROR_INVALID_STATE => b"the save state holds an invalid CPU state\0",
+        _ => b"unknown error\0",
+    };
+
+    message.as_ptr() as *const c_char
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Emulator {
+    // Warnings are taken with chip8_next_warning, a library has no business writing to
+    // the stderr of its host
+    let mut emulator = Emulator::new();
+    emulator.set_print_warnings(false);
+
+    Box::into_raw(Box::new(emulator))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(emulator: *mut Emulator) {
+    if !emulator.is_null() {
+        drop(Box::from_raw(emulator));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_seed(emulator: *mut Emulator, seed: u64) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.seed(seed);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_quirks(emulator: *mut Emulator, preset: *const c_char) -> i32 {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) if !preset.is_null() => emulator,
+        _ => return CHIP8_ERROR_INVALID_ARGUMENT,
+    };
+
+    match CStr::from_ptr(preset).to_str().ok().and_then(Quirks::preset) {
+        Some(quirks) => {
+            emulator.set_quirks(quirks);
+            CHIP8_OK
+        },
+        None => CHIP8_ERROR_INVALID_ARGUMENT,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_speed(emulator: *mut Emulator, instructions_per_frame: u32) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.set_speed(instructions_per_frame);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(emulator: *mut Emulator, data: *const u8, size: usize) -> i32 {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) if !data.is_null() || size == 0 => emulator,
+        _ => return CHIP8_ERROR_INVALID_ARGUMENT,
+    };
+
+    let program = if size == 0 { &[] } else { slice::from_raw_parts(data, size) };
+
+    error_code(emulator.load_rom(program))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(emulator: *mut Emulator) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.reset();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(emulator: *mut Emulator) -> u16 {
+    match emulator.as_mut() {
+        Some(emulator) => emulator.step(),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(emulator: *mut Emulator) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.run_frame();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick_timers(emulator: *mut Emulator) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.tick_timers();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(emulator: *mut Emulator, key: u8, pressed: bool) {
+    if let Some(emulator) = emulator.as_mut() {
+        emulator.set_key(key, pressed);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(emulator: *const Emulator) -> *const u8 {
+    match emulator.as_ref() {
+        // The rows are laid out one after the other
+        Some(emulator) => emulator.framebuffer().pixel_repr.as_ptr() as *const u8,
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_memory(emulator: *const Emulator) -> *const u8 {
+    match emulator.as_ref() {
+        Some(emulator) => emulator.memory().as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_cpu(emulator: *const Emulator, cpu: *mut Cpu) {
+    let (emulator, out) = match (emulator.as_ref(), cpu.as_mut()) {
+        (Some(emulator), Some(out)) => (emulator, out),
+        _ => return,
+    };
+
+    let cpu = emulator.cpu();
+    *out = Cpu {
+        v: cpu.registers,
+        i: cpu.VI,
+        pc: cpu.PC,
+        sp: cpu.SP,
+        dt: cpu.timer.DT,
+        st: cpu.timer.ST,
+        stack: cpu.stack,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(emulator: *const Emulator) -> bool {
+    emulator.as_ref().is_some_and(|emulator| emulator.sound_active())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_instructions_executed(emulator: *const Emulator) -> u64 {
+    emulator.as_ref().map_or(0, |emulator| emulator.instructions_executed())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_next_warning(emulator: *mut Emulator, warning: *mut WarningInfo) -> bool {
+    let (emulator, out) = match (emulator.as_mut(), warning.as_mut()) {
+        (Some(emulator), Some(out)) => (emulator, out),
+        _ => return false,
+    };
+
+    let (kind, addr, opcode, target) = match emulator.next_warning() {
+        Some(Warning::UnknownOpcode { opcode, addr }) => (CHIP8_WARNING_UNKNOWN_OPCODE, addr, opcode, 0),
+        Some(Warning::InvalidReturn { addr }) => (CHIP8_WARNING_INVALID_RETURN, addr, 0, 0),
+        Some(Warning::StackOverflow { addr }) => (CHIP8_WARNING_STACK_OVERFLOW, addr, 0, 0),
+        Some(Warning::OutOfBounds { addr, target }) => (CHIP8_WARNING_OUT_OF_BOUNDS, addr, 0, target as u32),
+        None => (CHIP8_WARNING_NONE, 0, 0, 0),
+    };
+    *out = WarningInfo { kind, addr, opcode, target };
+
+    kind != CHIP8_WARNING_NONE
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_clear_warnings(emulator: *mut Emulator) {
+    if let Some(emulator) = emulator.as_mut() {
+        while emulator.next_warning().is_some() {}
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_state_size() -> usize {
+    STATE_SIZE
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(emulator: *const Emulator, data: *mut u8, size: usize) -> i32 {
+    let emulator = match emulator.as_ref() {
+        Some(emulator) if !data.is_null() => emulator,
+        _ => return CHIP8_ERROR_INVALID_ARGUMENT,
+    };
+
+    error_code(emulator.save_state_into(slice::from_raw_parts_mut(data, size)).map(|_| ()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(emulator: *mut Emulator, data: *const u8, size: usize) -> i32 {
+    let emulator = match emulator.as_mut() {
+        Some(emulator) if !data.is_null() => emulator,
+        _ => return CHIP8_ERROR_INVALID_ARGUMENT,
+    };
+
+    error_code(emulator.load_state(slice::from_raw_parts(data, size)))
+}
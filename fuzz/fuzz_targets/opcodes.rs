@@ -0,0 +1,22 @@
+// Executes arbitrary opcodes one after the other, whatever the PC says
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8_fuzz::{check_invariants, check_save_state, emulator_for};
+
+fuzz_target!(|data: &[u8]| {
+    let (config, opcodes) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut emulator = emulator_for(*config);
+
+    for pair in opcodes.chunks_exact(2) {
+        emulator.execute((pair[0] as u16) << 8 | pair[1] as u16);
+        check_invariants(&emulator);
+    }
+
+    check_save_state(&emulator);
+});
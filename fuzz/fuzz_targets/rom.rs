@@ -0,0 +1,36 @@
+// Runs arbitrary bytes as a ROM for a few frames, with keys pressed and released
+// along the way
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8_fuzz::{check_invariants, check_save_state, emulator_for};
+
+const FRAMES: usize = 30;
+
+fuzz_target!(|data: &[u8]| {
+    // config, keys for every frame, ROM
+    if data.len() < 3 {
+        return;
+    }
+
+    let mut emulator = emulator_for(data[0]);
+    let keys = &data[1..3];
+    if emulator.load_rom(&data[3..]).is_err() {
+        return;
+    }
+
+    for frame in 0..FRAMES {
+        // A different key goes down or up every frame
+        let key = keys[frame % 2].wrapping_add(frame as u8);
+        emulator.set_key(key & 0xF, key & 0x10 != 0);
+
+        for _ in 0..emulator.speed() {
+            emulator.step();
+            check_invariants(&emulator);
+        }
+        emulator.tick_timers();
+    }
+
+    check_save_state(&emulator);
+});
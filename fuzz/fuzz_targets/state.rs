@@ -0,0 +1,40 @@
+// Loads save states with arbitrary contents and runs from them. The fuzzer would rarely
+// come up with the header by itself, so the input is laid over a valid state.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8::emulator::Emulator;
+use chip8_fuzz::{check_invariants, check_save_state};
+
+// "C8ST" and the version
+const HEADER_SIZE: usize = 5;
+
+const STEPS: usize = 1000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = Emulator::builder().seed(0).build();
+
+    // Inputs shorter than a state give truncated states
+    let mut state = emulator.save_state();
+    let len = (HEADER_SIZE + data.len()).min(state.len());
+    state.truncate(len);
+    for (byte, input) in state[HEADER_SIZE..].iter_mut().zip(data) {
+        *byte = *input;
+    }
+
+    let before = emulator.save_state();
+    if emulator.load_state(&state).is_err() {
+        assert_eq!(emulator.save_state(), before, "a state that didn't load changed the emulator");
+        return;
+    }
+
+    check_invariants(&emulator);
+
+    for _ in 0..STEPS {
+        emulator.step();
+        check_invariants(&emulator);
+    }
+
+    check_save_state(&emulator);
+});
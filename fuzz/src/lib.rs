@@ -0,0 +1,43 @@
+// What the fuzz targets share: the invariants the interpreter has to keep after every
+// instruction, whatever it was fed.
+
+use chip8::emulator::quirks::{Quirks, PRESET_NAMES};
+use chip8::emulator::{Emulator, MEMORY_SIZE};
+
+// Panics (which the fuzzer reports as a crash) if the machine is in a state the
+// interpreter can't continue from
+pub fn check_invariants(emulator: &Emulator) {
+    let cpu = emulator.cpu();
+
+    assert!((cpu.PC as usize) < MEMORY_SIZE, "PC {:#x} is outside of memory", cpu.PC);
+    assert!((cpu.SP as usize) < cpu.stack.len(), "SP {} is past the stack", cpu.SP);
+    for entry in &cpu.stack[1..=cpu.SP as usize] {
+        assert!((*entry as usize) < MEMORY_SIZE, "return address {:#x} is outside of memory", entry);
+    }
+
+    assert_eq!(emulator.memory().len(), MEMORY_SIZE);
+    for row in emulator.framebuffer().pixel_repr.iter() {
+        assert!(row.iter().all(|pixel| *pixel <= 1), "pixel values other than 0 and 1");
+    }
+}
+
+// Every state the machine gets into can be saved and loaded again
+pub fn check_save_state(emulator: &Emulator) {
+    let state = emulator.save_state();
+
+    let mut restored = Emulator::builder().seed(0).build();
+    restored.load_state(&state).expect("a saved state doesn't load");
+
+    assert_eq!(restored.save_state(), state, "the loaded state differs from the saved one");
+}
+
+// An emulator configured by the first byte of the input: the quirk preset and the speed
+pub fn emulator_for(config: u8) -> Emulator {
+    let preset = PRESET_NAMES[config as usize % PRESET_NAMES.len()];
+
+    Emulator::builder()
+        .seed(0)
+        .quirks(Quirks::preset(preset).unwrap())
+        .speed(1 + (config >> 2) as u32)
+        .build()
+}
@@ -0,0 +1,217 @@
+// Loads the core the way a libretro frontend would and runs a ROM headless, to try the
+// core out without RetroArch:
+//
+//   cargo build -p chip8-libretro
+//   cargo run -p chip8-libretro --example harness -- target/debug/libchip8_libretro.so game.ch8 [frames]
+//
+// It prints the last frame as text, checks that a save state taken halfway brings the
+// machine back to the same picture and reports how much audio the core produced.
+
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::sync::Mutex;
+
+use libloading::{Library, Symbol};
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct SystemAvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+// What the core handed to the callbacks during the last frame
+struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+    samples: usize,
+}
+
+static FRAME: Mutex<Frame> = Mutex::new(Frame { width: 0, height: 0, pixels: Vec::new(), samples: 0 });
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        ENVIRONMENT_SET_PIXEL_FORMAT => true,
+        ENVIRONMENT_SET_VARIABLES => {
+            let mut variable = data as *const Variable;
+            while !(*variable).key.is_null() {
+                println!("option {}: {}", CStr::from_ptr((*variable).key).to_string_lossy(),
+                    CStr::from_ptr((*variable).value).to_string_lossy());
+                variable = variable.add(1);
+            }
+            true
+        },
+        // The defaults of all options
+        ENVIRONMENT_GET_VARIABLE => false,
+        ENVIRONMENT_GET_VARIABLE_UPDATE => {
+            *(data as *mut bool) = false;
+            true
+        },
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let mut frame = FRAME.lock().unwrap();
+
+    frame.width = width as usize;
+    frame.height = height as usize;
+    frame.pixels.clear();
+    for y in 0..height as usize {
+        let row = (data as *const u8).add(y * pitch) as *const u32;
+        frame.pixels.extend_from_slice(std::slice::from_raw_parts(row, width as usize));
+    }
+}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    FRAME.lock().unwrap().samples += frames;
+
+    frames
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+unsafe extern "C" fn input_poll() {}
+
+// No buttons are pressed
+unsafe extern "C" fn input_state(_port: c_uint, _device: c_uint, _index: c_uint, _id: c_uint) -> i16 {
+    0
+}
+
+fn screen() -> String {
+    let frame = FRAME.lock().unwrap();
+
+    frame.pixels.chunks(frame.width.max(1))
+        .map(|row| row.iter().map(|pixel| if *pixel != 0 { '#' } else { '.' }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        return Err(format!("usage: {} <core> <rom> [frames]", args[0]));
+    }
+
+    let frames: u32 = match args.get(3) {
+        Some(frames) => frames.parse().map_err(|_| format!("not a number of frames: {}", frames))?,
+        None => 120,
+    };
+
+    let rom = fs::read(&args[2])
+        .map_err(|err| format!("Couldn't read {}: {}", args[2], err))?;
+
+    unsafe {
+        let core = Library::new(&args[1])
+            .map_err(|err| format!("Couldn't load {}: {}", args[1], err))?;
+
+        macro_rules! symbol {
+            ($name:ident: $type:ty) => {
+                let $name: Symbol<$type> = core.get(concat!(stringify!($name), "\0").as_bytes())
+                    .map_err(|err| format!("The core has no {}: {}", stringify!($name), err))?;
+            };
+        }
+
+        symbol!(retro_api_version: unsafe extern "C" fn() -> c_uint);
+        symbol!(retro_set_environment: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, *mut c_void) -> bool));
+        symbol!(retro_set_video_refresh: unsafe extern "C" fn(unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize)));
+        symbol!(retro_set_audio_sample: unsafe extern "C" fn(unsafe extern "C" fn(i16, i16)));
+        symbol!(retro_set_audio_sample_batch: unsafe extern "C" fn(unsafe extern "C" fn(*const i16, usize) -> usize));
+        symbol!(retro_set_input_poll: unsafe extern "C" fn(unsafe extern "C" fn()));
+        symbol!(retro_set_input_state: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16));
+        symbol!(retro_init: unsafe extern "C" fn());
+        symbol!(retro_deinit: unsafe extern "C" fn());
+        symbol!(retro_get_system_av_info: unsafe extern "C" fn(*mut SystemAvInfo));
+        symbol!(retro_load_game: unsafe extern "C" fn(*const GameInfo) -> bool);
+        symbol!(retro_unload_game: unsafe extern "C" fn());
+        symbol!(retro_run: unsafe extern "C" fn());
+        symbol!(retro_serialize_size: unsafe extern "C" fn() -> usize);
+        symbol!(retro_serialize: unsafe extern "C" fn(*mut c_void, usize) -> bool);
+        symbol!(retro_unserialize: unsafe extern "C" fn(*const c_void, usize) -> bool);
+
+        println!("API version {}", retro_api_version());
+
+        retro_set_environment(environment);
+        retro_set_video_refresh(video_refresh);
+        retro_set_audio_sample(audio_sample);
+        retro_set_audio_sample_batch(audio_sample_batch);
+        retro_set_input_poll(input_poll);
+        retro_set_input_state(input_state);
+        retro_init();
+
+        let game = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        if !retro_load_game(&game) {
+            return Err(format!("The core didn't load {}", args[2]));
+        }
+
+        let mut av_info = std::mem::zeroed::<SystemAvInfo>();
+        retro_get_system_av_info(&mut av_info);
+        println!("{}x{} at {} FPS, {} Hz audio", av_info.base_width, av_info.base_height, av_info.fps, av_info.sample_rate);
+
+        let mut state = vec![0; retro_serialize_size()];
+
+        for frame in 0..frames {
+            if frame == frames / 2 && !retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) {
+                return Err("The core couldn't save its state".to_string());
+            }
+
+            retro_run();
+        }
+
+        let last = screen();
+        println!("{}", last);
+        println!("{} audio frames", FRAME.lock().unwrap().samples);
+
+        // Running the second half again from the save state has to end on the same picture
+        if !retro_unserialize(state.as_ptr() as *const c_void, state.len()) {
+            return Err("The core couldn't load its state".to_string());
+        }
+        for _ in frames / 2..frames {
+            retro_run();
+        }
+
+        if screen() == last {
+            println!("Save state OK");
+        }
+        else {
+            return Err("The frame after loading the save state differs".to_string());
+        }
+
+        retro_unload_game();
+        retro_deinit();
+    }
+
+    Ok(())
+}
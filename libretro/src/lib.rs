@@ -0,0 +1,429 @@
+// The emulator as a libretro core. The frontend (RetroArch, ...) loads this library,
+// hands it a ROM and calls retro_run 60 times a second. The core answers with a frame
+// of video, a frame of audio and reads the joypad in between.
+//
+// libretro cores are singletons, the frontend never loads two games into the same
+// instance, so the state lives in a global.
+
+// The retro_* functions follow the contract of libretro.h, there is nothing to add
+#![allow(clippy::missing_safety_doc)]
+
+mod sys;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::slice;
+use std::sync::Mutex;
+
+use chip8::detect;
+use chip8::emulator::framebuffer::{PIXELS_ALONG_X, PIXELS_ALONG_Y};
+use chip8::emulator::quirks::Quirks;
+use chip8::emulator::{Emulator, DEFAULT_INSTRUCTIONS_PER_FRAME, STATE_SIZE};
+
+use sys::*;
+
+const WIDTH: usize = PIXELS_ALONG_X as usize;
+const HEIGHT: usize = PIXELS_ALONG_Y as usize;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / 60) as usize;
+
+// The buzzer is a plain square wave
+const TONE: u32 = 440;
+const VOLUME: i16 = 0x1000;
+
+// The classic palette of the SDL frontend, indexed by the value of a pixel
+const COLORS: [u32; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
+
+// The key of the hexadecimal keypad each joypad button presses. The d-pad and A sit on
+// 2/4/6/8 and 5, which most games use for movement and action.
+const JOYPAD_KEYS: [(c_uint, u8); 16] = [
+    (DEVICE_ID_JOYPAD_UP, 0x2),
+    (DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (DEVICE_ID_JOYPAD_A, 0x5),
+    (DEVICE_ID_JOYPAD_B, 0xA),
+    (DEVICE_ID_JOYPAD_X, 0xB),
+    (DEVICE_ID_JOYPAD_Y, 0x0),
+    (DEVICE_ID_JOYPAD_L, 0x1),
+    (DEVICE_ID_JOYPAD_R, 0x3),
+    (DEVICE_ID_JOYPAD_L2, 0x7),
+    (DEVICE_ID_JOYPAD_R2, 0x9),
+    (DEVICE_ID_JOYPAD_L3, 0xC),
+    (DEVICE_ID_JOYPAD_R3, 0xD),
+    (DEVICE_ID_JOYPAD_START, 0xE),
+    (DEVICE_ID_JOYPAD_SELECT, 0xF),
+];
+
+// The core options, shown in the quick menu of the frontend. The first value is the
+// default.
+const QUIRKS_OPTION: &[u8] = b"chip8_quirks\0";
+const SPEED_OPTION: &[u8] = b"chip8_speed\0";
+const OPTIONS: [(&[u8], &[u8]); 2] = [
+    (QUIRKS_OPTION, b"Quirks; auto|chip8|vip|schip|xochip\0"),
+    (SPEED_OPTION, b"Instructions per frame; 10|15|20|30|50|100|200|500|1000|5\0"),
+];
+
+// The callbacks the frontend registers before loading a game
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    emulator: Emulator,
+    rom: Vec<u8>,
+    // The quirks detected from the ROM, for the "auto" option
+    detected_quirks: Quirks,
+    video: [u32; WIDTH * HEIGHT],
+    audio: [i16; SAMPLES_PER_FRAME * 2],
+    // Position within the square wave, in samples
+    phase: u32,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn callbacks() -> std::sync::MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+// Runs `f` on the loaded game, None if there is none
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> Option<T> {
+    let mut core = CORE.lock().unwrap_or_else(|err| err.into_inner());
+
+    core.as_mut().map(f)
+}
+
+// The value of a core option, None if the frontend doesn't know it
+unsafe fn option(callbacks: &Callbacks, key: &[u8]) -> Option<String> {
+    let environment = callbacks.environment?;
+
+    let mut variable = Variable {
+        key: key.as_ptr() as *const c_char,
+        value: std::ptr::null(),
+    };
+
+    if !environment(ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void) || variable.value.is_null() {
+        return None;
+    }
+
+    Some(CStr::from_ptr(variable.value).to_string_lossy().into_owned())
+}
+
+impl Core {
+    fn new(rom: Vec<u8>) -> Result<Core, String> {
+        let detection = detect::detect(&rom);
+        let quirks = Quirks::preset(detection.quirks).unwrap_or_else(|| detection.platform.default_quirks());
+
+        let mut emulator = Emulator::builder()
+            .quirks(quirks)
+            .build();
+        // The frontend has its own log, stderr of the player isn't it
+        emulator.set_print_warnings(false);
+        emulator.load_rom(&rom)
+            .map_err(|err| err.to_string())?;
+
+        Ok(Core {
+            emulator,
+            rom,
+            detected_quirks: quirks,
+            video: [0; WIDTH * HEIGHT],
+            audio: [0; SAMPLES_PER_FRAME * 2],
+            phase: 0,
+        })
+    }
+
+    unsafe fn apply_options(&mut self, callbacks: &Callbacks) {
+        let quirks = option(callbacks, QUIRKS_OPTION)
+            .and_then(|name| Quirks::preset(&name))
+            .unwrap_or(self.detected_quirks);
+        self.emulator.set_quirks(quirks);
+
+        let speed = option(callbacks, SPEED_OPTION)
+            .and_then(|speed| speed.parse().ok())
+            .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME);
+        self.emulator.set_speed(speed);
+    }
+
+    unsafe fn run(&mut self, callbacks: &Callbacks) {
+        if let Some(input_poll) = callbacks.input_poll {
+            input_poll();
+        }
+
+        if let Some(input_state) = callbacks.input_state {
+            for (button, key) in JOYPAD_KEYS.iter() {
+                let pressed = input_state(0, DEVICE_JOYPAD, 0, *button) != 0;
+                self.emulator.set_key(*key, pressed);
+            }
+        }
+
+        self.emulator.run_frame();
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            self.render();
+            video_refresh(self.video.as_ptr() as *const c_void, WIDTH as c_uint, HEIGHT as c_uint, WIDTH * 4);
+        }
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            self.mix();
+            audio_sample_batch(self.audio.as_ptr(), SAMPLES_PER_FRAME);
+        }
+    }
+
+    fn render(&mut self) {
+        let pixel_repr = &self.emulator.framebuffer().pixel_repr;
+
+        for (y, row) in pixel_repr.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                self.video[y * WIDTH + x] = COLORS[(value & 0b11) as usize];
+            }
+        }
+    }
+
+    // Fills the audio buffer with a frame of the buzzer, or silence
+    fn mix(&mut self) {
+        let period = SAMPLE_RATE / TONE;
+
+        for frame in self.audio.chunks_mut(2) {
+            let sample = if !self.emulator.sound_active() {
+                0
+            }
+            else if self.phase < period / 2 {
+                VOLUME
+            }
+            else {
+                -VOLUME
+            };
+
+            frame[0] = sample;
+            frame[1] = sample;
+            self.phase = (self.phase + 1) % period;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    callbacks().environment = Some(callback);
+
+    let mut variables: Vec<Variable> = OPTIONS.iter()
+        .map(|(key, value)| Variable {
+            key: key.as_ptr() as *const c_char,
+            value: value.as_ptr() as *const c_char,
+        })
+        .collect();
+    // The list ends with an empty entry
+    variables.push(Variable { key: std::ptr::null(), value: std::ptr::null() });
+
+    callback(ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    callbacks().video_refresh = Some(callback);
+}
+
+// Audio is handed over a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    callbacks().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    callbacks().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    callbacks().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    retro_unload_game();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    if info.is_null() {
+        return;
+    }
+
+    *info = SystemInfo {
+        library_name: b"chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|sc8|xo8|rom\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    if info.is_null() {
+        return;
+    }
+
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+// There is only the joypad
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        let rom = core.rom.clone();
+        // The ROM loaded fine the first time, it does again
+        let _ = core.emulator.load_rom(&rom);
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let callbacks = *callbacks();
+
+    let mut updated = false;
+    if let Some(environment) = callbacks.environment {
+        environment(ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void);
+    }
+
+    with_core(|core| {
+        if updated {
+            core.apply_options(&callbacks);
+        }
+
+        core.run(&callbacks);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = slice::from_raw_parts_mut(data as *mut u8, size);
+
+    with_core(|core| core.emulator.save_state_into(data).is_ok()).unwrap_or(false)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = slice::from_raw_parts(data as *const u8, size);
+
+    with_core(|core| core.emulator.load_state(data).is_ok()).unwrap_or(false)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+
+    let callbacks = *callbacks();
+    let environment = match callbacks.environment {
+        Some(environment) => environment,
+        None => return false,
+    };
+
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+        return false;
+    }
+
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+
+    let mut core = match Core::new(rom) {
+        Ok(core) => core,
+        Err(err) => {
+            eprintln!("chip8: {}", err);
+            return false;
+        },
+    };
+    core.apply_options(&callbacks);
+
+    *CORE.lock().unwrap_or_else(|err| err.into_inner()) = Some(core);
+
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// The memory isn't exposed, the emulator only hands out a shared view of it
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
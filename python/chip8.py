@@ -0,0 +1,256 @@
+"""Python bindings for the chip8 emulator, on top of the C interface in capi/include/chip8.h.
+
+This is a plain ctypes wrapper, not an extension module: there is nothing to compile
+besides the shared library, build it first:
+
+    cargo build --release -p chip8-capi
+
+The module looks for it in target/release and target/debug of the repository, set
+CHIP8_LIBRARY to its path to load it from elsewhere.
+
+    import chip8
+
+    emulator = chip8.Emulator(seed=1)
+    emulator.load_rom(open("game.ch8", "rb").read())
+    emulator.set_key(0x5, True)
+    emulator.run_frame()
+    print(emulator.screen_text())
+    print(emulator.registers())
+    for warning in emulator.warnings():
+        print(warning)
+
+The tests next to this file run against the same library:
+
+    python3 -m unittest discover python
+"""
+
+import ctypes
+import os
+import sys
+
+WIDTH = 64
+HEIGHT = 32
+MEMORY_SIZE = 4096
+PROGRAM_START = 0x200
+
+
+class Cpu(ctypes.Structure):
+    _fields_ = [
+        ("v", ctypes.c_uint8 * 16),
+        ("i", ctypes.c_uint16),
+        ("pc", ctypes.c_uint16),
+        ("sp", ctypes.c_uint8),
+        ("dt", ctypes.c_uint8),
+        ("st", ctypes.c_uint8),
+        ("stack", ctypes.c_uint16 * 16),
+    ]
+
+
+class WarningInfo(ctypes.Structure):
+    _fields_ = [
+        ("kind", ctypes.c_int),
+        ("addr", ctypes.c_uint16),
+        ("opcode", ctypes.c_uint16),
+        ("target", ctypes.c_uint32),
+    ]
+
+
+class Chip8Warning:
+    """A problem the program ran into, the emulator carries on after it. The kind is one of
+    unknown-opcode, invalid-return, stack-overflow or out-of-bounds."""
+
+    KINDS = {1: "unknown-opcode", 2: "invalid-return", 3: "stack-overflow", 4: "out-of-bounds"}
+
+    def __init__(self, info):
+        self.kind = self.KINDS[info.kind]
+        self.addr = info.addr
+        self.opcode = info.opcode if self.kind == "unknown-opcode" else None
+        self.target = info.target if self.kind == "out-of-bounds" else None
+
+    def __repr__(self):
+        if self.kind == "unknown-opcode":
+            return "Unknown opcode {:#06x} at {:#x}".format(self.opcode, self.addr)
+        if self.kind == "invalid-return":
+            return "Invalid return at {:#x}".format(self.addr)
+        if self.kind == "stack-overflow":
+            return "Stack overflow at {:#x}".format(self.addr)
+        return "Out of bounds memory access to {:#x} at {:#x}".format(self.target, self.addr)
+
+
+class Chip8Error(Exception):
+    def __init__(self, code):
+        super().__init__(_lib.chip8_error_message(code).decode())
+        self.code = code
+
+
+def _library_path():
+    if "CHIP8_LIBRARY" in os.environ:
+        return os.environ["CHIP8_LIBRARY"]
+
+    if sys.platform == "win32":
+        name = "chip8_capi.dll"
+    elif sys.platform == "darwin":
+        name = "libchip8_capi.dylib"
+    else:
+        name = "libchip8_capi.so"
+
+    root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+    for profile in ("release", "debug"):
+        path = os.path.join(root, "target", profile, name)
+        if os.path.exists(path):
+            return path
+
+    # Let the system search its library path
+    return name
+
+
+def _load():
+    lib = ctypes.CDLL(_library_path())
+
+    emulator = ctypes.c_void_p
+    signatures = {
+        "chip8_error_message": (ctypes.c_char_p, [ctypes.c_int]),
+        "chip8_new": (emulator, []),
+        "chip8_free": (None, [emulator]),
+        "chip8_seed": (None, [emulator, ctypes.c_uint64]),
+        "chip8_set_quirks": (ctypes.c_int, [emulator, ctypes.c_char_p]),
+        "chip8_set_speed": (None, [emulator, ctypes.c_uint32]),
+        "chip8_load_rom": (ctypes.c_int, [emulator, ctypes.c_char_p, ctypes.c_size_t]),
+        "chip8_reset": (None, [emulator]),
+        "chip8_step": (ctypes.c_uint16, [emulator]),
+        "chip8_run_frame": (None, [emulator]),
+        "chip8_tick_timers": (None, [emulator]),
+        "chip8_set_key": (None, [emulator, ctypes.c_uint8, ctypes.c_bool]),
+        "chip8_framebuffer": (ctypes.POINTER(ctypes.c_uint8), [emulator]),
+        "chip8_memory": (ctypes.POINTER(ctypes.c_uint8), [emulator]),
+        "chip8_cpu": (None, [emulator, ctypes.POINTER(Cpu)]),
+        "chip8_sound_active": (ctypes.c_bool, [emulator]),
+        "chip8_instructions_executed": (ctypes.c_uint64, [emulator]),
+        "chip8_next_warning": (ctypes.c_bool, [emulator, ctypes.POINTER(WarningInfo)]),
+        "chip8_clear_warnings": (None, [emulator]),
+        "chip8_state_size": (ctypes.c_size_t, []),
+        "chip8_save_state": (ctypes.c_int, [emulator, ctypes.c_char_p, ctypes.c_size_t]),
+        "chip8_load_state": (ctypes.c_int, [emulator, ctypes.c_char_p, ctypes.c_size_t]),
+    }
+    for name, (restype, argtypes) in signatures.items():
+        function = getattr(lib, name)
+        function.restype = restype
+        function.argtypes = argtypes
+
+    return lib
+
+
+_lib = _load()
+
+
+def _check(code):
+    if code != 0:
+        raise Chip8Error(code)
+
+
+class Emulator:
+    """A chip8 machine. Quirks are a preset name: chip8, vip, schip or xochip."""
+
+    def __init__(self, quirks=None, speed=None, seed=None):
+        self._handle = _lib.chip8_new()
+        if quirks is not None:
+            self.set_quirks(quirks)
+        if speed is not None:
+            self.set_speed(speed)
+        if seed is not None:
+            self.seed(seed)
+
+    def __del__(self):
+        if getattr(self, "_handle", None):
+            _lib.chip8_free(self._handle)
+            self._handle = None
+
+    def seed(self, seed):
+        _lib.chip8_seed(self._handle, seed)
+
+    def set_quirks(self, preset):
+        _check(_lib.chip8_set_quirks(self._handle, preset.encode()))
+
+    def set_speed(self, instructions_per_frame):
+        _lib.chip8_set_speed(self._handle, instructions_per_frame)
+
+    def load_rom(self, rom):
+        rom = bytes(rom)
+        _check(_lib.chip8_load_rom(self._handle, rom, len(rom)))
+
+    def reset(self):
+        _lib.chip8_reset(self._handle)
+
+    def step(self):
+        """Executes a single instruction, returns its opcode"""
+        return _lib.chip8_step(self._handle)
+
+    def run_frame(self):
+        _lib.chip8_run_frame(self._handle)
+
+    def run_frames(self, frames):
+        for _ in range(frames):
+            _lib.chip8_run_frame(self._handle)
+
+    def tick_timers(self):
+        _lib.chip8_tick_timers(self._handle)
+
+    def set_key(self, key, pressed):
+        _lib.chip8_set_key(self._handle, key, pressed)
+
+    def framebuffer(self):
+        """The screen as WIDTH * HEIGHT bytes, row by row"""
+        return ctypes.string_at(_lib.chip8_framebuffer(self._handle), WIDTH * HEIGHT)
+
+    def pixels(self):
+        """The screen as a list of rows"""
+        framebuffer = self.framebuffer()
+        return [list(framebuffer[y * WIDTH:(y + 1) * WIDTH]) for y in range(HEIGHT)]
+
+    def screen_text(self, on="#", off="."):
+        return "\n".join("".join(on if pixel else off for pixel in row) for row in self.pixels())
+
+    def memory(self):
+        return ctypes.string_at(_lib.chip8_memory(self._handle), MEMORY_SIZE)
+
+    def registers(self):
+        cpu = Cpu()
+        _lib.chip8_cpu(self._handle, ctypes.byref(cpu))
+        return {
+            "v": list(cpu.v),
+            "i": cpu.i,
+            "pc": cpu.pc,
+            "sp": cpu.sp,
+            "dt": cpu.dt,
+            "st": cpu.st,
+            "stack": list(cpu.stack),
+        }
+
+    def sound_active(self):
+        return _lib.chip8_sound_active(self._handle)
+
+    def instructions_executed(self):
+        return _lib.chip8_instructions_executed(self._handle)
+
+    def next_warning(self):
+        """The oldest warning that wasn't taken yet, None if there is none"""
+        info = WarningInfo()
+        if not _lib.chip8_next_warning(self._handle, ctypes.byref(info)):
+            return None
+        return Chip8Warning(info)
+
+    def warnings(self):
+        """All the warnings that weren't taken yet, the emulator keeps the first 16"""
+        return list(iter(self.next_warning, None))
+
+    def clear_warnings(self):
+        _lib.chip8_clear_warnings(self._handle)
+
+    def save_state(self):
+        data = ctypes.create_string_buffer(_lib.chip8_state_size())
+        _check(_lib.chip8_save_state(self._handle, data, len(data)))
+        return data.raw
+
+    def load_state(self, data):
+        data = bytes(data)
+        _check(_lib.chip8_load_state(self._handle, data, len(data)))
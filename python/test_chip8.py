@@ -0,0 +1,74 @@
+"""Smoke tests of the Python bindings and the C library under them.
+
+    cargo build -p chip8-capi
+    python3 -m unittest discover python
+"""
+
+import unittest
+
+import chip8
+
+# LD V0, #0A; LD F, V0; DRW V0, V0, 5; JP #206
+DIGIT = bytes([0x60, 0x0A, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06])
+
+
+class EmulatorTest(unittest.TestCase):
+    def test_runs_a_rom(self):
+        emulator = chip8.Emulator(seed=1)
+        emulator.load_rom(DIGIT)
+
+        self.assertEqual(emulator.step(), 0x600A)
+        self.assertEqual(emulator.registers()["v"][0], 0x0A)
+        self.assertEqual(emulator.registers()["pc"], 0x202)
+
+        emulator.run_frame()
+        self.assertEqual(emulator.pixels()[10][10], 1)
+        self.assertEqual(emulator.memory()[chip8.PROGRAM_START:chip8.PROGRAM_START + 2], DIGIT[:2])
+
+    def test_rejects_bad_roms(self):
+        emulator = chip8.Emulator()
+
+        with self.assertRaises(chip8.Chip8Error) as error:
+            emulator.load_rom(b"")
+        self.assertEqual(str(error.exception), "the ROM is empty")
+
+        with self.assertRaises(chip8.Chip8Error):
+            emulator.set_quirks("unknown")
+
+    def test_states_round_trip(self):
+        emulator = chip8.Emulator(seed=1)
+        emulator.load_rom(DIGIT)
+        emulator.run_frame()
+        state = emulator.save_state()
+
+        restored = chip8.Emulator()
+        restored.load_state(state)
+        self.assertEqual(restored.registers(), emulator.registers())
+        self.assertEqual(restored.framebuffer(), emulator.framebuffer())
+
+        with self.assertRaises(chip8.Chip8Error):
+            restored.load_state(state[:100])
+
+    def test_warnings_are_returned_not_printed(self):
+        emulator = chip8.Emulator()
+        # An unknown opcode, then RET with an empty stack
+        emulator.load_rom(bytes([0xE1, 0x00, 0x00, 0xEE]))
+        emulator.step()
+        emulator.step()
+
+        warnings = emulator.warnings()
+        self.assertEqual([warning.kind for warning in warnings], ["unknown-opcode", "invalid-return"])
+        self.assertEqual((warnings[0].opcode, warnings[0].addr), (0xE100, 0x200))
+        self.assertEqual(str(warnings[0]), "Unknown opcode 0xe100 at 0x200")
+        self.assertEqual(warnings[1].addr, 0x202)
+        self.assertIsNone(emulator.next_warning())
+
+        emulator.reset()
+        emulator.load_rom(bytes([0xE1, 0x00]))
+        emulator.step()
+        emulator.clear_warnings()
+        self.assertEqual(emulator.warnings(), [])
+
+
+if __name__ == "__main__":
+    unittest.main()
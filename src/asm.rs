@@ -0,0 +1,784 @@
+//! An assembler for the mnemonics of Cowgod's reference, the syntax `disasm` prints.
+//!
+//! ```text
+//! ; Comments start with a semicolon
+//! SPEED = 4                   ; Constants
+//!
+//! start:                      ; Labels
+//!     LD I, smiley
+//!     LD V0, SPEED + 1        ; Numbers are decimal, #hex, 0xhex, %binary or 0bbinary
+//!     DRW V0, V1, 5
+//!     JP start
+//!
+//! smiley:
+//!     sprite "..####.."       ; One row per string, # or 1 for pixels that are on
+//!     sprite ".#.##.#."
+//!     db %01111110, #3C       ; Bytes
+//!     dw #1234                ; Big endian words
+//!     include "font.asm"      ; Relative to the file it is in
+//!     org #300                ; Pads with zeros up to an address
+//! ```
+//!
+//! ```
+//! use chip8::asm::assemble;
+//!
+//! let assembly = assemble("loop:\n    ADD V0, 1\n    JP loop\n").unwrap();
+//!
+//! assert_eq!(assembly.program, [0x70, 0x01, 0x12, 0x00]);
+//! assert_eq!(assembly.labels["loop"], 0x200);
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::emulator::{MAX_ROM_SIZE, MEMORY_SIZE, PROGRAM_START};
+
+// Includes nested deeper than this are taken for a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An assembled program
+pub struct Assembly {
+    /// The bytes of the ROM, loaded at 0x200
+    pub program: Vec<u8>,
+    /// Label -> address
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// Where assembling stopped
+#[derive(Debug)]
+pub struct AsmError {
+    /// The source file the line is in
+    pub file: String,
+    /// Counted from 1, 0 when the error isn't about a line
+    pub line: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+// A line of source after includes were resolved
+struct Line {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+// What a line assembles into, with its address fixed by the first pass
+struct Statement {
+    line: usize,
+    addr: usize,
+    // In lowercase
+    mnemonic: String,
+    operands: Vec<String>,
+}
+
+enum Operand {
+    V(u16),
+    I,
+    // [I]
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    Value(String),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        }
+        else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+impl Assembly {
+    /// One `ADDR LABEL` line per label, by address, for debuggers and disassemblers
+    pub fn symbol_file(&self) -> String {
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, addr)| (**addr, name.as_str()));
+
+        labels.iter()
+            .map(|(name, addr)| format!("#{:03X} {}\n", addr, name))
+            .collect()
+    }
+}
+
+/// Assembles a source file, includes are relative to its directory
+pub fn assemble_file(path: &Path) -> Result<Assembly, AsmError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|err| AsmError { file: name.clone(), line: 0, message: format!("cannot read source: {}", err) })?;
+
+    let mut lines = Vec::new();
+    add_lines(&name, &source, directory(path), 0, &mut lines)?;
+
+    Assembler::new(lines).assemble()
+}
+
+/// Assembles source text, includes are relative to the current directory
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let mut lines = Vec::new();
+    add_lines("<source>", source, Path::new("."), 0, &mut lines)?;
+
+    Assembler::new(lines).assemble()
+}
+
+// Where the includes of a file are looked up
+fn directory(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new("."))
+}
+
+// Adds the lines of a file, with the ones of the files it includes in their place
+fn add_lines(file: &str, source: &str, dir: &Path, depth: usize, lines: &mut Vec<Line>) -> Result<(), AsmError> {
+    for (idx, text) in source.lines().enumerate() {
+        let line = Line {
+            file: file.to_string(),
+            number: idx + 1,
+            text: strip_comment(text).trim().to_string(),
+        };
+
+        let (mnemonic, rest) = split_mnemonic(&line.text);
+        if !mnemonic.eq_ignore_ascii_case("include") {
+            lines.push(line);
+            continue;
+        }
+
+        let error = |message: String| AsmError { file: file.to_string(), line: idx + 1, message };
+
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(error("includes are nested too deep, do they include each other?".to_string()));
+        }
+
+        // A missing file is reported at the include, not at a line of the file
+        let path = dir.join(parse_string(rest.trim()).map_err(error)?);
+        let source = fs::read_to_string(&path)
+            .map_err(|err| error(format!("cannot include {}: {}", path.display(), err)))?;
+
+        add_lines(&path.display().to_string(), &source, directory(&path), depth + 1, lines)?;
+    }
+
+    Ok(())
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    // Labels and constants
+    symbols: BTreeMap<String, i64>,
+    labels: BTreeMap<String, u16>,
+}
+
+impl Assembler {
+    fn new(lines: Vec<Line>) -> Assembler {
+        Assembler {
+            lines,
+            symbols: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn error(&self, line: usize, message: String) -> AsmError {
+        let line = &self.lines[line];
+        AsmError { file: line.file.clone(), line: line.number, message }
+    }
+
+    fn assemble(mut self) -> Result<Assembly, AsmError> {
+        let statements = self.first_pass()?;
+
+        let mut program = Vec::new();
+        for statement in &statements {
+            let offset = statement.addr - PROGRAM_START;
+            if program.len() < offset {
+                program.resize(offset, 0);
+            }
+
+            let bytes = self.encode(statement)
+                .map_err(|message| self.error(statement.line, message))?;
+            program.extend(bytes);
+        }
+
+        if program.len() > MAX_ROM_SIZE {
+            let file = self.lines.first().map_or_else(String::new, |line| line.file.clone());
+            return Err(AsmError {
+                file,
+                line: 0,
+                message: format!("the program is {} bytes, only {} fit into memory", program.len(), MAX_ROM_SIZE),
+            });
+        }
+
+        Ok(Assembly { program, labels: self.labels })
+    }
+
+    // Defines the labels and constants and settles where every statement goes
+    fn first_pass(&mut self) -> Result<Vec<Statement>, AsmError> {
+        let mut statements = Vec::new();
+        let mut addr = PROGRAM_START;
+
+        for idx in 0..self.lines.len() {
+            let mut text = self.lines[idx].text.clone();
+
+            // Labels, any number of them in front of a statement
+            while let Some(colon) = text.find(':') {
+                let name = text[..colon].trim();
+                if !is_identifier(name) {
+                    break;
+                }
+
+                self.define(idx, name, addr as i64)?;
+                self.labels.insert(name.to_string(), addr as u16);
+                text = text[colon + 1..].trim().to_string();
+            }
+
+            if text.is_empty() {
+                continue;
+            }
+
+            // Constants, NAME = value or NAME equ value
+            let (name, rest) = split_mnemonic(&text);
+            let value = if let Some(value) = rest.trim_start().strip_prefix('=') {
+                Some(value)
+            }
+            else {
+                let (keyword, value) = split_mnemonic(rest.trim_start());
+                if keyword.eq_ignore_ascii_case("equ") { Some(value) } else { None }
+            };
+
+            if let Some(value) = value {
+                if !is_identifier(name) {
+                    return Err(self.error(idx, format!("'{}' isn't a valid name for a constant", name)));
+                }
+
+                let value = self.evaluate(value.trim()).map_err(|message| self.error(idx, message))?;
+                self.define(idx, name, value)?;
+                continue;
+            }
+
+            let statement = Statement {
+                line: idx,
+                addr,
+                mnemonic: name.to_ascii_lowercase(),
+                operands: split_operands(rest),
+            };
+
+            addr = match statement.mnemonic.as_str() {
+                "org" => {
+                    let target = match statement.operands.as_slice() {
+                        [target] => self.evaluate(target).map_err(|message| self.error(idx, message))?,
+                        _ => return Err(self.error(idx, "org takes an address".to_string())),
+                    };
+
+                    if target < addr as i64 || target > MEMORY_SIZE as i64 {
+                        return Err(self.error(idx, format!("org {:#x} is behind the current address {:#x} or outside of memory", target, addr)));
+                    }
+
+                    target as usize
+                },
+                _ => addr + self.size(&statement).map_err(|message| self.error(idx, message))?,
+            };
+
+            statements.push(statement);
+        }
+
+        Ok(statements)
+    }
+
+    fn define(&mut self, line: usize, name: &str, value: i64) -> Result<(), AsmError> {
+        if parse_operand(name).is_some() {
+            return Err(self.error(line, format!("'{}' is a register and can't be used as a name", name)));
+        }
+
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(self.error(line, format!("'{}' is defined twice", name)));
+        }
+
+        Ok(())
+    }
+
+    // The number of bytes a statement takes, which has to be known before the labels are
+    fn size(&self, statement: &Statement) -> Result<usize, String> {
+        let operands = &statement.operands;
+
+        match statement.mnemonic.as_str() {
+            "db" => operands.iter()
+                .map(|operand| if operand.starts_with('"') { parse_string(operand).map(|text| text.len()) } else { Ok(1) })
+                .sum(),
+            "dw" => Ok(operands.len() * 2),
+            "sprite" => Ok(sprite_width(operands)? * operands.len()),
+            _ => Ok(2),
+        }
+    }
+
+    fn encode(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        let operands = &statement.operands;
+
+        match statement.mnemonic.as_str() {
+            "org" => Ok(Vec::new()),
+            "db" => {
+                let mut bytes = Vec::new();
+                for operand in operands {
+                    if operand.starts_with('"') {
+                        bytes.extend(parse_string(operand)?.bytes());
+                    }
+                    else {
+                        bytes.push(self.byte(operand)?);
+                    }
+                }
+                Ok(bytes)
+            },
+            "dw" => {
+                let mut bytes = Vec::new();
+                for operand in operands {
+                    let word = self.ranged(operand, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend(word.to_be_bytes());
+                }
+                Ok(bytes)
+            },
+            "sprite" => {
+                let width = sprite_width(operands)?;
+                let mut bytes = Vec::new();
+                for operand in operands {
+                    let row = parse_string(operand)?;
+                    let mut bits: u16 = 0;
+                    for (idx, pixel) in row.chars().enumerate() {
+                        match pixel {
+                            '#' | '1' => bits |= 0x8000 >> idx,
+                            '.' | '0' | ' ' => {},
+                            _ => return Err(format!("'{}' isn't a pixel, use # or 1 for on and . or 0 for off", pixel)),
+                        }
+                    }
+                    bytes.extend(&bits.to_be_bytes()[..width]);
+                }
+                Ok(bytes)
+            },
+            _ => {
+                let opcode = self.instruction(&statement.mnemonic, operands)?;
+                Ok(opcode.to_be_bytes().to_vec())
+            },
+        }
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[String]) -> Result<u16, String> {
+        use Operand::*;
+
+        let parsed: Vec<Operand> = operands.iter()
+            .map(|operand| parse_operand(operand).unwrap_or_else(|| Value(operand.clone())))
+            .collect();
+
+        let opcode = match (mnemonic, parsed.as_slice()) {
+            ("cls", []) => 0x00E0,
+            ("ret", []) => 0x00EE,
+            ("sys", [Value(addr)]) => self.addr(addr)?,
+            ("jp", [Value(addr)]) => 0x1000 | self.addr(addr)?,
+            ("jp", [V(0), Value(addr)]) => 0xB000 | self.addr(addr)?,
+            ("call", [Value(addr)]) => 0x2000 | self.addr(addr)?,
+            ("se", [V(x), Value(byte)]) => 0x3000 | x << 8 | self.byte(byte)? as u16,
+            ("sne", [V(x), Value(byte)]) => 0x4000 | x << 8 | self.byte(byte)? as u16,
+            ("se", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+            ("sne", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+            ("ld", [V(x), Value(byte)]) => 0x6000 | x << 8 | self.byte(byte)? as u16,
+            ("ld", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+            ("ld", [I, Value(addr)]) => 0xA000 | self.addr(addr)?,
+            ("ld", [V(x), DT]) => 0xF007 | x << 8,
+            ("ld", [V(x), K]) => 0xF00A | x << 8,
+            ("ld", [DT, V(x)]) => 0xF015 | x << 8,
+            ("ld", [ST, V(x)]) => 0xF018 | x << 8,
+            ("ld", [F, V(x)]) => 0xF029 | x << 8,
+            ("ld", [B, V(x)]) => 0xF033 | x << 8,
+            ("ld", [IndirectI, V(x)]) => 0xF055 | x << 8,
+            ("ld", [V(x), IndirectI]) => 0xF065 | x << 8,
+            ("add", [V(x), Value(byte)]) => 0x7000 | x << 8 | self.byte(byte)? as u16,
+            ("add", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+            ("add", [I, V(x)]) => 0xF01E | x << 8,
+            ("or", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+            ("and", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+            ("xor", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+            ("sub", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+            ("subn", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+            // Vy only matters with the shift quirk off, it defaults to Vx
+            ("shr", [V(x)]) => 0x8006 | x << 8 | x << 4,
+            ("shr", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+            ("shl", [V(x)]) => 0x800E | x << 8 | x << 4,
+            ("shl", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+            ("rnd", [V(x), Value(byte)]) => 0xC000 | x << 8 | self.byte(byte)? as u16,
+            ("drw", [V(x), V(y), Value(rows)]) => 0xD000 | x << 8 | y << 4 | self.ranged(rows, 0, 15)? as u16,
+            ("skp", [V(x)]) => 0xE09E | x << 8,
+            ("sknp", [V(x)]) => 0xE0A1 | x << 8,
+            _ if is_mnemonic(mnemonic) => return Err(format!("invalid operands for {}: '{}'", mnemonic.to_ascii_uppercase(), operands.join(", "))),
+            _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+        };
+
+        Ok(opcode)
+    }
+
+    fn addr(&self, text: &str) -> Result<u16, String> {
+        Ok(self.ranged(text, 0, 0xFFF)? as u16)
+    }
+
+    // Negative bytes are taken as two's complement, ADD V0, -1 subtracts one
+    fn byte(&self, text: &str) -> Result<u8, String> {
+        Ok(self.ranged(text, -0x80, 0xFF)? as u8)
+    }
+
+    fn ranged(&self, text: &str, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.evaluate(text)?;
+
+        if value < min || value > max {
+            return Err(format!("'{}' is {}, which is out of range ({} to {})", text, value, min, max));
+        }
+
+        Ok(value)
+    }
+
+    // Numbers and names added and subtracted
+    fn evaluate(&self, text: &str) -> Result<i64, String> {
+        if text.is_empty() {
+            return Err("missing value".to_string());
+        }
+
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut rest = text.trim();
+
+        loop {
+            if let Some(term) = rest.strip_prefix('-') {
+                sign = -sign;
+                rest = term.trim_start();
+                continue;
+            }
+
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+
+            let value = match parse_number(term) {
+                Some(value) => value,
+                None if is_identifier(term) => *self.symbols.get(term)
+                    .ok_or_else(|| format!("'{}' isn't defined", term))?,
+                None => return Err(format!("'{}' isn't a number or a name", term)),
+            };
+            total = value.checked_mul(sign).and_then(|value| total.checked_add(value))
+                .ok_or_else(|| format!("'{}' is too large", text.trim()))?;
+
+            if end == rest.len() {
+                return Ok(total);
+            }
+
+            sign = if rest[end..].starts_with('-') { -1 } else { 1 };
+            rest = rest[end + 1..].trim_start();
+        }
+    }
+}
+
+fn is_mnemonic(mnemonic: &str) -> bool {
+    matches!(mnemonic, "cls" | "ret" | "sys" | "jp" | "call" | "se" | "sne" | "ld" | "add" | "or" | "and"
+        | "xor" | "sub" | "subn" | "shr" | "shl" | "rnd" | "drw" | "skp" | "sknp")
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let operand = match text.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        register => {
+            let digit = register.strip_prefix('V')?;
+            if digit.len() != 1 {
+                return None;
+            }
+            Operand::V(u16::from_str_radix(digit, 16).ok()?)
+        },
+    };
+
+    Some(operand)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix('#').or_else(|| text.strip_prefix("0x")).or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    }
+    else if let Some(binary) = text.strip_prefix('%').or_else(|| text.strip_prefix("0b")).or_else(|| text.strip_prefix("0B")) {
+        (binary, 2)
+    }
+    else {
+        (text, 10)
+    };
+
+    if digits.is_empty() || !digits.starts_with(|c: char| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    i64::from_str_radix(digits, radix).ok()
+}
+
+// "text" without the quotes, there are no escapes
+fn parse_string(text: &str) -> Result<String, String> {
+    match text.strip_prefix('"').and_then(|text| text.strip_suffix('"')) {
+        Some(text) if !text.contains('"') => Ok(text.to_string()),
+        _ => Err(format!("expected a string in double quotes, got '{}'", text)),
+    }
+}
+
+// The bytes per row of a sprite, 16 pixel wide rows take two
+fn sprite_width(rows: &[String]) -> Result<usize, String> {
+    if rows.is_empty() {
+        return Err("sprite needs at least one row".to_string());
+    }
+
+    let mut width = 1;
+    for row in rows {
+        match parse_string(row)?.chars().count() {
+            0..=8 => {},
+            9..=16 => width = 2,
+            _ => return Err(format!("sprite rows are at most 16 pixels wide, '{}' is wider", row)),
+        }
+    }
+
+    Ok(width)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+// The first word and everything after it
+fn split_mnemonic(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(text.len());
+    (&text[..end], &text[end..])
+}
+
+// Operands are separated by commas, which can also be part of strings
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut operand = String::new();
+    let mut in_string = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                operand.push(c);
+            },
+            ',' if !in_string => operands.push(std::mem::take(&mut operand).trim().to_string()),
+            _ => operand.push(c),
+        }
+    }
+
+    let operand = operand.trim();
+    if !operand.is_empty() || !operands.is_empty() {
+        operands.push(operand.to_string());
+    }
+
+    operands
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..idx],
+            _ => {},
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use std::path::PathBuf;
+    use std::{env, process};
+
+    // The error as it is printed, file:line: message
+    fn error(source: &str) -> String {
+        assemble(source).err().unwrap().to_string()
+    }
+
+    // A directory of source files for one test
+    fn source_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chip8-asm-{}-{}", test, process::id()));
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn disassembly_assembles_back_into_the_program() {
+        let source = "
+            start:
+                CLS
+                LD V0, 0
+                LD V1, 4
+                LD I, smiley
+            draw:
+                DRW V0, V1, 3
+                ADD V0, 8
+                SE V0, 64
+                JP draw
+                CALL wait
+                LD B, V0
+                LD [I], V2
+                JP start
+            wait:
+                LD V3, 30
+                LD DT, V3
+            delay:
+                LD V3, DT
+                SNE V3, 0
+                RET
+                JP delay
+            smiley:
+                sprite \"..####..\"
+                sprite \".#.##.#.\"
+                db %01111110
+        ";
+
+        let program = assemble(source).unwrap().program;
+        let listing = disassemble(&program, PROGRAM_START);
+
+        assert_eq!(assemble(&listing).unwrap().program, program);
+    }
+
+    #[test]
+    fn expressions_are_checked_for_overflow() {
+        let assembly = assemble("BASE = #300\nLD I, BASE - -2 + 1\n").unwrap();
+        assert_eq!(assembly.program, [0xA3, 0x03]);
+
+        assert_eq!(error("LD I, 9223372036854775807 + 1\n"), "<source>:1: '9223372036854775807 + 1' is too large");
+        assert_eq!(error("CLS\nLD I, -9223372036854775807 - 2\n"), "<source>:2: '-9223372036854775807 - 2' is too large");
+    }
+
+    #[test]
+    fn includes_are_assembled_in_place() {
+        let dir = source_dir("include", &[
+            ("main.asm", "CLS\ninclude \"sprites/smiley.asm\"\nLD I, smiley\n"),
+            ("sprites/smiley.asm", "smiley:\ninclude \"row.asm\"\n"),
+            ("sprites/row.asm", "db #3C\n"),
+        ]);
+
+        let assembly = assemble_file(&dir.join("main.asm")).unwrap();
+
+        assert_eq!(assembly.program, [0x00, 0xE0, 0x3C, 0xA2, 0x02]);
+        assert_eq!(assembly.labels["smiley"], 0x202);
+    }
+
+    #[test]
+    fn errors_in_includes_name_their_file_and_line() {
+        let dir = source_dir("include-error", &[
+            ("main.asm", "CLS\ninclude \"bad.asm\"\n"),
+            ("bad.asm", "CLS\n\nJP nowhere\n"),
+        ]);
+
+        let err = assemble_file(&dir.join("main.asm")).err().unwrap();
+
+        assert_eq!(err.file, dir.join("bad.asm").display().to_string());
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "'nowhere' isn't defined");
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let dir = source_dir("include-cycle", &[
+            ("a.asm", "CLS\ninclude \"b.asm\"\n"),
+            ("b.asm", "include \"a.asm\"\n"),
+        ]);
+
+        let err = assemble_file(&dir.join("a.asm")).err().unwrap();
+
+        assert_eq!(err.file, dir.join("a.asm").display().to_string());
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("nested too deep"), "{}", err);
+    }
+
+    #[test]
+    fn missing_includes_are_reported_at_the_include() {
+        let dir = source_dir("include-missing", &[("main.asm", "CLS\n\ninclude \"missing.asm\"\n")]);
+
+        let err = assemble_file(&dir.join("main.asm")).err().unwrap();
+
+        assert_eq!(err.file, dir.join("main.asm").display().to_string());
+        assert_eq!(err.line, 3);
+        assert!(err.message.starts_with("cannot include"), "{}", err);
+        assert!(err.message.contains("missing.asm"), "{}", err);
+    }
+
+    #[test]
+    fn org_pads_up_to_an_address() {
+        let assembly = assemble("CLS\norg #206\nend:\nRET\n").unwrap();
+
+        assert_eq!(assembly.program, [0x00, 0xE0, 0, 0, 0, 0, 0x00, 0xEE]);
+        assert_eq!(assembly.labels["end"], 0x206);
+
+        assert_eq!(error("CLS\nCLS\norg #202\n"), "<source>:3: org 0x202 is behind the current address 0x204 or outside of memory");
+        assert_eq!(error("\norg #1001\n"), "<source>:2: org 0x1001 is behind the current address 0x200 or outside of memory");
+        assert_eq!(error("org\n"), "<source>:1: org takes an address");
+    }
+
+    #[test]
+    fn data_is_assembled_as_written() {
+        let assembly = assemble("db 1, -1, \"A;B\", %101\ndw #1234, -1\n").unwrap();
+
+        assert_eq!(assembly.program, [0x01, 0xFF, b'A', b';', b'B', 0x05, 0x12, 0x34, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn sprites_can_be_16_pixels_wide() {
+        let assembly = assemble("sprite \"########\"\nsprite \"#\", \"#.......#\"\nsprite \"################\"\n").unwrap();
+
+        assert_eq!(assembly.program, [0xFF, 0x80, 0x00, 0x80, 0x80, 0xFF, 0xFF]);
+
+        assert_eq!(
+            error("CLS\nsprite \"#################\"\n"),
+            "<source>:2: sprite rows are at most 16 pixels wide, '\"#################\"' is wider"
+        );
+        assert_eq!(
+            error("sprite \"#x\"\n"),
+            "<source>:1: 'x' isn't a pixel, use # or 1 for on and . or 0 for off"
+        );
+    }
+
+    #[test]
+    fn symbol_files_list_labels_by_address() {
+        let assembly = assemble("SPEED = 4\nstart: CLS\nmain:\nloop: JP start\n").unwrap();
+
+        assert_eq!(assembly.symbol_file(), "#200 start\n#202 loop\n#202 main\n");
+    }
+
+    #[test]
+    fn labels_are_defined_once() {
+        assert_eq!(error("start:\nCLS\nstart: RET\n"), "<source>:3: 'start' is defined twice");
+        assert_eq!(error("SPEED = 1\n\nSPEED:\n"), "<source>:3: 'SPEED' is defined twice");
+        assert_eq!(error("CLS\nJP nowhere\n"), "<source>:2: 'nowhere' isn't defined");
+        assert_eq!(error("v3: CLS\n"), "<source>:1: 'v3' is a register and can't be used as a name");
+    }
+
+    #[test]
+    fn immediates_and_addresses_are_range_checked() {
+        assert_eq!(error("LD V0, 256\n"), "<source>:1: '256' is 256, which is out of range (-128 to 255)");
+        assert_eq!(error("CLS\nADD V0, -129\n"), "<source>:2: '-129' is -129, which is out of range (-128 to 255)");
+        assert_eq!(error("\n\nJP #1000\n"), "<source>:3: '#1000' is 4096, which is out of range (0 to 4095)");
+        assert_eq!(error("LD I, -1\n"), "<source>:1: '-1' is -1, which is out of range (0 to 4095)");
+        assert_eq!(error("DRW V0, V1, 16\n"), "<source>:1: '16' is 16, which is out of range (0 to 15)");
+        assert_eq!(error("CLS\ndw #10000\n"), "<source>:2: '#10000' is 65536, which is out of range (-32768 to 65535)");
+        assert_eq!(error("db 1, 300\n"), "<source>:1: '300' is 300, which is out of range (-128 to 255)");
+    }
+}
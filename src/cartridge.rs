@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use chip8::emulator::quirks::{Platform, Quirks};
+use crate::cli::RunOptions;
+use crate::frontend::palette::parse_color;
+
+// Octo cartridges are GIFs with a label on them and the source of the program hidden in
+// the pixels. The lowest two bits of the palette index of every pixel, frame after frame,
+// are the payload, four pixels to a byte with the most significant bits first. The
+// payload is a 32 bit big endian length followed by that many bytes of JSON:
+//
+//     {"program": "<Octo source>", "options": {"tickrate": 20, "fillColor": "#FFCC00", ...}}
+#[derive(Deserialize)]
+pub struct Cartridge {
+    pub program: String,
+    #[serde(default)]
+    pub options: CartridgeOptions,
+}
+
+// The settings of Octo the program was written with, the ones without a counterpart here
+// (the other plane colours, the buzzer colours, the font, ...) are skipped
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CartridgeOptions {
+    // Instructions per frame
+    pub tickrate: Option<u32>,
+    pub fill_color: Option<String>,
+    pub background_color: Option<String>,
+    // The quirks are named after what Octo does differently from the VIP when they are on
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    // Octo picks the platform by how much memory programs may take
+    pub max_size: Option<u32>,
+}
+
+pub fn is_cartridge(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+}
+
+pub fn read_cartridge(path: &str) -> Result<Cartridge, String> {
+    let data = fs::read(path)
+        .map_err(|err| format!("cannot read cartridge '{}': {}", path, err))?;
+
+    decode(&data).map_err(|err| format!("cannot load cartridge '{}': {}", path, err))
+}
+
+fn decode(data: &[u8]) -> Result<Cartridge, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(data).map_err(|err| err.to_string())?;
+
+    let mut bits = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|err| err.to_string())? {
+        bits.extend(frame.buffer.iter().map(|index| index & 0b11));
+    }
+
+    let payload: Vec<u8> = bits.chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, bits| byte << 2 | bits))
+        .collect();
+
+    if payload.len() < 4 {
+        return Err("not an Octo cartridge, there is no program in it".to_string());
+    }
+
+    let size = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    let json = payload.get(4..4 + size)
+        .ok_or("not an Octo cartridge, there is no program in it")?;
+
+    serde_json::from_slice(json)
+        .map_err(|err| format!("not an Octo cartridge, the program in it is broken: {}", err))
+}
+
+impl CartridgeOptions {
+    // Sets the options that are still None, like a profile of the config does
+    pub fn apply(&self, options: &mut RunOptions) -> Result<(), String> {
+        if options.platform.is_none() && options.quirks.is_none() {
+            options.platform = self.max_size.map(|size| match size {
+                0..=3216 => Platform::Chip8,
+                3217..=3584 => Platform::SuperChip,
+                _ => Platform::XoChip,
+            });
+            options.quirks = self.quirks();
+        }
+
+        if options.speed.is_none() {
+            options.speed = self.tickrate;
+        }
+
+        if options.foreground.is_none() {
+            if let Some(color) = &self.fill_color {
+                options.foreground = Some(parse_color(color)
+                    .ok_or_else(|| format!("invalid fill colour '{}' in the cartridge", color))?);
+            }
+        }
+
+        if options.background.is_none() {
+            if let Some(color) = &self.background_color {
+                options.background = Some(parse_color(color)
+                    .ok_or_else(|| format!("invalid background colour '{}' in the cartridge", color))?);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Octo leaves a quirk off when the cartridge doesn't mention it, but a cartridge
+    // without any of them is better off with the detected platform
+    fn quirks(&self) -> Option<Quirks> {
+        let flags = [self.shift_quirks, self.load_store_quirks, self.jump_quirks,
+            self.logic_quirks, self.clip_quirks, self.v_blank_quirks];
+        if flags.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let on = |flag: Option<bool>| flag.unwrap_or(false);
+
+        Some(Quirks {
+            shift_uses_vy: !on(self.shift_quirks),
+            load_store_increments_i: !on(self.load_store_quirks),
+            jump_uses_vx: on(self.jump_quirks),
+            logic_resets_vf: on(self.logic_quirks),
+            clip_sprites: on(self.clip_quirks),
+            display_wait: on(self.v_blank_quirks),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::pixels::Color;
+
+    // A GIF with the payload in the low bits of the pixels, the way Octo hides it
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+
+        // The high bits are the label on the cartridge, they don't matter
+        let pixels: Vec<u8> = payload.iter()
+            .flat_map(|byte| [byte >> 6, byte >> 4, byte >> 2, *byte])
+            .map(|bits| 0b0100 | bits & 0b11)
+            .collect();
+
+        let palette: Vec<u8> = (0..8).flat_map(|idx| [idx * 32; 3]).collect();
+        let mut data = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut data, pixels.len() as u16, 1, &palette).unwrap();
+            let frame = gif::Frame {
+                width: pixels.len() as u16,
+                height: 1,
+                buffer: pixels.into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        data
+    }
+
+    #[test]
+    fn decodes_the_program_and_its_options() {
+        let data = cartridge(r##"{"program": ": main jump main", "options": {"tickrate": 30,
+            "fillColor": "#FFCC00", "backgroundColor": "#996600", "shiftQuirks": true,
+            "clipQuirks": true, "maxSize": 3584}}"##);
+        let cartridge = decode(&data).unwrap();
+        assert_eq!(cartridge.program, ": main jump main");
+
+        let mut options = RunOptions::default();
+        cartridge.options.apply(&mut options).unwrap();
+
+        assert_eq!(options.speed, Some(30));
+        assert_eq!(options.platform, Some(Platform::SuperChip));
+        assert_eq!(options.foreground, Some(Color::RGB(0xFF, 0xCC, 0x00)));
+        assert_eq!(options.background, Some(Color::RGB(0x99, 0x66, 0x00)));
+
+        let quirks = options.quirks.unwrap();
+        assert!(!quirks.shift_uses_vy && quirks.clip_sprites);
+        assert!(quirks.load_store_increments_i && !quirks.jump_uses_vx && !quirks.display_wait);
+    }
+
+    #[test]
+    fn options_that_are_set_already_win() {
+        let data = cartridge(r##"{"program": "", "options": {"tickrate": 30, "fillColor": "#FFCC00"}}"##);
+        let cartridge = decode(&data).unwrap();
+
+        let mut options = RunOptions { speed: Some(10), ..RunOptions::default() };
+        cartridge.options.apply(&mut options).unwrap();
+
+        assert_eq!(options.speed, Some(10));
+        assert_eq!(options.quirks, None);
+        assert_eq!(options.foreground, Some(Color::RGB(0xFF, 0xCC, 0x00)));
+    }
+
+    #[test]
+    fn rejects_gifs_without_a_program() {
+        let data = cartridge("");
+        assert!(matches!(decode(&data), Err(err) if err.contains("broken")));
+
+        let mut data = cartridge("{}");
+        data.truncate(10);
+        assert!(decode(&data).is_err());
+    }
+}
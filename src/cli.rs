@@ -0,0 +1,407 @@
+use sdl2::pixels::Color;
+
+use chip8::emulator::quirks::{Platform, Quirks, PRESET_NAMES};
+use crate::frontend::filter::Filter;
+use crate::frontend::keymap::Keymap;
+use crate::frontend::palette::{parse_color, Palette};
+
+pub const USAGE: &str = "\
+Usage: chip8 [run] <rom> [options]      Run a ROM in a window, a directory opens the ROM browser
+       chip8 debug <rom> [options]      Step through a ROM in the terminal
+       chip8 disasm [--linear] <rom>    Print the assembly of a ROM
+       chip8 decompile <rom>            Print a ROM as structured pseudocode
+       chip8 lint <rom>                 Check a ROM for likely bugs without running it
+       chip8 asm <source> [-o <rom>]    Assemble a ROM
+       chip8 info <rom>                 Print information about a ROM
+       chip8 report <dir> [options]     Run every ROM in a directory headless and report problems
+
+Options:
+  --speed <n>           Instructions executed per frame, at 60 frames per second (default 10)
+  --platform <name>     chip8, schip or xochip, selects the default quirks
+  --quirks <preset>     chip8, vip, schip or xochip
+  --palette <name>      classic, green, amber, lcd, high-contrast or colorblind
+  --fg RRGGBB           Custom foreground colour
+  --bg RRGGBB           Custom background colour
+  --ghosting <decay>    Reduce flicker by fading pixels out, keeping <decay> of their brightness per frame
+  --persist <frames>    Reduce flicker by keeping pixels lit that were set in any of the last 1-8 frames
+  --fast-forward <n>    Frames run per frame shown while Tab is held, 0 for as fast as possible (default 4)
+  --scale <factor>      Initial window size as a multiple of the 64x32 display (default 12)
+  --integer-scale       Only scale the display by whole multiples
+  --fullscreen          Start in fullscreen
+  --keymap <map>        hex, qwerty or 16 comma separated key names for the keys 0-F
+  --seed <n>            Seed for the random number generator, makes runs reproducible
+  --headless            Run without a window and print the screen when done
+  --benchmark           Run without a window as fast as possible and print the instructions per second
+  --frames <n>          Stop after n frames (headless runs default to 600, benchmarks to 100000)
+  --trace <file>        Log every executed instruction to a file, '-' for stdout
+  --state <file>        Load a save state after loading the ROM
+  --watch               Restart the ROM whenever the file changes
+  --config <file>       Read per-ROM settings from this file instead of the user config
+  --no-config           Ignore the config file
+  -h, --help            Print this help
+
+Report options (and --speed, --platform, --quirks, --seed and --frames from above):
+  --jobs <n>            ROMs run at the same time (default: one per CPU)
+  -o, --output <file>   Write the report to a file instead of stdout";
+
+pub enum Command {
+    Run(RunOptions),
+    Debug(RunOptions),
+    Disasm { rom: String, linear: bool },
+    Decompile { rom: String },
+    Lint { rom: String },
+    Asm { source: String, output: Option<String> },
+    Info { rom: String },
+    Report(ReportOptions),
+    Help,
+}
+
+// Everything that can be configured when running a ROM. Options that weren't given
+// are None, so that other sources of configuration can fill them in.
+#[derive(Default, Clone)]
+pub struct RunOptions {
+    pub rom: String,
+    pub speed: Option<u32>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<Palette>,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub filter: Option<Filter>,
+    pub scale: Option<u32>,
+    pub fast_forward: Option<u32>,
+    pub integer_scaling: bool,
+    pub fullscreen: bool,
+    pub keymap: Option<Keymap>,
+    pub seed: Option<u64>,
+    pub headless: bool,
+    pub benchmark: bool,
+    pub frames: Option<u64>,
+    pub trace: Option<String>,
+    pub state: Option<String>,
+    pub watch: bool,
+    pub config: Option<String>,
+    pub no_config: bool,
+}
+
+// What `report` runs, the run options only carry the settings that apply to every ROM
+#[derive(Default)]
+pub struct ReportOptions {
+    pub dir: String,
+    pub jobs: Option<usize>,
+    pub output: Option<String>,
+    pub run: RunOptions,
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = match args.first().map(|arg| arg.as_str()) {
+        // Without a ROM the ROM browser opens in the current directory
+        None => return Ok(Command::Run(RunOptions { rom: ".".to_string(), ..RunOptions::default() })),
+        Some("-h") | Some("--help") | Some("help") => return Ok(Command::Help),
+        Some("run") => ("run", &args[1..]),
+        Some("debug") => ("debug", &args[1..]),
+        Some("disasm") => ("disasm", &args[1..]),
+        Some("decompile") => ("decompile", &args[1..]),
+        Some("lint") => ("lint", &args[1..]),
+        Some("asm") => ("asm", &args[1..]),
+        Some("info") => ("info", &args[1..]),
+        Some("report") => ("report", &args[1..]),
+        // Plain `chip8 <rom>` runs the ROM
+        Some(_) => ("run", args),
+    };
+
+    if rest.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+
+    match command {
+        "run" => Ok(Command::Run(parse_run_options(rest)?)),
+        "debug" => Ok(Command::Debug(parse_run_options(rest)?)),
+        "disasm" => {
+            // --linear decodes every pair of bytes, for ROMs whose control flow can't be followed
+            let linear = rest.iter().any(|arg| arg == "--linear");
+            let rest: Vec<String> = rest.iter().filter(|arg| *arg != "--linear").cloned().collect();
+
+            Ok(Command::Disasm { rom: single_path(command, &rest)?, linear })
+        },
+        "decompile" => Ok(Command::Decompile { rom: single_path(command, rest)? }),
+        "lint" => Ok(Command::Lint { rom: single_path(command, rest)? }),
+        "info" => Ok(Command::Info { rom: single_path(command, rest)? }),
+        "report" => Ok(Command::Report(parse_report_options(rest)?)),
+        "asm" => {
+            let mut source = None;
+            let mut output = None;
+            let mut args = rest.iter();
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "-o" | "--output" => {
+                        output = Some(args.next()
+                            .ok_or_else(|| format!("{} needs a value", arg))?
+                            .clone());
+                    },
+                    _ if arg.starts_with('-') => return Err(format!("unknown option '{}' for asm", arg)),
+                    _ if source.is_none() => source = Some(arg.clone()),
+                    _ => return Err(format!("unexpected argument '{}'", arg)),
+                }
+            }
+
+            let source = source.ok_or("asm needs a source file")?;
+            Ok(Command::Asm { source, output })
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn single_path(command: &str, args: &[String]) -> Result<String, String> {
+    match args {
+        [path] if !path.starts_with('-') => Ok(path.clone()),
+        [] => Err(format!("{} needs a ROM file", command)),
+        _ => Err(format!("{} takes exactly one ROM file", command)),
+    }
+}
+
+fn parse_run_options(args: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions::default();
+    let mut rom: Option<String> = None;
+
+    let mut idx = 0;
+    while idx < args.len() {
+        let arg = args[idx].as_str();
+
+        // Flags without a value
+        match arg {
+            "--integer-scale" => options.integer_scaling = true,
+            "--fullscreen" => options.fullscreen = true,
+            "--headless" => options.headless = true,
+            "--benchmark" => options.benchmark = true,
+            "--no-config" => options.no_config = true,
+            "--watch" => options.watch = true,
+            _ if !arg.starts_with('-') || arg == "-" => {
+                if rom.is_some() {
+                    return Err(format!("unexpected argument '{}', only one ROM can be run", arg));
+                }
+                rom = Some(arg.to_string());
+            },
+            _ => {
+                let value = args.get(idx + 1)
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                idx += 1;
+
+                parse_option(&mut options, arg, value)?;
+            }
+        }
+
+        idx += 1;
+    }
+
+    options.rom = rom.ok_or("no ROM file given")?;
+
+    Ok(options)
+}
+
+fn parse_report_options(args: &[String]) -> Result<ReportOptions, String> {
+    let mut options = ReportOptions::default();
+    let mut dir: Option<String> = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if dir.is_some() {
+                return Err(format!("unexpected argument '{}', only one directory can be reported on", arg));
+            }
+            dir = Some(arg.clone());
+            continue;
+        }
+
+        let value = args.next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+
+        match arg.as_str() {
+            "--jobs" => options.jobs = Some(parse_number(arg, value, 1, 1024)? as usize),
+            "-o" | "--output" => options.output = Some(value.clone()),
+            "--speed" | "--platform" | "--quirks" | "--seed" | "--frames" => parse_option(&mut options.run, arg, value)?,
+            _ => return Err(format!("unknown option '{}' for report", arg)),
+        }
+    }
+
+    options.dir = dir.ok_or("report needs a directory of ROMs")?;
+
+    Ok(options)
+}
+
+fn parse_option(options: &mut RunOptions, option: &str, value: &str) -> Result<(), String> {
+    match option {
+        "--speed" => {
+            options.speed = Some(parse_number(option, value, 1, 1_000_000)? as u32);
+        },
+        "--platform" => {
+            options.platform = Some(Platform::from_name(value)
+                .ok_or_else(|| format!("unknown platform '{}' (expected chip8, schip or xochip)", value))?);
+        },
+        "--quirks" => {
+            options.quirks = Some(Quirks::preset(value)
+                .ok_or_else(|| format!("unknown quirk preset '{}' (expected {})", value, PRESET_NAMES.join(", ")))?);
+        },
+        "--palette" => {
+            options.palette = Some(Palette::find(value)
+                .ok_or_else(|| format!("unknown palette '{}'", value))?);
+        },
+        "--fg" | "--bg" => {
+            let color = parse_color(value)
+                .ok_or_else(|| format!("invalid colour '{}' for {} (expected RRGGBB)", value, option))?;
+
+            if option == "--fg" {
+                options.foreground = Some(color);
+            }
+            else {
+                options.background = Some(color);
+            }
+        },
+        "--ghosting" => {
+            options.filter = match value.parse::<f32>() {
+                Ok(decay) if (0.0..1.0).contains(&decay) => Some(Filter::Decay(decay)),
+                _ => return Err(format!("invalid decay '{}' (expected a value in [0, 1))", value)),
+            };
+        },
+        "--persist" => {
+            options.filter = Some(Filter::Persist(parse_number(option, value, 1, 8)? as u8));
+        },
+        "--fast-forward" => {
+            options.fast_forward = Some(parse_number(option, value, 0, 1000)? as u32);
+        },
+        "--scale" => {
+            options.scale = Some(parse_number(option, value, 1, 64)? as u32);
+        },
+        "--keymap" => {
+            options.keymap = Some(Keymap::parse(value)
+                .map_err(|err| format!("invalid key map: {}", err))?);
+        },
+        "--seed" => {
+            options.seed = Some(parse_number(option, value, 0, u64::MAX)?);
+        },
+        "--frames" => {
+            options.frames = Some(parse_number(option, value, 1, u64::MAX)?);
+        },
+        "--trace" => {
+            options.trace = Some(value.to_string());
+        },
+        "--state" => {
+            options.state = Some(value.to_string());
+        },
+        "--config" => {
+            options.config = Some(value.to_string());
+        },
+        _ => return Err(format!("unknown option '{}'", option)),
+    }
+
+    Ok(())
+}
+
+// Parses decimal or 0x prefixed hexadecimal numbers within [min, max]
+fn parse_number(option: &str, value: &str, min: u64, max: u64) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse::<u64>(),
+    };
+
+    match parsed {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(format!("invalid value '{}' for {} (expected a number from {} to {})", value, option, min, max)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Command, String> {
+        parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn run_options(args: &str) -> RunOptions {
+        match parse_args(args) {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("'{}' isn't a run", args),
+        }
+    }
+
+    fn error(args: &str) -> String {
+        parse_args(args).err().unwrap()
+    }
+
+    #[test]
+    fn runs_roms_with_options() {
+        let options = run_options("run pong.ch8 --speed 20 --platform schip --quirks vip --seed 0x10 --headless --frames 5");
+        assert_eq!(options.rom, "pong.ch8");
+        assert_eq!(options.speed, Some(20));
+        assert_eq!(options.platform, Some(Platform::SuperChip));
+        assert_eq!(options.quirks, Quirks::preset("vip"));
+        assert_eq!(options.seed, Some(16));
+        assert_eq!(options.frames, Some(5));
+        assert!(options.headless && !options.benchmark);
+
+        // The run command is the default, and without a ROM the browser opens here
+        assert_eq!(run_options("pong.ch8 --persist 3").filter, Some(Filter::Persist(3)));
+        assert_eq!(run_options("").rom, ".");
+        assert_eq!(run_options("- --ghosting 0.5").filter, Some(Filter::Decay(0.5)));
+    }
+
+    #[test]
+    fn parses_every_command() {
+        assert!(matches!(parse_args("debug pong.ch8 --speed 2"), Ok(Command::Debug(options)) if options.rom == "pong.ch8" && options.speed == Some(2)));
+        assert!(matches!(parse_args("disasm pong.ch8"), Ok(Command::Disasm { rom, linear: false }) if rom == "pong.ch8"));
+        assert!(matches!(parse_args("disasm --linear pong.ch8"), Ok(Command::Disasm { rom, linear: true }) if rom == "pong.ch8"));
+        assert!(matches!(parse_args("decompile pong.ch8"), Ok(Command::Decompile { rom }) if rom == "pong.ch8"));
+        assert!(matches!(parse_args("lint pong.ch8"), Ok(Command::Lint { rom }) if rom == "pong.ch8"));
+        assert!(matches!(parse_args("info pong.ch8"), Ok(Command::Info { rom }) if rom == "pong.ch8"));
+        assert!(matches!(parse_args("asm pong.asm"), Ok(Command::Asm { source, output: None }) if source == "pong.asm"));
+        assert!(matches!(parse_args("asm pong.asm -o pong.ch8"), Ok(Command::Asm { source, output: Some(output) }) if source == "pong.asm" && output == "pong.ch8"));
+        assert!(matches!(parse_args("help"), Ok(Command::Help)));
+        assert!(matches!(parse_args("run pong.ch8 --help"), Ok(Command::Help)));
+
+        match parse_args("report roms --jobs 4 -o report.txt --speed 30 --frames 100") {
+            Ok(Command::Report(options)) => {
+                assert_eq!(options.dir, "roms");
+                assert_eq!(options.jobs, Some(4));
+                assert_eq!(options.output.as_deref(), Some("report.txt"));
+                assert_eq!((options.run.speed, options.run.frames), (Some(30), Some(100)));
+            },
+            _ => panic!("not a report"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(error("pong.ch8 --sped 20"), "unknown option '--sped'");
+        assert_eq!(error("asm pong.asm --verbose"), "unknown option '--verbose' for asm");
+        assert_eq!(error("report roms --headless 1"), "unknown option '--headless' for report");
+        assert_eq!(error("pong.ch8 --platform chip9"), "unknown platform 'chip9' (expected chip8, schip or xochip)");
+        assert_eq!(error("pong.ch8 --quirks eti"), "unknown quirk preset 'eti' (expected chip8, vip, schip, xochip)");
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert_eq!(error("pong.ch8 --speed"), "--speed needs a value");
+        assert_eq!(error("asm pong.asm -o"), "-o needs a value");
+        assert_eq!(error("report roms --jobs"), "--jobs needs a value");
+        assert_eq!(error("run --headless"), "no ROM file given");
+        assert_eq!(error("disasm"), "disasm needs a ROM file");
+        assert_eq!(error("lint a.ch8 b.ch8"), "lint takes exactly one ROM file");
+        assert_eq!(error("asm"), "asm needs a source file");
+        assert_eq!(error("report"), "report needs a directory of ROMs");
+        assert_eq!(error("a.ch8 b.ch8"), "unexpected argument 'b.ch8', only one ROM can be run");
+    }
+
+    #[test]
+    fn rejects_bad_numbers() {
+        assert_eq!(error("pong.ch8 --speed fast"), "invalid value 'fast' for --speed (expected a number from 1 to 1000000)");
+        assert_eq!(error("pong.ch8 --speed 0"), "invalid value '0' for --speed (expected a number from 1 to 1000000)");
+        assert_eq!(error("pong.ch8 --persist 9"), "invalid value '9' for --persist (expected a number from 1 to 8)");
+        assert_eq!(error("pong.ch8 --seed -1"), "invalid value '-1' for --seed (expected a number from 0 to 18446744073709551615)");
+        assert_eq!(error("pong.ch8 --scale 0xZ"), "invalid value '0xZ' for --scale (expected a number from 1 to 64)");
+        assert_eq!(error("pong.ch8 --ghosting 1"), "invalid decay '1' (expected a value in [0, 1))");
+        assert_eq!(error("report roms --jobs 0"), "invalid value '0' for --jobs (expected a number from 1 to 1024)");
+        assert_eq!(error("pong.ch8 --fg red"), "invalid colour 'red' for --fg (expected RRGGBB)");
+    }
+}
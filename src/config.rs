@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cli::RunOptions;
+use chip8::emulator::quirks::{Platform, Quirks};
+use crate::frontend::keymap::Keymap;
+use crate::frontend::palette::Palette;
+
+// The configuration file lives in the user's config directory, for example
+// ~/.config/chip8/config.toml on Linux:
+//
+//   [defaults]
+//   palette = "green"
+//   keymap = "qwerty"
+//
+//   # Settings for a single ROM, keyed by the SHA-1 of its contents (see `chip8 info`)
+//   [roms.0123456789abcdef0123456789abcdef01234567]
+//   name = "Some game"
+//   platform = "schip"
+//   quirks = "vip"
+//   speed = 20
+//
+// Options given on the command line win over the profile of the ROM, which wins over
+// the built-in database (see romdb.rs), which wins over the defaults.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Profile,
+    #[serde(default)]
+    pub roms: HashMap<String, Profile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    // Only there to tell the profiles apart, it isn't used
+    pub name: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<String>,
+    pub speed: Option<u32>,
+    pub palette: Option<String>,
+    pub keymap: Option<String>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+    }
+
+    // A missing file is an empty configuration, a broken one is an error
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) if !path.exists() => return Ok(Config::default()),
+            Err(err) => return Err(format!("cannot read config '{}': {}", path.display(), err)),
+        };
+
+        toml::from_str(&text)
+            .map_err(|err| format!("invalid config '{}': {}", path.display(), err))
+    }
+
+    // Fills in the options that weren't given on the command line, first from the
+    // profile of the ROM with this hash, then from what the built-in database knows
+    // (or the detection guessed) about the ROM and last from the defaults
+    pub fn apply(&self, hash: &str, known: &Profile, options: &mut RunOptions) -> Result<(), String> {
+        if let Some(profile) = self.roms.get(&hash.to_ascii_lowercase()) {
+            profile.apply(options)
+                .map_err(|err| format!("in the config of ROM {}: {}", hash, err))?;
+        }
+
+        known.apply(options)
+            .map_err(|err| format!("in the built-in database entry of ROM {}: {}", hash, err))?;
+
+        self.defaults.apply(options)
+            .map_err(|err| format!("in the default config: {}", err))
+    }
+}
+
+impl Profile {
+    // Sets the options that are still None
+    pub fn apply(&self, options: &mut RunOptions) -> Result<(), String> {
+        // The platform and the quirk preset both decide the quirks, so they are only taken
+        // over together. Otherwise a platform given on the command line could end up with
+        // the quirks of the profile.
+        if options.platform.is_none() && options.quirks.is_none() {
+            if let Some(name) = &self.platform {
+                options.platform = Some(Platform::from_name(name)
+                    .ok_or_else(|| format!("unknown platform '{}'", name))?);
+            }
+
+            if let Some(name) = &self.quirks {
+                options.quirks = Some(Quirks::preset(name)
+                    .ok_or_else(|| format!("unknown quirk preset '{}'", name))?);
+            }
+        }
+
+        if options.speed.is_none() {
+            options.speed = self.speed;
+        }
+
+        if options.palette.is_none() {
+            if let Some(name) = &self.palette {
+                options.palette = Some(Palette::find(name)
+                    .ok_or_else(|| format!("unknown palette '{}'", name))?);
+            }
+        }
+
+        if options.keymap.is_none() {
+            if let Some(map) = &self.keymap {
+                options.keymap = Some(Keymap::parse(map)
+                    .map_err(|err| format!("invalid key map: {}", err))?);
+            }
+        }
+
+        Ok(())
+    }
+}
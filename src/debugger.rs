@@ -0,0 +1,336 @@
+use std::io::{self, BufRead, Write};
+
+use chip8::disasm;
+use chip8::emulator::Emulator;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]         Execute n instructions (default 1)
+  f, frame [n]        Run n frames (default 1)
+  c, continue         Run until a breakpoint is hit
+  b, break <addr>     Set a breakpoint
+  d, delete <addr>    Remove a breakpoint
+  l, list             List the breakpoints
+  r, regs             Show the registers
+  m, mem <addr> [n]   Dump n bytes of memory (default 64)
+  x, screen           Show the screen
+  k, key <key> [up]   Press (or release) a key of the keypad
+  q, quit             Exit
+An empty line repeats the last command.";
+
+// A line typed at the prompt
+#[derive(Debug, PartialEq)]
+enum DebugCommand {
+    Step(u64),
+    Frame(u64),
+    Continue,
+    Break(u16),
+    Delete(u16),
+    List,
+    Registers,
+    Memory { addr: u16, len: u64 },
+    Screen,
+    Key { key: u8, pressed: bool },
+    Quit,
+    Help,
+}
+
+// Stops `continue` from spinning forever on a ROM that never reaches a breakpoint
+const CONTINUE_LIMIT: u64 = 60 * 60 * 10;
+
+// A terminal debugger that runs the emulator without a window
+pub struct Debugger {
+    pub breakpoints: Vec<u16>,
+    // Instructions executed in the current frame, the timers tick when a frame is full
+    cycle: u32,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            cycle: 0,
+        }
+    }
+
+    pub fn run(&mut self, emulator: &mut Emulator) {
+        let stdin = io::stdin();
+        let mut last_command = String::from("step");
+
+        println!("{}", HELP);
+        self.print_next(emulator);
+
+        loop {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+
+            let line = line.trim();
+            let command = if line.is_empty() { last_command.clone() } else { line.to_string() };
+            last_command = command.clone();
+
+            match parse_command(&command) {
+                Ok(DebugCommand::Quit) => break,
+                Ok(command) => {
+                    self.execute(emulator, command);
+                    self.print_next(emulator);
+                },
+                Err(err) => println!("{}", err),
+            }
+        }
+    }
+
+    fn execute(&mut self, emulator: &mut Emulator, command: DebugCommand) {
+        match command {
+            DebugCommand::Step(count) => {
+                for _ in 0..count {
+                    if !self.step(emulator) {
+                        break;
+                    }
+                }
+            },
+            DebugCommand::Frame(count) => {
+                for _ in 0..count {
+                    if !self.run_frame(emulator) {
+                        break;
+                    }
+                }
+            },
+            DebugCommand::Continue => {
+                // Step over a breakpoint we're stopped at
+                self.step(emulator);
+
+                let mut frames = 0;
+                while frames < CONTINUE_LIMIT && self.run_frame(emulator) {
+                    frames += 1;
+                }
+
+                if frames == CONTINUE_LIMIT {
+                    println!("No breakpoint hit after {} frames", frames);
+                }
+            },
+            DebugCommand::Break(addr) => {
+                if !self.breakpoints.contains(&addr) {
+                    self.breakpoints.push(addr);
+                }
+            },
+            DebugCommand::Delete(addr) => self.breakpoints.retain(|breakpoint| *breakpoint != addr),
+            DebugCommand::List => {
+                for addr in self.breakpoints.iter() {
+                    println!("  {:#05x}", addr);
+                }
+            },
+            DebugCommand::Registers => print!("{}", registers(emulator)),
+            DebugCommand::Memory { addr, len } => print!("{}", memory(emulator, addr as usize, len as usize)),
+            DebugCommand::Screen => print!("{}", emulator.framebuffer().to_ascii()),
+            DebugCommand::Key { key, pressed } => emulator.set_key(key, pressed),
+            // Leaving the prompt is up to `run`
+            DebugCommand::Quit => {},
+            DebugCommand::Help => println!("{}", HELP),
+        }
+    }
+
+    // Executes one instruction, returns false if a breakpoint was reached
+    fn step(&mut self, emulator: &mut Emulator) -> bool {
+        emulator.step();
+
+        self.cycle += 1;
+        if self.cycle >= emulator.speed() {
+            self.cycle = 0;
+            emulator.tick_timers();
+        }
+
+        !self.breakpoints.contains(&emulator.cpu().PC)
+    }
+
+    // Runs until the end of the current frame, returns false if a breakpoint was reached
+    fn run_frame(&mut self, emulator: &mut Emulator) -> bool {
+        loop {
+            let last_in_frame = self.cycle + 1 >= emulator.speed();
+
+            if !self.step(emulator) {
+                println!("Breakpoint at {:#05x}", emulator.cpu().PC);
+                return false;
+            }
+
+            if last_in_frame {
+                return true;
+            }
+        }
+    }
+
+    fn print_next(&self, emulator: &Emulator) {
+        let pc = emulator.cpu().PC as usize;
+        let opcode = emulator.opcode_at(pc);
+        let assembly = disasm::mnemonic(opcode).unwrap_or_else(|| "???".to_string());
+
+        println!("{:#05x}: {:04X}  {}", pc, opcode, assembly);
+    }
+}
+
+fn parse_command(line: &str) -> Result<DebugCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let command = match words.first().copied().unwrap_or("") {
+        "s" | "step" => DebugCommand::Step(parse_count(words.get(1))?),
+        "f" | "frame" => DebugCommand::Frame(parse_count(words.get(1))?),
+        "c" | "continue" => DebugCommand::Continue,
+        "b" | "break" => DebugCommand::Break(parse_addr(words.get(1))?),
+        "d" | "delete" => DebugCommand::Delete(parse_addr(words.get(1))?),
+        "l" | "list" => DebugCommand::List,
+        "r" | "regs" => DebugCommand::Registers,
+        "m" | "mem" => {
+            let addr = parse_addr(words.get(1))?;
+            let len = match words.get(2) {
+                Some(_) => parse_count(words.get(2))?,
+                None => 64,
+            };
+            DebugCommand::Memory { addr, len }
+        },
+        "x" | "screen" => DebugCommand::Screen,
+        "k" | "key" => {
+            let key = parse_addr(words.get(1))?;
+            if key > 0xF {
+                return Err("keys go from 0 to F".to_string());
+            }
+            DebugCommand::Key { key: key as u8, pressed: words.get(2) != Some(&"up") }
+        },
+        "q" | "quit" => DebugCommand::Quit,
+        "h" | "help" => DebugCommand::Help,
+        other => return Err(format!("unknown command '{}', try 'help'", other)),
+    };
+
+    Ok(command)
+}
+
+fn registers(emulator: &Emulator) -> String {
+    let cpu = emulator.cpu();
+    let mut text = String::new();
+
+    for (idx, value) in cpu.registers.iter().enumerate() {
+        text += &format!("V{:X}={:02X} ", idx, value);
+        if idx % 8 == 7 {
+            text.push('\n');
+        }
+    }
+
+    text += &format!("I={:03X} PC={:03X} SP={:X} DT={:02X} ST={:02X}\n", cpu.VI, cpu.PC, cpu.SP, cpu.timer.DT, cpu.timer.ST);
+
+    let stack: Vec<String> = cpu.stack[1..=(cpu.SP as usize).min(15)].iter()
+        .map(|addr| format!("{:03X}", addr))
+        .collect();
+    text += &format!("Stack: [{}]\n", stack.join(", "));
+
+    text
+}
+
+fn memory(emulator: &Emulator, start: usize, len: usize) -> String {
+    let end = (start + len).min(emulator.memory().len());
+    let mut text = String::new();
+
+    for row_start in (start..end).step_by(16) {
+        let row: Vec<String> = (row_start..(row_start + 16).min(end))
+            .map(|addr| format!("{:02X}", emulator.read(addr)))
+            .collect();
+
+        text += &format!("{:03X}: {}\n", row_start, row.join(" "));
+    }
+
+    text
+}
+
+fn parse_count(word: Option<&&str>) -> Result<u64, String> {
+    match word {
+        None => Ok(1),
+        Some(word) => word.parse::<u64>()
+            .map_err(|_| format!("'{}' is not a count", word)),
+    }
+}
+
+// Addresses are hexadecimal, with or without a 0x prefix
+fn parse_addr(word: Option<&&str>) -> Result<u16, String> {
+    let word = word.ok_or("missing address")?;
+    let hex = word.strip_prefix("0x").unwrap_or(word);
+
+    u16::from_str_radix(hex, 16)
+        .map_err(|_| format!("'{}' is not a hexadecimal address", word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_command("s"), Ok(DebugCommand::Step(1)));
+        assert_eq!(parse_command("step 25"), Ok(DebugCommand::Step(25)));
+        assert_eq!(parse_command("f 3"), Ok(DebugCommand::Frame(3)));
+        assert_eq!(parse_command("c"), Ok(DebugCommand::Continue));
+        assert_eq!(parse_command("  regs  "), Ok(DebugCommand::Registers));
+        assert_eq!(parse_command("m 0x300"), Ok(DebugCommand::Memory { addr: 0x300, len: 64 }));
+        assert_eq!(parse_command("mem 300 16"), Ok(DebugCommand::Memory { addr: 0x300, len: 16 }));
+        assert_eq!(parse_command("k a"), Ok(DebugCommand::Key { key: 0xA, pressed: true }));
+        assert_eq!(parse_command("key 5 up"), Ok(DebugCommand::Key { key: 0x5, pressed: false }));
+        assert_eq!(parse_command("q"), Ok(DebugCommand::Quit));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert_eq!(parse_command("jump"), Err("unknown command 'jump', try 'help'".to_string()));
+        assert_eq!(parse_command("s many"), Err("'many' is not a count".to_string()));
+        assert_eq!(parse_command("b"), Err("missing address".to_string()));
+        assert_eq!(parse_command("b 0xZZ"), Err("'0xZZ' is not a hexadecimal address".to_string()));
+        assert_eq!(parse_command("k 10"), Err("keys go from 0 to F".to_string()));
+    }
+
+    #[test]
+    fn stops_at_breakpoints() {
+        // LD V0, 1; LD V1, 2; LD V2, 3; JP #200
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x00]).unwrap();
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut emulator, parse_command("b 204").unwrap());
+        debugger.execute(&mut emulator, parse_command("b 0x204").unwrap());
+        assert_eq!(debugger.breakpoints, [0x204]);
+
+        debugger.execute(&mut emulator, DebugCommand::Step(10));
+        assert_eq!(emulator.cpu().PC, 0x204);
+
+        debugger.execute(&mut emulator, DebugCommand::Continue);
+        assert_eq!(emulator.cpu().PC, 0x204);
+        assert_eq!(emulator.instructions_executed(), 6);
+
+        debugger.execute(&mut emulator, DebugCommand::Delete(0x204));
+        debugger.execute(&mut emulator, DebugCommand::Step(2));
+        assert_eq!(emulator.cpu().PC, 0x200);
+    }
+
+    #[test]
+    fn dumps_registers_and_memory() {
+        // LD V0, #AB; LD VF, 1; LD I, #123; CALL #20A; ...; LD DT, V0
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&[0x60, 0xAB, 0x6F, 0x01, 0xA1, 0x23, 0x22, 0x0A, 0x00, 0x00, 0xF0, 0x15]).unwrap();
+        for _ in 0..5 {
+            emulator.step();
+        }
+
+        assert_eq!(registers(&emulator), "\
+V0=AB V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 
+V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=01 
+I=123 PC=20C SP=1 DT=AB ST=00
+Stack: [208]
+");
+        assert_eq!(memory(&emulator, 0x200, 20), "\
+200: 60 AB 6F 01 A1 23 22 0A 00 00 F0 15 00 00 00 00
+210: 00 00 00 00
+");
+        // Dumps stop at the end of memory
+        assert_eq!(memory(&emulator, 0xFFE, 64), "FFE: 00 00\n");
+    }
+}
@@ -0,0 +1,437 @@
+//! Turns a ROM into structured pseudocode: a function per subroutine, loops where code
+//! jumps back, if/else where a skip is followed by a jump over a block. What doesn't fit
+//! those shapes stays a goto.
+
+use std::collections::BTreeSet;
+
+use crate::disasm::{analyze, Analysis, LabelKind};
+
+const INDENT: &str = "    ";
+
+enum Stmt {
+    Op(String),
+    // A label gotos jump to
+    Label(usize),
+    If { cond: Cond, then: Vec<Stmt>, otherwise: Vec<Stmt> },
+    Loop(Vec<Stmt>),
+    Break,
+    Continue,
+    Goto(usize),
+}
+
+// The condition under which a skip instruction skips
+#[derive(Clone, Copy)]
+struct Cond {
+    opcode: u16,
+    negated: bool,
+}
+
+// The loop the code being structured is in
+#[derive(Clone, Copy, Default)]
+struct Context {
+    head: Option<usize>,
+    // The jump back to the head
+    back: Option<usize>,
+    exit: Option<usize>,
+}
+
+struct Decompiler<'a> {
+    program: &'a [u8],
+    analysis: Analysis,
+    // The entries of all functions, jumps to them are tail calls
+    entries: BTreeSet<usize>,
+    // The instructions of the function being decompiled
+    body: BTreeSet<usize>,
+    // Where gotos go, they get labels
+    targets: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+}
+
+/// Decompiles a program loaded at `start`, execution begins at `start`
+pub fn decompile(program: &[u8], start: usize) -> String {
+    let analysis = analyze(program, start);
+
+    let mut entries: BTreeSet<usize> = analysis.labels.iter()
+        .filter(|(addr, kind)| **kind == LabelKind::Subroutine && analysis.is_code(**addr))
+        .map(|(addr, _)| *addr)
+        .collect();
+    entries.insert(start);
+
+    let mut decompiler = Decompiler {
+        program,
+        analysis,
+        entries: entries.clone(),
+        body: BTreeSet::new(),
+        targets: BTreeSet::new(),
+        gotos: BTreeSet::new(),
+    };
+
+    let mut text = String::new();
+    for entry in entries {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&decompiler.function(entry));
+    }
+
+    text
+}
+
+impl<'a> Decompiler<'a> {
+    fn opcode_at(&self, addr: usize) -> Option<u16> {
+        let offset = addr.checked_sub(self.analysis.start)?;
+        let bytes = self.program.get(offset..offset + 2)?;
+
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn name(&self, addr: usize) -> String {
+        if addr == self.analysis.start {
+            return "main".to_string();
+        }
+
+        match self.analysis.labels.get(&addr) {
+            Some(kind) => kind.name(addr),
+            None => format!("label_{:03X}", addr),
+        }
+    }
+
+    fn function(&mut self, entry: usize) -> String {
+        self.body = self.reachable(entry);
+        let end = self.body.iter().next_back().map_or(entry, |last| last + 2);
+
+        // Structured once to find out where the gotos go, and again to put the labels there
+        self.targets.clear();
+        self.gotos.clear();
+        self.block(entry, end, Context::default());
+        self.targets = std::mem::take(&mut self.gotos);
+        let stmts = self.block(entry, end, Context::default());
+
+        let mut text = format!("fn {}() {{\n", self.name(entry));
+        self.render(&stmts, 1, &mut text);
+        text.push_str("}\n");
+
+        text
+    }
+
+    // The instructions a function runs without following calls or tail calls
+    fn reachable(&self, entry: usize) -> BTreeSet<usize> {
+        let mut body = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(addr) = pending.pop() {
+            if !self.analysis.is_code(addr) || !body.insert(addr) {
+                continue;
+            }
+
+            let opcode = self.opcode_at(addr).unwrap();
+            let target = (opcode & 0x0FFF) as usize;
+
+            match opcode >> 12 {
+                0x0 if opcode == 0x00EE || opcode == 0x00FD => {},
+                0x1 if target != entry && self.entries.contains(&target) => {},
+                0x1 => pending.push(target),
+                0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                    pending.push(addr + 2);
+                    pending.push(addr + 4);
+                },
+                0xB => {
+                    let mut row = target;
+                    while self.opcode_at(row).is_some_and(|opcode| opcode >> 12 == 0x1) {
+                        pending.push(row);
+                        row += 2;
+                    }
+                },
+                _ => pending.push(addr + 2),
+            }
+        }
+
+        body
+    }
+
+    fn next_in_body(&self, addr: usize) -> Option<usize> {
+        self.body.range(addr..).next().copied()
+    }
+
+    fn jump_target(&self, addr: usize) -> Option<usize> {
+        match self.opcode_at(addr) {
+            Some(opcode) if opcode >> 12 == 0x1 && self.body.contains(&addr) => Some((opcode & 0x0FFF) as usize),
+            _ => None,
+        }
+    }
+
+    // The statements for the instructions in [addr, end)
+    fn block(&mut self, mut addr: usize, end: usize, context: Context) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+
+        while addr < end {
+            addr = match self.next_in_body(addr) {
+                Some(next) if next < end => next,
+                _ => break,
+            };
+
+            if self.targets.contains(&addr) && context.head != Some(addr) {
+                stmts.push(Stmt::Label(addr));
+            }
+
+            // The last jump back to here closes a loop, the head of the loop being
+            // structured is only a loop once
+            if context.head != Some(addr) {
+                let back = self.body.range(addr + 2..end).rev()
+                    .find(|jump| self.jump_target(**jump) == Some(addr))
+                    .copied();
+
+                if let Some(back) = back {
+                    let inner = Context { head: Some(addr), back: Some(back), exit: self.next_in_body(back + 2) };
+                    stmts.push(Stmt::Loop(self.block(addr, back, inner)));
+                    addr = back + 2;
+                    continue;
+                }
+            }
+
+            let opcode = self.opcode_at(addr).unwrap();
+
+            if is_skip(opcode) {
+                let cond = Cond { opcode, negated: false };
+                let after = addr + 4;
+
+                // skip; jump past the block; block is an if, with an else when the block
+                // ends in a jump past another one
+                if let Some(target) = self.jump_target(addr + 2).filter(|target| *target > after && *target <= end) {
+                    let (then_end, join) = match self.jump_target(target - 2) {
+                        Some(join) if target - 2 >= after && join > target && join <= end => (target - 2, join),
+                        _ => (target, target),
+                    };
+
+                    let then = self.block(after, then_end, context);
+                    let otherwise = self.block(target, join, context);
+                    stmts.push(Stmt::If { cond, then, otherwise });
+                    addr = join;
+                    continue;
+                }
+
+                // Another skip can't be put inside an if, it would skip out of it. Neither
+                // can the instruction that ends the block, which runs when the block is
+                // left anyway.
+                if addr + 2 >= end || self.opcode_at(addr + 2).is_some_and(is_skip) {
+                    let jump = self.jump(after, context);
+                    stmts.push(Stmt::If { cond, then: vec![jump], otherwise: Vec::new() });
+                    addr += 2;
+                    continue;
+                }
+
+                let then = self.instruction(addr + 2, context, None);
+                stmts.push(Stmt::If { cond: cond.negate(), then, otherwise: Vec::new() });
+                addr = after;
+                continue;
+            }
+
+            // Jumps to where the code goes on anyway disappear
+            let next = self.next_in_body(addr + 2).filter(|next| *next < end);
+            stmts.extend(self.instruction(addr, context, next));
+            addr += 2;
+        }
+
+        stmts
+    }
+
+    // A single instruction, `next` is where execution continues after it in the text
+    fn instruction(&mut self, addr: usize, context: Context, next: Option<usize>) -> Vec<Stmt> {
+        let opcode = match self.opcode_at(addr) {
+            Some(opcode) if self.body.contains(&addr) => opcode,
+            _ => return Vec::new(),
+        };
+        let target = (opcode & 0x0FFF) as usize;
+
+        let stmt = match opcode >> 12 {
+            0x1 if target == addr => Stmt::Op("halt()".to_string()),
+            0x1 if Some(target) == next => return Vec::new(),
+            0x1 if self.entries.contains(&target) && context.head != Some(target) => {
+                Stmt::Op(format!("return {}()", self.name(target)))
+            },
+            0x1 => self.jump(target, context),
+            0xB => {
+                self.gotos.insert(target);
+                Stmt::Op(self.operation(opcode))
+            },
+            _ => Stmt::Op(self.operation(opcode)),
+        };
+
+        vec![stmt]
+    }
+
+    // Going to `target`, which leaves or restarts the loop the code is in
+    fn jump(&mut self, target: usize, context: Context) -> Stmt {
+        if context.head == Some(target) || context.back == Some(target) {
+            Stmt::Continue
+        }
+        else if context.exit == Some(target) {
+            Stmt::Break
+        }
+        else {
+            self.gotos.insert(target);
+            Stmt::Goto(target)
+        }
+    }
+
+    fn operation(&self, opcode: u16) -> String {
+        let x = (opcode & 0x0F00) >> 8;
+        let y = (opcode & 0x00F0) >> 4;
+        let byte = opcode & 0x00FF;
+        let addr = (opcode & 0x0FFF) as usize;
+
+        match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => "clear_screen()".to_string(),
+                0x00EE => "return".to_string(),
+                0x00FD => "exit()".to_string(),
+                _ => format!("native({:#05x})", addr),
+            },
+            0x2 => format!("{}()", self.name(addr)),
+            0x6 => format!("v{:X} = {:#04x}", x, byte),
+            0x7 => format!("v{:X} += {:#04x}", x, byte),
+            0x8 => match opcode & 0x000F {
+                0x0 => format!("v{:X} = v{:X}", x, y),
+                0x1 => format!("v{:X} |= v{:X}", x, y),
+                0x2 => format!("v{:X} &= v{:X}", x, y),
+                0x3 => format!("v{:X} ^= v{:X}", x, y),
+                0x4 => format!("v{:X} += v{:X}  // vF = carry", x, y),
+                0x5 => format!("v{:X} -= v{:X}  // vF = no borrow", x, y),
+                0x6 => format!("v{:X} = v{:X} >> 1  // vF = bit shifted out", x, y),
+                0x7 => format!("v{:X} = v{:X} - v{:X}  // vF = no borrow", x, y, x),
+                _ => format!("v{:X} = v{:X} << 1  // vF = bit shifted out", x, y),
+            },
+            0xA => match self.analysis.labels.get(&addr) {
+                Some(kind) => format!("i = {}", kind.name(addr)),
+                None => format!("i = {:#05x}", addr),
+            },
+            0xB => format!("goto {}[v0]", self.name(addr)),
+            0xC => format!("v{:X} = random() & {:#04x}", x, byte),
+            0xD => format!("draw(v{:X}, v{:X}, {})  // vF = collision", x, y, opcode & 0x000F),
+            0xF => match byte {
+                0x07 => format!("v{:X} = delay", x),
+                0x0A => format!("v{:X} = wait_key()", x),
+                0x15 => format!("delay = v{:X}", x),
+                0x18 => format!("sound = v{:X}", x),
+                0x1E => format!("i += v{:X}", x),
+                0x29 => format!("i = font(v{:X})", x),
+                0x33 => format!("memory[i..i + 3] = bcd(v{:X})", x),
+                0x55 => format!("memory[i..] = v0..=v{:X}", x),
+                0x65 => format!("v0..=v{:X} = memory[i..]", x),
+                _ => format!("unknown({:#06x})", opcode),
+            },
+            _ => format!("unknown({:#06x})", opcode),
+        }
+    }
+
+    fn render(&self, stmts: &[Stmt], depth: usize, text: &mut String) {
+        let indent = INDENT.repeat(depth);
+
+        for stmt in stmts {
+            match stmt {
+                Stmt::Op(op) => text.push_str(&format!("{}{}\n", indent, op)),
+                Stmt::Label(addr) => text.push_str(&format!("{}{}:\n", INDENT.repeat(depth - 1), self.name(*addr))),
+                Stmt::Break => text.push_str(&format!("{}break\n", indent)),
+                Stmt::Continue => text.push_str(&format!("{}continue\n", indent)),
+                Stmt::Goto(addr) => text.push_str(&format!("{}goto {}\n", indent, self.name(*addr))),
+                Stmt::If { cond, then, otherwise } => {
+                    text.push_str(&format!("{}if {} {{\n", indent, cond.text()));
+                    self.render(then, depth + 1, text);
+                    if !otherwise.is_empty() {
+                        text.push_str(&format!("{}}}\n{}else {{\n", indent, indent));
+                        self.render(otherwise, depth + 1, text);
+                    }
+                    text.push_str(&format!("{}}}\n", indent));
+                },
+                Stmt::Loop(body) => {
+                    // A loop that starts by breaking out is a while loop
+                    match body.first() {
+                        Some(Stmt::If { cond, then, otherwise }) if matches!(then.as_slice(), [Stmt::Break]) && otherwise.is_empty() => {
+                            text.push_str(&format!("{}while {} {{\n", indent, cond.negate().text()));
+                            self.render(&body[1..], depth + 1, text);
+                        },
+                        _ => {
+                            text.push_str(&format!("{}loop {{\n", indent));
+                            self.render(body, depth + 1, text);
+                        },
+                    }
+                    text.push_str(&format!("{}}}\n", indent));
+                },
+            }
+        }
+    }
+}
+
+impl Cond {
+    fn negate(self) -> Cond {
+        Cond { negated: !self.negated, ..self }
+    }
+
+    fn text(&self) -> String {
+        let x = (self.opcode & 0x0F00) >> 8;
+        let y = (self.opcode & 0x00F0) >> 4;
+        let byte = self.opcode & 0x00FF;
+        let (equal, unequal) = if self.negated { ("!=", "==") } else { ("==", "!=") };
+        let not = if self.negated { "!" } else { "" };
+
+        match self.opcode >> 12 {
+            0x3 => format!("v{:X} {} {:#04x}", x, equal, byte),
+            0x4 => format!("v{:X} {} {:#04x}", x, unequal, byte),
+            0x5 => format!("v{:X} {} v{:X}", x, equal, y),
+            0x9 => format!("v{:X} {} v{:X}", x, unequal, y),
+            _ if byte == 0x9E => format!("{}key_pressed(v{:X})", not, x),
+            _ => {
+                let not = if self.negated { "" } else { "!" };
+                format!("{}key_pressed(v{:X})", not, x)
+            },
+        }
+    }
+}
+
+fn is_skip(opcode: u16) -> bool {
+    matches!(opcode >> 12, 0x3 | 0x4 | 0x5 | 0x9) || (opcode >> 12 == 0xE && matches!(opcode & 0x00FF, 0x9E | 0xA1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_over_jumps_become_if_else() {
+        let program = [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03, 0x12, 0x0A];
+
+        assert_eq!(decompile(&program, 0x200), "\
+fn main() {
+    if v0 == 0x01 {
+        v1 = 0x02
+    }
+    else {
+        v1 = 0x03
+    }
+    halt()
+}
+");
+    }
+
+    #[test]
+    fn jumps_back_become_loops_and_calls_functions() {
+        let program = [0x70, 0x01, 0x30, 0x10, 0x12, 0x00, 0x22, 0x0A, 0x12, 0x08, 0x00, 0xEE];
+
+        assert_eq!(decompile(&program, 0x200), "\
+fn main() {
+    loop {
+        v0 += 0x01
+        if v0 == 0x10 {
+            break
+        }
+    }
+    sub_20A()
+    halt()
+}
+
+fn sub_20A() {
+    return
+}
+");
+    }
+}
@@ -0,0 +1,323 @@
+//! Guesses the platform and the quirks a ROM was written for.
+
+use crate::emulator::quirks::Platform;
+#[cfg(feature = "std")]
+use crate::disasm::{analyze_extended, Analysis};
+#[cfg(feature = "std")]
+use crate::emulator::PROGRAM_START;
+
+/// Guesses the platform a ROM was written for from the instructions it contains. Only
+/// the code that can run counts, so that sprites and other data that happen to look
+/// like instructions give nothing away.
+#[cfg(feature = "std")]
+pub struct Detection {
+    /// Chip8 unless instructions of a later platform were found
+    pub platform: Platform,
+    /// The opcodes that gave the platform away, as they appear in the ROM
+    pub evidence: Vec<u16>,
+    /// The quirk preset the ROM probably expects
+    pub quirks: &'static str,
+    /// The opcodes that gave the quirks away
+    pub quirk_evidence: Vec<u16>,
+}
+
+// How the code uses the instructions the interpreters disagree on
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct QuirkHints {
+    // 8XY6/8XYE with X != Y only make sense when VY is shifted
+    shift_uses_vy: Vec<u16>,
+    // FX55/FX65 that run again without I being set in between rely on I moving along
+    load_store_increments_i: Vec<u16>,
+    // BXNN right after VX (and not V0) was set jumps by VX
+    jump_uses_vx: Vec<u16>,
+}
+
+/// The platform that introduced an opcode, None for the ones chip8 already had (or that
+/// no platform knows)
+pub fn platform_of(opcode: u16) -> Option<Platform> {
+    let x = (opcode & 0x0F00) >> 8;
+    let nibble = opcode & 0x000F;
+
+    match opcode & 0xF000 {
+        // Scrolling (00CN, 00FB, 00FC), exit (00FD) and the resolution switches
+        0x0000 if opcode & 0xFFF0 == 0x00C0 && nibble != 0 => Some(Platform::SuperChip),
+        0x0000 if (0x00FB..=0x00FF).contains(&opcode) => Some(Platform::SuperChip),
+        // Scrolling up
+        0x0000 if opcode & 0xFFF0 == 0x00D0 && nibble != 0 => Some(Platform::XoChip),
+        // Saving/loading a range of registers
+        0x5000 if nibble == 2 || nibble == 3 => Some(Platform::XoChip),
+        // 16x16 sprites
+        0xD000 if nibble == 0 => Some(Platform::SuperChip),
+        0xF000 => match opcode & 0x00FF {
+            // Big font and the RPL flags
+            0x30 | 0x75 | 0x85 => Some(Platform::SuperChip),
+            // Plane selection and the audio pitch
+            0x01 | 0x3A => Some(Platform::XoChip),
+            // Long I (F000 NNNN) and the audio pattern
+            0x00 | 0x02 if x == 0 => Some(Platform::XoChip),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Looks at the instructions of a ROM for its platform and quirks
+#[cfg(feature = "std")]
+pub fn detect(program: &[u8]) -> Detection {
+    let mut detection = Detection {
+        platform: Platform::Chip8,
+        evidence: Vec::new(),
+        quirks: Platform::Chip8.name(),
+        quirk_evidence: Vec::new(),
+    };
+
+    let analysis = analyze_extended(program, PROGRAM_START);
+    let mut big_sprite = None;
+
+    for opcode in code(program, &analysis).map(|(_, opcode)| opcode) {
+        // DXY0 is also a plain chip8 instruction that draws nothing on the VIP, so it
+        // only counts along with other hints
+        if opcode & 0xF00F == 0xD000 {
+            big_sprite = big_sprite.or(Some(opcode));
+            continue;
+        }
+
+        let platform = match platform_of(opcode) {
+            Some(platform) => platform,
+            None => continue,
+        };
+
+        if !detection.evidence.contains(&opcode) {
+            detection.evidence.push(opcode);
+        }
+
+        // XO-CHIP is a superset of SUPER-CHIP
+        if platform == Platform::XoChip || detection.platform == Platform::Chip8 {
+            detection.platform = platform;
+        }
+    }
+
+    if let Some(opcode) = big_sprite {
+        if detection.platform != Platform::Chip8 {
+            detection.evidence.push(opcode);
+        }
+    }
+
+    // The platforms come with the quirks their games usually expect. SUPER-CHIP is the
+    // only one that jumps by VX, and plain chip8 games that shift VY or let FX55/FX65
+    // move I were written for the VIP.
+    let hints = quirk_hints(program, &analysis);
+    let (quirks, evidence) = match detection.platform {
+        Platform::XoChip => (Platform::XoChip.name(), Vec::new()),
+        Platform::SuperChip => (Platform::SuperChip.name(), hints.jump_uses_vx),
+        Platform::Chip8 if !hints.jump_uses_vx.is_empty() => ("schip", hints.jump_uses_vx),
+        Platform::Chip8 => {
+            let evidence: Vec<u16> = hints.shift_uses_vy.into_iter()
+                .chain(hints.load_store_increments_i)
+                .collect();
+
+            (if evidence.is_empty() { Platform::Chip8.name() } else { "vip" }, evidence)
+        },
+    };
+
+    detection.quirks = quirks;
+    for opcode in evidence {
+        if !detection.quirk_evidence.contains(&opcode) {
+            detection.quirk_evidence.push(opcode);
+        }
+    }
+
+    detection
+}
+
+// The instructions that can run and their addresses
+#[cfg(feature = "std")]
+fn code<'a>(program: &'a [u8], analysis: &'a Analysis) -> impl Iterator<Item = (usize, u16)> + 'a {
+    analysis.code.iter().map(move |addr| {
+        let offset = addr - analysis.start;
+        (*addr, (program[offset] as u16) << 8 | program[offset + 1] as u16)
+    })
+}
+
+// Looks at the code that can run, data can't give anything away here
+#[cfg(feature = "std")]
+fn quirk_hints(program: &[u8], analysis: &Analysis) -> QuirkHints {
+    let mut hints = QuirkHints::default();
+
+    let code: Vec<(usize, u16)> = code(program, analysis).collect();
+
+    let sets_i = |opcode: u16| opcode >> 12 == 0xA || matches!(opcode & 0xF0FF, 0xF01E | 0xF029);
+    let loads_or_stores = |opcode: u16| matches!(opcode & 0xF0FF, 0xF055 | 0xF065);
+    let branches = |opcode: u16| matches!(opcode >> 12, 0x1 | 0x2 | 0xB) || opcode == 0x00EE;
+
+    // The load or store since I was last set, in a run of instructions without branches
+    let mut pending: Option<u16> = None;
+
+    for (idx, (addr, opcode)) in code.iter().enumerate() {
+        let x = (opcode & 0x0F00) >> 8;
+        let y = (opcode & 0x00F0) >> 4;
+
+        if matches!(opcode & 0xF00F, 0x8006 | 0x800E) && x != y {
+            hints.shift_uses_vy.push(*opcode);
+        }
+
+        if analysis.labels.contains_key(addr) || sets_i(*opcode) {
+            pending = None;
+        }
+        if loads_or_stores(*opcode) {
+            if pending.is_some() {
+                hints.load_store_increments_i.push(*opcode);
+            }
+            pending = Some(*opcode);
+        }
+        if branches(*opcode) {
+            pending = None;
+        }
+
+        // A loop that loads or stores without ever setting I
+        if opcode >> 12 == 0x1 && (opcode & 0x0FFF) as usize <= *addr {
+            let body = code.iter()
+                .filter(|(body_addr, _)| (opcode & 0x0FFF) as usize <= *body_addr && body_addr < addr)
+                .map(|(_, opcode)| *opcode);
+            let (mut accesses, mut set) = (None, false);
+            for opcode in body {
+                set |= sets_i(opcode);
+                accesses = accesses.or(Some(opcode).filter(|opcode| loads_or_stores(*opcode)));
+            }
+            if let (Some(access), false) = (accesses, set) {
+                hints.load_store_increments_i.push(access);
+            }
+        }
+
+        // The instruction right before BXNN shows which register holds the offset
+        if opcode >> 12 == 0xB && x != 0 && idx > 0 {
+            let (previous_addr, previous) = code[idx - 1];
+            if previous_addr + 2 == *addr && writes_register(previous) == Some(x) {
+                hints.jump_uses_vx.push(*opcode);
+            }
+        }
+    }
+
+    hints
+}
+
+// The register an instruction writes a value into, VF as a flag doesn't count
+#[cfg(feature = "std")]
+fn writes_register(opcode: u16) -> Option<u16> {
+    let x = (opcode & 0x0F00) >> 8;
+
+    match opcode >> 12 {
+        0x6 | 0x7 | 0x8 | 0xC => Some(x),
+        0xF if matches!(opcode & 0x00FF, 0x07 | 0x0A) => Some(x),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "std")]
+impl Detection {
+    /// What was detected and why, in a sentence or two for the command line
+    pub fn describe(&self) -> String {
+        let platform = if self.evidence.is_empty() {
+            format!("{}, no instructions of other platforms found", self.platform.name())
+        }
+        else {
+            format!("{}, uses {}", self.platform.name(), opcodes(&self.evidence))
+        };
+
+        if self.quirk_evidence.is_empty() {
+            return format!("{}, {} quirks", platform, self.quirks);
+        }
+
+        format!("{}, {} quirks for {}", platform, self.quirks, opcodes(&self.quirk_evidence))
+    }
+}
+
+#[cfg(feature = "std")]
+fn opcodes(opcodes: &[u16]) -> String {
+    let opcodes: Vec<String> = opcodes.iter()
+        .take(5)
+        .map(|opcode| format!("{:04X}", opcode))
+        .collect();
+
+    opcodes.join(", ")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_chip8() {
+        // LD V0, #05; DRW V0, V0, 5; JP #204
+        let detection = detect(&[0x60, 0x05, 0xD0, 0x05, 0x12, 0x04]);
+
+        assert_eq!(detection.platform, Platform::Chip8);
+        assert_eq!(detection.quirks, "chip8");
+        assert!(detection.quirk_evidence.is_empty());
+    }
+
+    #[test]
+    fn superchip_and_xochip_opcodes() {
+        // HIGH; JP #202
+        assert_eq!(detect(&[0x00, 0xFF, 0x12, 0x02]).platform, Platform::SuperChip);
+        // LD I, long #0300; JP #204
+        let detection = detect(&[0xF0, 0x00, 0x03, 0x00, 0x12, 0x04]);
+        assert_eq!(detection.platform, Platform::XoChip);
+        assert_eq!(detection.quirks, "xochip");
+    }
+
+    #[test]
+    fn shift_of_another_register() {
+        // SHR V0, V1; JP #202
+        let detection = detect(&[0x80, 0x16, 0x12, 0x02]);
+        assert_eq!(detection.quirks, "vip");
+        assert_eq!(detection.quirk_evidence, vec![0x8016]);
+
+        // SHR V0, V0 works the same either way
+        assert_eq!(detect(&[0x80, 0x06, 0x12, 0x02]).quirks, "chip8");
+    }
+
+    #[test]
+    fn loads_that_rely_on_i_moving() {
+        // LD I, #300; LD V1, [I]; LD V1, [I]; JP #206
+        assert_eq!(detect(&[0xA3, 0x00, 0xF1, 0x65, 0xF1, 0x65, 0x12, 0x06]).quirks, "vip");
+        // LD I, #300; loop: LD V1, [I]; JP loop
+        assert_eq!(detect(&[0xA3, 0x00, 0xF1, 0x65, 0x12, 0x02]).quirks, "vip");
+        // LD I, #300; LD V1, [I]; LD I, #302; LD V1, [I]; JP #208
+        assert_eq!(detect(&[0xA3, 0x00, 0xF1, 0x65, 0xA3, 0x02, 0xF1, 0x65, 0x12, 0x08]).quirks, "chip8");
+    }
+
+    #[test]
+    fn jump_by_vx() {
+        // LD V1, #04; JP V0, #100 (BXNN by V1 on SUPER-CHIP)
+        let detection = detect(&[0x61, 0x04, 0xB1, 0x00]);
+        assert_eq!(detection.quirks, "schip");
+        assert_eq!(detection.quirk_evidence, vec![0xB100]);
+
+        // LD V0, #04; JP V0, #100
+        assert_eq!(detect(&[0x60, 0x04, 0xB1, 0x00]).quirks, "chip8");
+    }
+
+    #[test]
+    fn data_gives_nothing_away() {
+        // JP #200, followed by bytes that would be SHR V0, V1
+        assert_eq!(detect(&[0x12, 0x00, 0x80, 0x16]).quirks, "chip8");
+
+        // LD I, sprite; DRW V0, V0, 4; JP #204; sprite: bytes that would be HIGH, SHR V0, V1,
+        // a long LD I and a 16x16 sprite
+        let detection = detect(&[0xA2, 0x06, 0xD0, 0x04, 0x12, 0x04, 0x00, 0xFF, 0x80, 0x16, 0xF0, 0x00, 0xD1, 0x20]);
+        assert_eq!(detection.platform, Platform::Chip8);
+        assert!(detection.evidence.is_empty());
+        assert_eq!(detection.quirks, "chip8");
+        assert!(detection.quirk_evidence.is_empty());
+    }
+
+    #[test]
+    fn code_after_new_instructions_counts() {
+        // LD I, long #0300; SE V0, 0 (skips all of the next); LD I, long #0400; HIGH; SHR V0, V1; JP #20E
+        let detection = detect(&[0xF0, 0x00, 0x03, 0x00, 0x30, 0x00, 0xF0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0x80, 0x16, 0x12, 0x0E]);
+        assert_eq!(detection.platform, Platform::XoChip);
+        assert_eq!(detection.evidence, vec![0xF000, 0x00FF]);
+    }
+}
@@ -0,0 +1,419 @@
+//! Turns opcodes back into the mnemonics of Cowgod's reference. Addresses and bytes are
+//! written as #hex, nibbles as decimal.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::detect::platform_of;
+use crate::emulator::instructions::{decode, Op};
+
+/// The assembly for a single opcode, None if it isn't a valid chip8 instruction
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    let addr = opcode & 0x0FFF;
+    let byte = opcode & 0x00FF;
+    let nibble = opcode & 0x000F;
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+
+    let registers = |operation: &str| format!("{} V{:X}, V{:X}", operation, x, y);
+
+    let text = match decode(opcode).op {
+        Op::Cls => "CLS".to_string(),
+        Op::Ret => "RET".to_string(),
+        Op::Sys => format!("SYS #{:03X}", addr),
+        Op::Jp => format!("JP #{:03X}", addr),
+        Op::Call => format!("CALL #{:03X}", addr),
+        Op::SeByte => format!("SE V{:X}, #{:02X}", x, byte),
+        Op::SneByte => format!("SNE V{:X}, #{:02X}", x, byte),
+        Op::SeReg => registers("SE"),
+        Op::LdByte => format!("LD V{:X}, #{:02X}", x, byte),
+        Op::AddByte => format!("ADD V{:X}, #{:02X}", x, byte),
+        Op::LdReg => registers("LD"),
+        Op::Or => registers("OR"),
+        Op::And => registers("AND"),
+        Op::Xor => registers("XOR"),
+        Op::AddReg => registers("ADD"),
+        Op::Sub => registers("SUB"),
+        Op::Shr => registers("SHR"),
+        Op::Subn => registers("SUBN"),
+        Op::Shl => registers("SHL"),
+        Op::SneReg => registers("SNE"),
+        Op::LdI => format!("LD I, #{:03X}", addr),
+        Op::JpV0 => format!("JP V0, #{:03X}", addr),
+        Op::Rnd => format!("RND V{:X}, #{:02X}", x, byte),
+        Op::Drw => format!("DRW V{:X}, V{:X}, {}", x, y, nibble),
+        Op::Skp => format!("SKP V{:X}", x),
+        Op::Sknp => format!("SKNP V{:X}", x),
+        Op::LdVxDt => format!("LD V{:X}, DT", x),
+        Op::LdVxK => format!("LD V{:X}, K", x),
+        Op::LdDtVx => format!("LD DT, V{:X}", x),
+        Op::LdStVx => format!("LD ST, V{:X}", x),
+        Op::AddI => format!("ADD I, V{:X}", x),
+        Op::LdF => format!("LD F, V{:X}", x),
+        Op::LdB => format!("LD B, V{:X}", x),
+        Op::Store => format!("LD [I], V{:X}", x),
+        Op::Load => format!("LD V{:X}, [I]", x),
+        Op::Unknown => return None,
+    };
+
+    Some(text)
+}
+
+/// A plain listing of a ROM loaded at `start`, every pair of bytes is decoded as an instruction
+pub fn listing(program: &[u8], start: usize) -> String {
+    let mut text = String::new();
+
+    for (idx, chunk) in program.chunks(2).enumerate() {
+        let addr = start + idx * 2;
+
+        if chunk.len() < 2 {
+            text.push_str(&format!("{:03X}: {:02X}        db #{:02X}\n", addr, chunk[0], chunk[0]));
+            continue;
+        }
+
+        let opcode = (chunk[0] as u16) << 8 | chunk[1] as u16;
+        let assembly = match mnemonic(opcode) {
+            Some(assembly) => assembly,
+            None => format!("dw #{:04X}", opcode),
+        };
+
+        text.push_str(&format!("{:03X}: {:02X} {:02X}     {}\n", addr, chunk[0], chunk[1], assembly));
+    }
+
+    text
+}
+
+/// What a label marks, later kinds win when an address is reached in more than one way
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LabelKind {
+    /// Loaded into I, usually a sprite
+    Data,
+    /// The start of a BNNN jump table
+    Table,
+    /// The target of a jump or skip
+    Jump,
+    /// Called with CALL
+    Subroutine,
+}
+
+/// Which bytes of a program are instructions, found by following the control flow from
+/// the start instead of decoding everything. Sprite data in between isn't mistaken for
+/// code that way.
+pub struct Analysis {
+    /// Where the program is loaded
+    pub start: usize,
+    /// The address right after the last byte of the program
+    pub end: usize,
+    /// The addresses instructions start at
+    pub code: BTreeSet<usize>,
+    /// Addresses something branches to or loads into I
+    pub labels: BTreeMap<usize, LabelKind>,
+}
+
+impl Analysis {
+    /// Whether an instruction starts at `addr`
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.code.contains(&addr)
+    }
+
+    fn label(&mut self, addr: usize, kind: LabelKind) {
+        let label = self.labels.entry(addr).or_insert(kind);
+        *label = (*label).max(kind);
+    }
+}
+
+impl LabelKind {
+    /// The label for `addr`, named after what it marks: data_, table_, label_ or sub_
+    pub fn name(&self, addr: usize) -> String {
+        let prefix = match self {
+            LabelKind::Data => "data",
+            LabelKind::Table => "table",
+            LabelKind::Jump => "label",
+            LabelKind::Subroutine => "sub",
+        };
+
+        format!("{}_{:03X}", prefix, addr)
+    }
+}
+
+/// Follows jumps, calls, skips and BNNN tables from the start of a program loaded at `start`
+pub fn analyze(program: &[u8], start: usize) -> Analysis {
+    follow(program, start, false)
+}
+
+/// Like `analyze`, but also follows the instructions SUPER-CHIP and XO-CHIP added, where
+/// plain chip8 code would end. For finding out which of them a program uses.
+pub fn analyze_extended(program: &[u8], start: usize) -> Analysis {
+    follow(program, start, true)
+}
+
+fn follow(program: &[u8], start: usize, extended: bool) -> Analysis {
+    let mut analysis = Analysis {
+        start,
+        end: start + program.len(),
+        code: BTreeSet::new(),
+        labels: BTreeMap::new(),
+    };
+
+    let opcode_at = |addr: usize| -> Option<u16> {
+        if addr < start || addr + 2 > start + program.len() {
+            return None;
+        }
+
+        Some((program[addr - start] as u16) << 8 | program[addr - start + 1] as u16)
+    };
+
+    let mut pending = vec![start];
+
+    while let Some(addr) = pending.pop() {
+        if analysis.is_code(addr) {
+            continue;
+        }
+
+        // Running off the program or into an invalid instruction means the path was
+        // never taken, or ran into data
+        let opcode = match opcode_at(addr) {
+            Some(opcode) if mnemonic(opcode).is_some() => opcode,
+            Some(opcode) if extended && platform_of(opcode).is_some() => opcode,
+            _ => continue,
+        };
+
+        analysis.code.insert(addr);
+
+        // XO-CHIP's LD I, long (F000 NNNN) takes 4 bytes, skips jump over all of them
+        let long = |addr: usize| extended && opcode_at(addr) == Some(0xF000);
+        let target = (opcode & 0x0FFF) as usize;
+        let next = if long(addr) { addr + 4 } else { addr + 2 };
+        let skip = if long(next) { next + 4 } else { next + 2 };
+
+        match opcode >> 12 {
+            // RET and EXIT end the path
+            0x0 if opcode == 0x00EE || opcode == 0x00FD => {},
+            0x1 => {
+                analysis.label(target, LabelKind::Jump);
+                pending.push(target);
+            },
+            0x2 => {
+                analysis.label(target, LabelKind::Subroutine);
+                pending.push(target);
+                pending.push(next);
+            },
+            0x3 | 0x4 | 0x5 | 0x9 => {
+                pending.push(next);
+                pending.push(skip);
+            },
+            0xE => {
+                pending.push(next);
+                pending.push(skip);
+            },
+            0xA => {
+                analysis.label(target, LabelKind::Data);
+                pending.push(next);
+            },
+            // Jump tables are rows of JPs, V0 picks one
+            0xB => {
+                analysis.label(target, LabelKind::Table);
+
+                let mut entry = target;
+                while let Some(opcode) = opcode_at(entry) {
+                    if opcode >> 12 != 0x1 {
+                        break;
+                    }
+                    pending.push(entry);
+                    entry += 2;
+                }
+            },
+            _ => pending.push(next),
+        }
+    }
+
+    // Jumps into the middle of the data loaded into I happen, a data label on code is
+    // just a jump target
+    let code = analysis.code.clone();
+    for (addr, kind) in analysis.labels.iter_mut() {
+        if *kind == LabelKind::Data && code.contains(addr) {
+            *kind = LabelKind::Jump;
+        }
+    }
+
+    analysis
+}
+
+// Data bytes not loaded into I are grouped this many per line
+const BYTES_PER_LINE: usize = 8;
+
+// Column the address and raw bytes comments start at
+const COMMENT_COLUMN: usize = 28;
+
+/// Disassembles a program loaded at `start` with labels for branch targets, and the bytes
+/// that aren't code as data. Sprites (data loaded into I) are drawn next to their bytes.
+/// Addresses and raw bytes are comments, so the output assembles back into the program.
+pub fn disassemble(program: &[u8], start: usize) -> String {
+    let analysis = analyze(program, start);
+
+    // Where lines start, labels can only be put there
+    let mut lines = Vec::new();
+    let mut addr = start;
+    while addr < analysis.end {
+        let len = if analysis.is_code(addr) {
+            2
+        }
+        else {
+            data_run(&analysis, addr)
+        };
+
+        lines.push((addr, len));
+        addr += len;
+    }
+
+    let line_starts: BTreeSet<usize> = lines.iter().map(|(addr, _)| *addr).collect();
+    let label = |addr: usize| -> Option<String> {
+        match analysis.labels.get(&addr) {
+            Some(kind) if line_starts.contains(&addr) => Some(kind.name(addr)),
+            _ => None,
+        }
+    };
+
+    let mut text = String::new();
+    let mut in_sprite = false;
+
+    for (addr, len) in lines {
+        let bytes = &program[addr - start..addr - start + len];
+
+        if let Some(name) = label(addr) {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("{}:\n", name));
+            in_sprite = analysis.labels.get(&addr) == Some(&LabelKind::Data);
+        }
+
+        let (assembly, comment) = if analysis.is_code(addr) {
+            let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
+            let target = (opcode & 0x0FFF) as usize;
+            let mut assembly = mnemonic(opcode).unwrap();
+
+            if matches!(opcode >> 12, 0x1 | 0x2 | 0xA | 0xB) {
+                if let Some(name) = label(target) {
+                    assembly = assembly.replace(&format!("#{:03X}", target), &name);
+                }
+            }
+
+            in_sprite = false;
+            (assembly, format!("{:03X}  {:02X} {:02X}", addr, bytes[0], bytes[1]))
+        }
+        else if in_sprite {
+            let row: String = (0..8).rev()
+                .map(|bit| if bytes[0] & (1 << bit) != 0 { '#' } else { '.' })
+                .collect();
+
+            (format!("db %{:08b}", bytes[0]), format!("{:03X}  {}", addr, row))
+        }
+        else {
+            let values: Vec<String> = bytes.iter().map(|byte| format!("#{:02X}", byte)).collect();
+
+            (format!("db {}", values.join(", ")), format!("{:03X}", addr))
+        };
+
+        text.push_str(&format!("    {:<width$} ; {}\n", assembly, comment, width = COMMENT_COLUMN - 5));
+    }
+
+    text
+}
+
+// The number of data bytes that go on the line at `addr`: up to the next instruction or
+// label, one byte per line for sprites
+fn data_run(analysis: &Analysis, addr: usize) -> usize {
+    let sprite = analysis.labels.range(..=addr).next_back()
+        .is_some_and(|(_, kind)| *kind == LabelKind::Data);
+    if sprite {
+        return 1;
+    }
+
+    let mut len = 1;
+    while len < BYTES_PER_LINE && addr + len < analysis.end
+        && !analysis.is_code(addr + len) && !analysis.labels.contains_key(&(addr + len)) {
+        len += 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    #[test]
+    fn mnemonics_follow_cowgod() {
+        assert_eq!(mnemonic(0x00E0).unwrap(), "CLS");
+        assert_eq!(mnemonic(0x8AB4).unwrap(), "ADD VA, VB");
+        assert_eq!(mnemonic(0xD125).unwrap(), "DRW V1, V2, 5");
+        assert_eq!(mnemonic(0xF355).unwrap(), "LD [I], V3");
+        assert_eq!(mnemonic(0x5121), None);
+        assert_eq!(mnemonic(0x8008), None);
+        assert_eq!(mnemonic(0xE000), None);
+    }
+
+    #[test]
+    fn every_instruction_assembles_back() {
+        for opcode in 0..=0xFFFF {
+            if let Some(text) = mnemonic(opcode) {
+                let assembly = assemble(&text).unwrap_or_else(|err| panic!("{}: {}", text, err));
+                assert_eq!(assembly.program, opcode.to_be_bytes(), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn data_runs_end_at_code_labels_and_sprites() {
+        let analysis = Analysis {
+            start: 0x200,
+            end: 0x21C,
+            code: BTreeSet::from([0x200, 0x206]),
+            labels: BTreeMap::from([(0x20C, LabelKind::Jump), (0x216, LabelKind::Data)]),
+        };
+
+        // Up to the next instruction
+        assert_eq!(data_run(&analysis, 0x202), 4);
+        // Up to the next label
+        assert_eq!(data_run(&analysis, 0x208), 4);
+        // At most a line full
+        assert_eq!(data_run(&analysis, 0x20C), BYTES_PER_LINE);
+        assert_eq!(data_run(&analysis, 0x214), 2);
+        // Sprites get a line for every row
+        assert_eq!(data_run(&analysis, 0x216), 1);
+        assert_eq!(data_run(&analysis, 0x21A), 1);
+
+        let analysis = Analysis { end: 0x205, ..analysis };
+        // Up to the end of the program
+        assert_eq!(data_run(&analysis, 0x202), 3);
+    }
+
+    #[test]
+    fn sprites_are_not_taken_for_code() {
+        let program = [0xA2, 0x08, 0x22, 0x06, 0x12, 0x04, 0x00, 0xEE, 0x3C, 0x42];
+        let analysis = analyze(&program, 0x200);
+
+        assert_eq!(analysis.code.iter().copied().collect::<Vec<_>>(), [0x200, 0x202, 0x204, 0x206]);
+        assert_eq!(analysis.labels[&0x204], LabelKind::Jump);
+        assert_eq!(analysis.labels[&0x206], LabelKind::Subroutine);
+        assert_eq!(analysis.labels[&0x208], LabelKind::Data);
+
+        let text = disassemble(&program, 0x200);
+        assert!(text.contains("LD I, data_208"));
+        assert!(text.contains("CALL sub_206"));
+        assert!(text.contains("label_204:\n"));
+        assert!(text.contains("db %00111100"));
+        assert!(text.contains("..####.."));
+    }
+
+    #[test]
+    fn jump_tables_are_followed() {
+        let program = [0xB2, 0x04, 0x00, 0x00, 0x12, 0x08, 0x12, 0x0A, 0x00, 0xEE, 0x00, 0xEE];
+        let analysis = analyze(&program, 0x200);
+
+        assert_eq!(analysis.code.iter().copied().collect::<Vec<_>>(), [0x200, 0x204, 0x206, 0x208, 0x20A]);
+        assert_eq!(analysis.labels[&0x204], LabelKind::Table);
+        assert_eq!(analysis.labels[&0x20A], LabelKind::Jump);
+    }
+}
@@ -1,37 +1,180 @@
-mod display;
-mod instructions;
-
-use display::Display;
-use sdl2::keyboard::Keycode;
-use sdl2::EventPump;
-
-pub struct Emulator {
-    pub cpu: CPU,
-    pub memory: Vec<u8>,
-    pub screen: Display,
-    pub event_pump: EventPump,
-    pub program_size: usize,
-    pub running: bool,
-    pub PROGRAM_START: usize
+//! The interpreter: CPU, memory, screen and keypad of the chip8.
+
+// Writes a line to the trace output, if tracing is enabled. Without std there is
+// nowhere to write to.
+#[cfg(feature = "std")]
+macro_rules! trace {
+    ($emulator:expr, $($arg:tt)*) => {
+        if let Some(out) = $emulator.trace.as_mut() {
+            let _ = writeln!(out, $($arg)*);
+        }
+    };
+}
+
+#[cfg(not(feature = "std"))]
+macro_rules! trace {
+    ($emulator:expr, $($arg:tt)*) => {};
+}
+
+pub mod error;
+pub mod framebuffer;
+pub(crate) mod instructions;
+pub mod keys;
+pub mod quirks;
+mod snapshot;
+
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use error::{Error, Warning};
+use framebuffer::Framebuffer;
+use instructions::{decode, Instruction, Op};
+use keys::Keypad;
+use quirks::{Platform, Quirks};
+use rand::{RngCore, SeedableRng};
+use rand::rngs::SmallRng;
+
+pub use snapshot::STATE_SIZE;
+
+/// Where CXNN gets its random numbers from. Any generator of the rand crate works,
+/// others can implement this themselves.
+pub trait RandomSource {
+    /// A random byte
+    fn next_u8(&mut self) -> u8;
+}
+
+impl<T: RngCore> RandomSource for T {
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u32() & 0xFF) as u8
+    }
+}
+
+/// The random number generator used unless another one is given to the builder
+pub type DefaultRng = SmallRng;
+
+/// The speed of a new emulator, 600 instructions a second at 60 frames
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+/// The chip8 has 4KB of RAM
+pub const MEMORY_SIZE: usize = 4096;
+
+/// Programs are loaded here, the interpreter used to live below
+pub const PROGRAM_START: usize = 0x200;
+
+/// The largest ROM that fits into memory
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+
+// Warnings that pile up while nobody takes them are dropped after this many
+const MAX_WARNINGS: usize = 16;
+
+// The font sits at the start of memory, 5 bytes for each of the 16 digits
+const FONT_SIZE: usize = 16 * 5;
+
+/// A chip8 machine: CPU, memory, display and keypad.
+///
+/// The emulator doesn't keep time, the caller runs `run_frame` 60 times a second (or
+/// steps through single instructions and ticks the timers itself). Nothing in here
+/// allocates, so it runs on targets without std (turn off the `std` feature). Only the
+/// `instruction-cache` feature puts decoded instructions on the heap.
+pub struct Emulator<R = DefaultRng> {
+    cpu: CPU,
+    memory: [u8; MEMORY_SIZE],
+    // The instruction at every address, decoded the first time it runs. Writes to memory
+    // drop the entries they change, so programs that modify themselves still work. It is
+    // kept on the heap so moving the emulator around stays cheap.
+    #[cfg(feature = "instruction-cache")]
+    decoded: Box<[Option<Instruction>]>,
+    screen: Framebuffer,
+    keys: Keypad,
+    quirks: Quirks,
+    // Source of CXNN, seed it to make runs reproducible
+    rng: R,
+    // Every executed instruction is logged here, if set
+    #[cfg(feature = "std")]
+    trace: Option<Box<dyn Write + Send>>,
+    program_size: usize,
+    // Number of instructions executed between two 60Hz timer ticks
+    instructions_per_frame: u32,
+    // Total number of instructions executed, for speed measurements
+    instructions_executed: u64,
+    // Problems the program ran into (unknown opcodes, ...), for the frontend to show.
+    // Only the first few are kept until someone takes them.
+    warnings: [Option<Warning>; MAX_WARNINGS],
+    // Warnings are also printed to stderr as they happen, if turned on
+    #[cfg(feature = "std")]
+    print_warnings: bool,
+}
+
+/// Sets up an emulator, see `Emulator::builder`
+pub struct EmulatorBuilder<R = DefaultRng> {
+    platform: Option<Platform>,
+    quirks: Option<Quirks>,
+    rng: R,
+    speed: Option<u32>,
 }
 
+/// The two timers, both count down at 60Hz until they reach 0
+// The registers keep the names of Cowgod's reference
+#[allow(non_snake_case)]
 pub struct Timer {
+    /// The delay timer, programs read it to keep time
     pub DT: u8,
+    /// The sound timer, the buzzer sounds while it isn't 0
     pub ST: u8
 }
 
+/// The registers of the chip8
+#[allow(non_snake_case)]
 pub struct CPU {
+    /// V0 - VF, VF doubles as the flag of some instructions
     pub registers: [u8; 16],
+    /// I, the address register
     pub VI: u16,
+    /// Return addresses of CALL
     pub stack: [u16; 16],
+    /// DT and ST
     pub timer: Timer,
+    /// The address of the next instruction
     pub PC: u16,
+    /// The number of return addresses on the stack
     pub SP: u8,
 }
 
 
 impl Emulator {
-    pub fn new(width: u32, height: u32) -> Emulator {
+    /// An emulator with the chip8 quirks, running 10 instructions per frame
+    pub fn new() -> Emulator {
+        Emulator::with_rng(default_rng())
+    }
+
+    /// Starts setting up an emulator with a platform, quirks, seed or speed
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder {
+            platform: None,
+            quirks: None,
+            rng: default_rng(),
+            speed: None,
+        }
+    }
+
+    /// Seeds the random number generator of CXNN, to make runs reproducible
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+}
+
+// Seeded from the operating system, or with 0 where there is none
+fn default_rng() -> DefaultRng {
+    #[cfg(feature = "std")]
+    return SmallRng::from_entropy();
+
+    #[cfg(not(feature = "std"))]
+    return SmallRng::seed_from_u64(0);
+}
+
+impl<R: RandomSource> Emulator<R> {
+    /// An emulator with the chip8 quirks that gets its random numbers from `rng`
+    pub fn with_rng(rng: R) -> Emulator<R> {
         let cpu = CPU {
             registers: [0; 16],
             VI: 0,
@@ -40,12 +183,12 @@ impl Emulator {
                 DT: 0,
                 ST: 0
             },
-            PC: 0,
+            PC: 0x200,
             SP: 0,
         };
 
         // The chip-8 langauge is capable of accessing up to 4KB of RAM
-        let mut memory: Vec<u8> = [0 ;4096].to_vec();
+        let mut memory = [0; MEMORY_SIZE];
 
         // load fonts
         let fonts = [0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -66,106 +209,376 @@ impl Emulator {
                      0xF0, 0x80, 0xF0, 0x80, 0x80  // F
                     ];
         
-        for addr in 0..fonts.len() {
-            memory[addr] = fonts[addr];
-        }
-
-        let screen = Display::new(width, height);
+        memory[..fonts.len()].copy_from_slice(&fonts);
 
         Emulator {
             cpu,
-            event_pump: screen.sdl_context.event_pump().unwrap(),
-            screen,
+            screen: Framebuffer::new(),
+            keys: Keypad::new(),
+            quirks: Quirks::default(),
+            rng,
+            #[cfg(feature = "std")]
+            trace: None,
             memory,
+            #[cfg(feature = "instruction-cache")]
+            decoded: vec![None; MEMORY_SIZE].into_boxed_slice(),
             program_size: 0,
-            running: false,
-            PROGRAM_START: 0x200,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            instructions_executed: 0,
+            warnings: [None; MAX_WARNINGS],
+            #[cfg(feature = "std")]
+            print_warnings: false,
+        }
+    }
+
+    /// The behaviours the interpreter follows where interpreters disagree
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Changes the quirks, takes effect with the next instruction
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Instructions executed per frame
+    pub fn speed(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Sets the instructions executed per frame, at least 1
+    pub fn set_speed(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame.max(1);
+    }
+
+    /// Logs every executed instruction to `out`, None stops logging
+    #[cfg(feature = "std")]
+    pub fn set_trace(&mut self, out: Option<Box<dyn Write + Send>>) {
+        self.trace = out;
+    }
+
+    /// The registers, timers and stack
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// All 4KB of memory, the font and the program included
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The screen, to draw after a frame
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.screen
+    }
+
+    /// For frontends that keep track of what they drew through `Framebuffer::dirty`
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.screen
+    }
+
+    /// The keys that are held down
+    pub fn keypad(&self) -> &Keypad {
+        &self.keys
+    }
+
+    /// Presses or releases a key of the hexadecimal keypad (0x0 - 0xF)
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keys.set_key(key, pressed);
+    }
+
+    /// Releases every key, when the window loses focus for example
+    pub fn release_keys(&mut self) {
+        self.keys.release_all();
+    }
+
+    /// True while the sound timer runs, which is when the buzzer should sound
+    pub fn sound_active(&self) -> bool {
+        self.cpu.timer.ST > 0
+    }
+
+    /// Instructions executed since the emulator was created
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// The oldest problem the program ran into that wasn't taken yet, an unknown opcode
+    /// for example
+    pub fn next_warning(&mut self) -> Option<Warning> {
+        let warning = self.warnings[0].take();
+        self.warnings.rotate_left(1);
+
+        warning
+    }
+
+    /// Also prints warnings to stderr as they happen. It is off by default, libraries and
+    /// embedded hosts collect them with next_warning instead.
+    #[cfg(feature = "std")]
+    pub fn set_print_warnings(&mut self, print: bool) {
+        self.print_warnings = print;
+    }
+
+    /// All the problems the program ran into since the last call
+    #[cfg(feature = "std")]
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::iter::from_fn(|| self.next_warning()).collect()
+    }
+
+    /// Puts the machine back into its power-on state, keeping the font, the quirks, the
+    /// speed and the keys that are held down
+    pub fn reset(&mut self) {
+        self.cpu.registers = [0; 16];
+        self.cpu.VI = 0;
+        self.cpu.stack = [0; 16];
+        self.cpu.timer.DT = 0;
+        self.cpu.timer.ST = 0;
+        self.cpu.PC = PROGRAM_START as u16;
+        self.cpu.SP = 0;
+
+        for byte in self.memory[FONT_SIZE..].iter_mut() {
+            *byte = 0;
         }
+        self.forget_decoded();
+
+        self.screen.clear();
+        self.keys.waiting = false;
+        self.keys.released = None;
+        self.program_size = 0;
+        self.warnings = [None; MAX_WARNINGS];
     }
 
-    pub fn read_rom(&mut self, program: &mut Vec<u8>) {
+    /// Resets the machine and loads a program at 0x200
+    pub fn load_rom(&mut self, program: &[u8]) -> Result<(), Error> {
+        if program.is_empty() {
+            return Err(Error::EmptyRom);
+        }
+
+        if program.len() > MAX_ROM_SIZE {
+            return Err(Error::RomTooLarge(program.len()));
+        }
 
-        let addr: usize = self.PROGRAM_START;
+        self.reset();
         self.program_size = program.len();
-        
-        for idx in 0..self.program_size {
-            self.write(addr + idx, program[idx]);
+
+        for (idx, byte) in program.iter().enumerate() {
+            self.write(PROGRAM_START + idx, *byte);
         }
+
+        Ok(())
     }
 
-    pub fn write(&mut self, addr: usize, value: u8) {
-        if addr == 15 {
-            println!("{:#x}", value);
+    fn write(&mut self, addr: usize, value: u8) {
+        if let Some(byte) = self.memory.get_mut(addr) {
+            *byte = value;
+
+            // The byte is part of the instruction starting at it and the one before it
+            #[cfg(feature = "instruction-cache")]
+            {
+                self.decoded[addr] = None;
+                if addr > 0 {
+                    self.decoded[addr - 1] = None;
+                }
+            }
         }
-        self.memory[addr] = value;
     }
 
-    pub fn read(& self, addr: usize) -> u8 {
-        return self.memory[addr]
+    // Empties the instruction cache, for when all of memory changes at once
+    fn forget_decoded(&mut self) {
+        #[cfg(feature = "instruction-cache")]
+        self.decoded.fill(None);
+    }
+
+    /// The byte at `addr`, 0 past the end of memory
+    pub fn read(&self, addr: usize) -> u8 {
+        self.memory.get(addr).copied().unwrap_or(0)
     }
 
-    pub fn convert_to_opcode(&self, b1: u8, b2: u8) -> u16 {
-        let opcode: u16 = (b1 as u16) << 8 | b2 as u16;
+    // Checks that an instruction accessing `len` bytes from `start` stays within memory,
+    // warns if it doesn't
+    fn check_bounds(&mut self, start: usize, len: usize) -> bool {
+        if start + len <= MEMORY_SIZE {
+            return true;
+        }
+
+        let addr = self.cpu.PC.wrapping_sub(2);
+        self.warn(Warning::OutOfBounds { addr, target: start.max(MEMORY_SIZE) });
 
-        return opcode;
+        false
     }
 
-    fn get_mapped_key(&self, primitive_key: u8) -> Keycode {
-        match primitive_key {
-            0 => {
-                Keycode::Num0
-            },
-            1 => {
-                Keycode::Num1
-            },
-            2 => {
-                Keycode::Num2
-            },
-            3 => {
-                Keycode::Num3
-            },
-            4 => {
-                Keycode::Num4
-            },
-            5 => {
-                Keycode::Num5
-            },
-            6 => {
-                Keycode::Num6
-            },
-            7 => {
-                Keycode::Num7
-            },
-            8 => {
-                Keycode::Num8
-            },
-            9 => {
-                Keycode::Num9
-            },
-            10 => {
-                Keycode::A
-            },
-            11 => {
-                Keycode::B
-            },
-            12 => {
-                Keycode::C
-            },
-            13 => {
-                Keycode::D
-            },
-            14 => {
-                Keycode::E
-            },
-            15 => {
-                Keycode::F
-            },
-            _ => {
-                Keycode::Space
+    /// The two bytes at `addr` as an opcode
+    pub fn opcode_at(&self, addr: usize) -> u16 {
+        self.convert_to_opcode(self.read(addr), self.read(addr + 1))
+    }
+
+    fn convert_to_opcode(&self, b1: u8, b2: u8) -> u16 {
+        (b1 as u16) << 8 | b2 as u16
+    }
+
+    /// Fetches, decodes and executes a single instruction, returns its opcode
+    pub fn step(&mut self) -> u16 {
+        self.cycle().opcode
+    }
+
+    // Decodes the instruction at `addr`, or takes it from the cache if it ran before
+    fn fetch(&mut self, addr: usize) -> Instruction {
+        #[cfg(feature = "instruction-cache")]
+        if let Some(instruction) = self.decoded[addr] {
+            return instruction;
+        }
+
+        let instruction = decode(self.opcode_at(addr));
+        #[cfg(feature = "instruction-cache")]
+        {
+            self.decoded[addr] = Some(instruction);
+        }
+
+        instruction
+    }
+
+    // Executes the instruction at PC
+    fn cycle(&mut self) -> Instruction {
+        // Fetch
+        let pc: usize = self.cpu.PC as usize;
+        let instruction = self.fetch(pc);
+        self.cpu.PC = (self.cpu.PC + 2) % MEMORY_SIZE as u16;
+        self.instructions_executed += 1;
+
+        #[cfg(feature = "std")]
+        if let Some(out) = self.trace.as_mut() {
+            let _ = write!(out, "Execute [{:#x}] {:#06x}: ", pc, instruction.opcode);
+        }
+
+        // Execute
+        self.execute_decoded(instruction);
+
+        instruction
+    }
+
+    // Skips the next instruction, wrapping around at the end of memory
+    fn skip(&mut self) {
+        self.cpu.PC = (self.cpu.PC + 2) % MEMORY_SIZE as u16;
+    }
+
+    /// Runs a frame worth of instructions and then ticks the timers, to be called at 60Hz
+    pub fn run_frame(&mut self) {
+        self.frame(|_| {});
+    }
+
+    /// Like `run_frame`, but hands every warning to `on_warning` right after the instruction
+    /// that ran into it. Only a few warnings are kept until they are taken, a frame at a
+    /// high speed can run into many more.
+    pub fn run_frame_with_warnings(&mut self, mut on_warning: impl FnMut(Warning)) {
+        self.frame(|emulator| {
+            while let Some(warning) = emulator.next_warning() {
+                on_warning(warning);
+            }
+        });
+    }
+
+    fn frame(&mut self, mut after_instruction: impl FnMut(&mut Self)) {
+        for _ in 0..self.instructions_per_frame {
+            let instruction = self.cycle();
+            after_instruction(self);
+
+            // Sprites were only drawn during the vertical blank on the VIP
+            if self.quirks.display_wait && instruction.op == Op::Drw {
+                break;
             }
         }
+
+        self.tick_timers();
     }
 
+    /// Counts the delay and sound timers down, to be called at 60Hz
+    pub fn tick_timers(&mut self) {
+        if self.cpu.timer.DT != 0 {
+            self.cpu.timer.DT -= 1;
+        }
+
+        if self.cpu.timer.ST != 0 {
+            self.cpu.timer.ST -= 1;
+        }
+    }
+
+    fn unknown_opcode(&mut self, opcode: u16) {
+        let addr = self.cpu.PC.wrapping_sub(2);
+
+        self.warn(Warning::UnknownOpcode { opcode, addr });
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        #[cfg(feature = "std")]
+        if self.print_warnings {
+            eprintln!("{}", warning);
+        }
+
+        if let Some(slot) = self.warnings.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(warning);
+        }
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
+    }
+}
+
+impl EmulatorBuilder {
+    /// Seeds the default random number generator, so CXNN gives the same numbers every run
+    pub fn seed(mut self, seed: u64) -> EmulatorBuilder {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl<R: RandomSource> EmulatorBuilder<R> {
+    /// Uses the default quirks of a platform, unless quirks are given too
+    pub fn platform(mut self, platform: Platform) -> EmulatorBuilder<R> {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Uses these quirks instead of the default ones
+    pub fn quirks(mut self, quirks: Quirks) -> EmulatorBuilder<R> {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Instructions executed per frame
+    pub fn speed(mut self, instructions_per_frame: u32) -> EmulatorBuilder<R> {
+        self.speed = Some(instructions_per_frame);
+        self
+    }
+
+    /// Gets the random numbers of CXNN from `rng` instead
+    pub fn rng<T: RandomSource>(self, rng: T) -> EmulatorBuilder<T> {
+        EmulatorBuilder {
+            platform: self.platform,
+            quirks: self.quirks,
+            rng,
+            speed: self.speed,
+        }
+    }
+
+    /// The emulator, with nothing loaded yet
+    pub fn build(self) -> Emulator<R> {
+        let mut emulator = Emulator::with_rng(self.rng);
+
+        // Explicit quirks win over the defaults of the platform
+        emulator.quirks = match (self.quirks, self.platform) {
+            (Some(quirks), _) => quirks,
+            (None, Some(platform)) => platform.default_quirks(),
+            (None, None) => emulator.quirks,
+        };
+
+        if let Some(speed) = self.speed {
+            emulator.set_speed(speed);
+        }
+
+        emulator
+    }
 }
 
 // NNN: Address
@@ -174,4 +587,80 @@ impl Emulator {
 
 // X & Y: [0 - F]: Each identifying a 4-bit egister
 //  - Registers are references as VX/VY.
-// I: 2 byte register (For memory address) (Similar to void pointer)
\ No newline at end of file
+// I: 2 byte register (For memory address) (Similar to void pointer)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_drop_the_decoded_instructions_they_change() {
+        // The STORE at 214 rewrites the LD V2, 5 at 20A into LD V2, 9 after it ran once
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&[0x63, 0x00, 0xA2, 0x0A, 0x60, 0x62, 0x61, 0x09, 0x12, 0x0A, 0x62, 0x05,
+            0x33, 0x01, 0x12, 0x14, 0x12, 0x10, 0x00, 0x00, 0x63, 0x01, 0xF1, 0x55, 0x12, 0x0A]).unwrap();
+
+        for _ in 0..7 {
+            emulator.step();
+        }
+        assert_eq!(emulator.cpu.registers[2], 5);
+        #[cfg(feature = "instruction-cache")]
+        assert!(emulator.decoded[0x20A].is_some());
+
+        for _ in 0..6 {
+            emulator.step();
+        }
+        assert_eq!(emulator.cpu.registers[2], 9);
+    }
+
+    #[test]
+    fn writes_to_the_second_byte_drop_the_decoded_instruction() {
+        // LD I, 205; LD V0, 9; LD V1, 5; LD [I], V0; JP 204
+        // The STORE only changes the byte after the 61 of LD V1, 5
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&[0xA2, 0x05, 0x60, 0x09, 0x61, 0x05, 0xF0, 0x55, 0x12, 0x04]).unwrap();
+
+        for _ in 0..3 {
+            emulator.step();
+        }
+        assert_eq!(emulator.cpu.registers[1], 5);
+        #[cfg(feature = "instruction-cache")]
+        assert!(emulator.decoded[0x204].is_some());
+
+        emulator.step();
+        #[cfg(feature = "instruction-cache")]
+        assert!(emulator.decoded[0x204].is_none());
+
+        emulator.step();
+        assert_eq!(emulator.step(), 0x6109);
+        assert_eq!(emulator.cpu.registers[1], 9);
+    }
+
+    #[test]
+    fn resets_drop_the_decoded_instructions() {
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&[0x60, 0x01]).unwrap();
+        emulator.step();
+
+        emulator.load_rom(&[0x60, 0x02]).unwrap();
+        emulator.step();
+        assert_eq!(emulator.cpu.registers[0], 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn frames_hand_over_every_warning() {
+        // An unknown opcode and a jump back to it, 20 times a frame
+        let rom = [0x51, 0x21, 0x12, 0x00];
+        let mut emulator = Emulator::builder().speed(40).build();
+        emulator.load_rom(&rom).unwrap();
+
+        let mut warnings = Vec::new();
+        emulator.run_frame_with_warnings(|warning| warnings.push(warning));
+        assert_eq!(warnings.len(), 20);
+        assert!(warnings.iter().all(|warning| *warning == Warning::UnknownOpcode { opcode: 0x5121, addr: 0x200 }));
+
+        // Without taking them only the first few are kept
+        emulator.run_frame();
+        assert_eq!(emulator.take_warnings().len(), MAX_WARNINGS);
+    }
+}
@@ -0,0 +1,73 @@
+//! What can go wrong while loading and running programs.
+
+use core::fmt;
+
+use crate::detect;
+
+/// What can go wrong when loading a ROM or a save state
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Error {
+    /// The ROM has no bytes
+    EmptyRom,
+    /// The ROM doesn't fit into memory, with its size
+    RomTooLarge(usize),
+    /// The data doesn't start like a save state
+    NotASaveState,
+    /// The save state was written by a newer version, with its version
+    UnsupportedStateVersion(u8),
+    /// The save state ends early
+    TruncatedState,
+    /// The amount of memory in the save state
+    StateMemoryMismatch(usize),
+    /// PC, SP, the stack or the screen hold values the interpreter can't continue from
+    InvalidState,
+    /// The buffer for a save state is smaller than STATE_SIZE, with its size
+    BufferTooSmall(usize),
+}
+
+/// Problems a running program ran into, the emulator carries on after them
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(missing_docs)]
+pub enum Warning {
+    /// An opcode at addr the interpreter doesn't know, it is skipped
+    UnknownOpcode { opcode: u16, addr: u16 },
+    /// RET with an empty stack at addr, it jumps to the unused first entry anyway
+    InvalidReturn { addr: u16 },
+    /// CALL with a full stack at addr, the return address is lost
+    StackOverflow { addr: u16 },
+    /// The instruction at addr accessed memory at target, past the end. Reads give 0,
+    /// writes are dropped.
+    OutOfBounds { addr: u16, target: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EmptyRom => write!(f, "the ROM is empty"),
+            Error::RomTooLarge(size) => write!(f, "the ROM is {} bytes, only {} fit into memory", size, super::MAX_ROM_SIZE),
+            Error::NotASaveState => write!(f, "not a chip8 save state"),
+            Error::UnsupportedStateVersion(version) => write!(f, "unsupported save state version {}", version),
+            Error::TruncatedState => write!(f, "the save state is truncated"),
+            Error::StateMemoryMismatch(size) => write!(f, "the save state has {} bytes of memory, expected {}", size, super::MEMORY_SIZE),
+            Error::InvalidState => write!(f, "the save state holds an invalid CPU state"),
+            Error::BufferTooSmall(size) => write!(f, "the buffer has {} bytes, a save state needs {}", size, super::STATE_SIZE),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnknownOpcode { opcode, addr } => match detect::platform_of(*opcode) {
+                Some(platform) => write!(f, "Unknown opcode {:#06x} at {:#x} ({} instruction, not supported yet)", opcode, addr, platform.name()),
+                None => write!(f, "Unknown opcode {:#06x} at {:#x}", opcode, addr),
+            },
+            Warning::InvalidReturn { addr } => write!(f, "Error: Invalid Return at {:#x}", addr),
+            Warning::StackOverflow { addr } => write!(f, "Stack overflow at {:#x}", addr),
+            Warning::OutOfBounds { addr, target } => write!(f, "Out of bounds memory access to {:#x} at {:#x}", target, addr),
+        }
+    }
+}
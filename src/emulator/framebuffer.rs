@@ -0,0 +1,115 @@
+//! The screen of the chip8.
+
+/// The width of the screen in pixels
+pub const PIXELS_ALONG_X: u32 = 64;
+/// The height of the screen in pixels
+pub const PIXELS_ALONG_Y: u32 = 32;
+
+/// The chip8 screen as the interpreter sees it, a pixel is either 0 (off) or 1 (on).
+/// Drawing it is left to the frontend.
+#[derive(Clone)]
+pub struct Framebuffer {
+    /// The pixels, row by row from the top left
+    pub pixel_repr: [[u8; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize], 
+    /// Set whenever pixel_repr changes, so that unchanged frames aren't redrawn
+    pub dirty: bool,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+impl Framebuffer {
+    /// A blank screen
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            pixel_repr: [[0; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize],
+            dirty: true,
+        }
+    }
+
+    // DXYN, the sprite has to be within `memory`
+    pub(crate) fn xor_sprite(&mut self, memory: &[u8], start_addr: usize, pos: (u8, u8), sprite_height: u8, clip: bool) -> u8 {
+        // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
+        //
+        // The interpreter reads n bytes from memory, starting at the address stored in I. 
+        // These bytes are then displayed as sprites on screen at coordinates (Vx, Vy).
+        // Sprites are XORed onto the existing screen. If this causes any pixels to be erased, 
+        // VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part 
+        // of it is outside the coordinates of the display, it wraps around to the opposite 
+        // side of the screen (unless clip is set, then that part is not drawn at all).
+
+        let sprite = &memory[start_addr..start_addr + sprite_height as usize];
+
+        let width = self.pixel_repr[0].len() as u8;
+        let height = self.pixel_repr.len() as u8;
+
+        // The starting position always wraps, only the parts sticking out are clipped
+        let (start_x, start_y) = (pos.0 % width, pos.1 % height);
+
+        let mut collision: u8 = 0; 
+
+        for y in 0..sprite_height {
+            if clip && start_y as u16 + y as u16 >= height as u16 {
+                break;
+            }
+
+            let pos_y = ((start_y as u16 + y as u16) % (height as u16)) as u8;
+
+            for x in 0..8 {
+                if clip && start_x as u16 + (7 - x) as u16 >= width as u16 {
+                    continue;
+                }
+
+                let pos_x = ((start_x as u16 + (7 - x) as u16) % (width as u16)) as u8;
+
+                let cur = self.pixel_repr[pos_y as usize][pos_x as usize];
+                // The following fetches the binary value at position x of the row.
+                let sprite_pixel = (sprite[y as usize] & (1 << x)) >> x;
+
+                // XOR the pixels
+                self.pixel_repr[pos_y as usize][pos_x as usize] = cur ^ sprite_pixel;
+                // self.pixel_repr[pos_y as usize][pos_x as usize] = sprite_pixel; 
+
+                if sprite_pixel != 0 {
+                    self.dirty = true;
+                }
+
+                // VF tells whether a pixel that was on got turned off
+                if cur != 0 && sprite_pixel != 0 {
+                    collision = 1;
+                }
+            }
+        }
+
+        collision
+    }
+
+    /// Turns every pixel off
+    pub fn clear(&mut self) {
+        for y in 0..self.pixel_repr.len() {
+            for x in 0..self.pixel_repr[0].len() {
+                self.pixel_repr[y][x] = 0;
+            }
+        }
+
+        self.dirty = true;
+    }
+
+    /// Draws the screen with one character per pixel, for terminals and logs
+    #[cfg(feature = "std")]
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+
+        for row in self.pixel_repr.iter() {
+            for pixel_val in row.iter() {
+                text.push(if *pixel_val != 0 { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+}
@@ -1,446 +1,819 @@
-use crate::emulator::Emulator;
+use crate::emulator::{Emulator, RandomSource, MEMORY_SIZE};
+use crate::emulator::error::Warning;
+
+// The operations of the chip8, what decoding an opcode finds out
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Cls,
+    Ret,
+    Sys,
+    Jp,
+    Call,
+    SeByte,
+    SneByte,
+    SeReg,
+    LdByte,
+    AddByte,
+    LdReg,
+    Or,
+    And,
+    Xor,
+    AddReg,
+    Sub,
+    Shr,
+    Subn,
+    Shl,
+    SneReg,
+    LdI,
+    JpV0,
+    Rnd,
+    Drw,
+    Skp,
+    Sknp,
+    LdVxDt,
+    LdVxK,
+    LdDtVx,
+    LdStVx,
+    AddI,
+    LdF,
+    LdB,
+    Store,
+    Load,
+    Unknown,
+}
 
-use sdl2::event::Event;
-use rand::{Rng, thread_rng};
+// A decoded opcode, the operands are still read out of the opcode when it runs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Instruction {
+    pub op: Op,
+    pub opcode: u16,
+}
 
-fn hex_to_int(hex_str: &str) -> u16 {
-    return u16::from_str_radix(hex_str, 16).expect("Cannot convert hex.");
+pub fn decode(opcode: u16) -> Instruction {
+    let nibble = opcode & 0x000F;
+    let byte = opcode & 0x00FF;
+
+    let op = match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => Op::Cls,
+            0x00EE => Op::Ret,
+            _ => Op::Sys,
+        },
+        0x1 => Op::Jp,
+        0x2 => Op::Call,
+        0x3 => Op::SeByte,
+        0x4 => Op::SneByte,
+        0x5 if nibble == 0 => Op::SeReg,
+        0x6 => Op::LdByte,
+        0x7 => Op::AddByte,
+        0x8 => match nibble {
+            0x0 => Op::LdReg,
+            0x1 => Op::Or,
+            0x2 => Op::And,
+            0x3 => Op::Xor,
+            0x4 => Op::AddReg,
+            0x5 => Op::Sub,
+            0x6 => Op::Shr,
+            0x7 => Op::Subn,
+            0xE => Op::Shl,
+            _ => Op::Unknown,
+        },
+        0x9 if nibble == 0 => Op::SneReg,
+        0xA => Op::LdI,
+        0xB => Op::JpV0,
+        0xC => Op::Rnd,
+        0xD => Op::Drw,
+        0xE => match byte {
+            0x9E => Op::Skp,
+            0xA1 => Op::Sknp,
+            _ => Op::Unknown,
+        },
+        0xF => match byte {
+            0x07 => Op::LdVxDt,
+            0x0A => Op::LdVxK,
+            0x15 => Op::LdDtVx,
+            0x18 => Op::LdStVx,
+            0x1E => Op::AddI,
+            0x29 => Op::LdF,
+            0x33 => Op::LdB,
+            0x55 => Op::Store,
+            0x65 => Op::Load,
+            _ => Op::Unknown,
+        },
+        _ => Op::Unknown,
+    };
+
+    Instruction { op, opcode }
 }
 
-impl Emulator {
-    pub fn execute(&mut self, opcode: u16, key_event_triggered: Vec<Event>) {
-        // This will be an array of bytes
-        let temp = format!("{:#06x}", opcode);
-        let opcode_str = temp.strip_prefix("0x").unwrap();
-        // let opcode_bytes = opcode_str.strip_prefix("0x").unwrap().as_bytes();
-        // Big endian
-        
+impl<R: RandomSource> Emulator<R> {
+    /// Executes a single opcode as if it was at PC, PC has to be past it already
+    pub fn execute(&mut self, opcode: u16) {
+        self.execute_decoded(decode(opcode));
+    }
+
+    pub(crate) fn execute_decoded(&mut self, instruction: Instruction) {
+        let opcode = instruction.opcode;
+
+        // Big endian, the nibbles are read straight out of the opcode
+
         // Values
-        let addr: u16 = hex_to_int(&opcode_str[1..]);
-        let byte: u8 = hex_to_int(&opcode_str[2..]) as u8;
-        let nibble: u8 = hex_to_int(&opcode_str[3..]) as u8;
+        let addr: u16 = opcode & 0x0FFF;
+        let byte: u8 = (opcode & 0x00FF) as u8;
+        let nibble: u8 = (opcode & 0x000F) as u8;
 
         // Registers
-        let vx_addr: usize = hex_to_int(&opcode_str[1..2]) as usize;
-        let vy_addr: usize = hex_to_int(&opcode_str[2..3]) as usize;
+        let vx_addr: usize = ((opcode & 0x0F00) >> 8) as usize;
+        let vy_addr: usize = ((opcode & 0x00F0) >> 4) as usize;
         let vf_addr = 15;
         let vx = self.cpu.registers[vx_addr];
         let vy = self.cpu.registers[vy_addr];
         let vi = self.cpu.VI;
 
-        match &opcode_str[0..1] {
-            "0" => {
-                match opcode {
-                    0x00E0 => {
-                        // CLS
-                        println!("CLS");
-                        self.screen.clear();
-                    },
-                    0x00EE => {
-                        // RET
-                        self.cpu.PC = self.cpu.stack[self.cpu.SP as usize] as u16;
-                        if self.cpu.SP > 0 {
-                            self.cpu.SP -= 1;
-                        } 
-                        else {
-                            println!("Error: Invalid Return");
-                        }
-                    },
-                    _ => {
-                        // SYS
-                    }
+        match instruction.op {
+            Op::Cls => {
+                // CLS
+                trace!(self, "CLS");
+                self.screen.clear();
+            },
+            Op::Ret => {
+                // RET
+                trace!(self, "RET");
+                let addr = self.cpu.PC.wrapping_sub(2);
+                self.cpu.PC = self.cpu.stack[self.cpu.SP as usize];
+                if self.cpu.SP > 0 {
+                    self.cpu.SP -= 1;
+                }
+                else {
+                    self.warn(Warning::InvalidReturn { addr });
                 }
             },
-            "1" => {
+            Op::Sys => {
+                // SYS
+                trace!(self, "SYS {:#x}", addr);
+            },
+            Op::Jp => {
                 // JP addr
-                println!("JP {:#x}", addr);
+                trace!(self, "JP {:#x}", addr);
                 self.cpu.PC = addr;
             },
-            "2" => {
+            Op::Call => {
                 // CALL addr
-                println!("CALL {:#x}", addr);
+                trace!(self, "CALL {:#x}", addr);
 
-                self.cpu.SP += 1;
-                self.cpu.stack[self.cpu.SP as usize] = self.cpu.PC;
+                // The first entry of the stack is never used, SP points at the top
+                if self.cpu.SP as usize + 1 < self.cpu.stack.len() {
+                    self.cpu.SP += 1;
+                    self.cpu.stack[self.cpu.SP as usize] = self.cpu.PC;
+                }
+                else {
+                    self.warn(Warning::StackOverflow { addr: self.cpu.PC.wrapping_sub(2) });
+                }
                 self.cpu.PC = addr;
             },
-            "3" => {
+            Op::SeByte => {
                 // SE Vx, byte
-                println!("SE {:#x} {:#x}", vx, byte);
+                trace!(self, "SE {:#x} {:#x}", vx, byte);
                 if vx == byte {
-                    self.cpu.PC += 2;
+                    self.skip();
                 }
             },
-            "4" => {
+            Op::SneByte => {
                 // SNE Vx, byte
-                println!("SNE {:#x} {:#x}", vx, byte);
+                trace!(self, "SNE {:#x} {:#x}", vx, byte);
 
                 if vx != byte {
-                    self.cpu.PC += 2;
+                    self.skip();
                 }
             },
-            "5" => {
-                match &opcode_str[3..] {
-                    "0" => {
-                        // SE Vx, Vy
-                        println!("SE {:#x} {:#x}", vx, vy);
-                        if vx == vy {
-                            self.cpu.PC += 2;
-                        }
-                    },
-                    _ => {
-                        // Error
-                        println!("Unknown opcode {:#x}", opcode);
-                    }
+            Op::SeReg => {
+                // SE Vx, Vy
+                trace!(self, "SE {:#x} {:#x}", vx, vy);
+                if vx == vy {
+                    self.skip();
                 }
             },
-            "6" => {
+            Op::LdByte => {
                 // LD Vx, byte
-                println!("LD {:#x} {:#x}", vx, byte);
+                trace!(self, "LD {:#x} {:#x}", vx, byte);
                 self.cpu.registers[vx_addr] = byte;
             },
-            "7" => {
+            Op::AddByte => {
                 // ADD Vx, byte
-                println!("Add {:#x} {:#x}", vx, byte);
+                trace!(self, "Add {:#x} {:#x}", vx, byte);
 
-                // Measure against overflow
-                let temp: u16 = vx as u16 + byte as u16;
-                if temp >= (1 << 8) {
-                    self.cpu.registers[vx_addr] = temp as u8;
+                // Wraps around, VF is left alone
+                self.cpu.registers[vx_addr] = vx.wrapping_add(byte);
+            },
+            Op::LdReg => {
+                // LD Vx, Vy
+                trace!(self, "LD {:#x} {:#x}", vx, vy);
+                self.cpu.registers[vx_addr] = vy;
+            },
+            Op::Or => {
+                // OR Vx, Vy
+                trace!(self, "OR {:#x} {:#x}", vx, vy);
+                self.cpu.registers[vx_addr] = vx | vy;
+
+                if self.quirks.logic_resets_vf {
+                    self.cpu.registers[vf_addr] = 0;
                 }
-                else {
-                    // Here's the actual instruction
-                    self.cpu.registers[vx_addr] += byte;
+            },
+            Op::And => {
+                // AND Vx, Vy
+                trace!(self, "AND {:#x} {:#x}", vx, vy);
+                self.cpu.registers[vx_addr] = vx & vy;
+
+                if self.quirks.logic_resets_vf {
+                    self.cpu.registers[vf_addr] = 0;
                 }
             },
-            "8" => {
-                match &opcode_str[3..] {
-                    "0" => {
-                        // LD Vx, Vy
-                        println!("LD {:#x} {:#x}", vx, vy);
-                        self.cpu.registers[vx_addr] = vy;
-                    },
-                    "1" => {
-                        // OR Vx, Vy
-                        println!("OR {:#x} {:#x}", vx, vy);
-                        self.cpu.registers[vx_addr] = vx | vy;
-                    },
-                    "2" => {
-                        // AND Vx, Vy
-                        println!("AND {:#x} {:#x}", vx, vy);
-                        self.cpu.registers[vx_addr] = vx & vy;
-                    },
-                    "3" => {
-                        // XOR Vx, Vy
-                        println!("XOR {:#x} {:#x}", vx, vy);
-                        self.cpu.registers[vx_addr] = vx ^ vy;
-                    },
-                    "4" => {
-                        // ADD Vx, Vy, & carry
-                        println!("ADD {:#x} {:#x}", vx, vy);
-                        self.cpu.registers[vx_addr] = ((vx as u16) + (vy as u16)) as u8;
-                        self.cpu.registers[vf_addr] = 0;
-
-                        if ((vx as u16) + (vy as u16)) > 255 {
-                            self.cpu.registers[vf_addr] = 1;
-                        }
-                    },
-                    "5" => {
-                        // SUBN Vx, Vy, carry
-                        println!("SUB {:#x} {:#x}", vx, vy);
-                        if vx > vy {
-                            self.cpu.registers[vf_addr] = 1;
-                            self.cpu.registers[vx_addr] = vx - vy;
-                        }
-                        else {
-                            self.cpu.registers[vf_addr] = 0;
-                            self.cpu.registers[vx_addr] = vy - vx;
-                        }
-                    },
-                    "6" => {
-                        // SHR Vx, Vy
-                        println!("SHR {:#x} {:#x}", vx, vy);
-
-                        // If LSB is 1, then set VF to 1 otherwise 0
-                        if vx % 2 == 1 {
-                            self.cpu.registers[vf_addr] = 1;
-                        } 
-                        else {
-                            self.cpu.registers[vf_addr] = 0;
-                        }
-
-                        // Then divide vx by 2
-                        self.cpu.registers[vx_addr] = vx >> 1;
-                    },
-                    "7" => {
-                        // SUBN Vx, Vy, carry
-                        println!("SUBN {:#x} {:#x}", vx, vy);
-
-                        if vy > vx {
-                            self.cpu.registers[vf_addr] = 1;
-                            self.cpu.registers[vx_addr] = vy - vx;
-                        }
-                        else {
-                            self.cpu.registers[vf_addr] = 0;
-                            self.cpu.registers[vx_addr] = vx - vy;
-                        }
-                    },
-                    "E" | "e" => {
-                        // SHL Vx, Vy
-                        println!("SHL {:#x} {:#x}", vx, vy);
-
-                        // IF MSB of Vx is 1, then set VF to 1 otherwise 0
-                        let check_msb = vx & 128;
-
-                        if check_msb != 0 {
-                            self.cpu.registers[vf_addr] = 1;
-                        }
-                        else {
-                            self.cpu.registers[vf_addr] = 0;
-                        }
-
-                        // Then multiply vx by 2
-                        self.cpu.registers[vx_addr] = vx << 1;
-                    },
-                    _ => {
-                        // Error
-                        println!("Unknown opcode {:#x}", opcode);
-                    }
+            Op::Xor => {
+                // XOR Vx, Vy
+                trace!(self, "XOR {:#x} {:#x}", vx, vy);
+                self.cpu.registers[vx_addr] = vx ^ vy;
+
+                if self.quirks.logic_resets_vf {
+                    self.cpu.registers[vf_addr] = 0;
                 }
             },
-            "9" => {
-                match &opcode_str[3..] {
-                    "0" => {
-                        // SNE Vx, Vy
-                        println!("SNE {:#x} {:#x}", vx, vy);
+            Op::AddReg => {
+                // ADD Vx, Vy, & carry
+                trace!(self, "ADD {:#x} {:#x}", vx, vy);
+                self.cpu.registers[vx_addr] = ((vx as u16) + (vy as u16)) as u8;
+                self.cpu.registers[vf_addr] = 0;
+
+                if ((vx as u16) + (vy as u16)) > 255 {
+                    self.cpu.registers[vf_addr] = 1;
+                }
+            },
+            Op::Sub => {
+                // SUB Vx, Vy, carry
+                trace!(self, "SUB {:#x} {:#x}", vx, vy);
+
+                // VF is 1 unless there was a borrow. Like the other flags it is set
+                // last, so it wins when VX is VF.
+                self.cpu.registers[vx_addr] = vx.wrapping_sub(vy);
+                self.cpu.registers[vf_addr] = (vx >= vy) as u8;
+            },
+            Op::Shr => {
+                // SHR Vx, Vy
+                trace!(self, "SHR {:#x} {:#x}", vx, vy);
 
-                        if vx != vy {
-                            self.cpu.PC += 2;
-                        }
-                    },
-                    _ => {
-                        // Error
-                        println!("Unknown opcode {:#x}", opcode);
-                    }
+                // The original interpreter shifted Vy into Vx
+                let vx = if self.quirks.shift_uses_vy { vy } else { vx };
+
+                // Divide vx by 2, VF gets the bit that was shifted out
+                self.cpu.registers[vx_addr] = vx >> 1;
+                self.cpu.registers[vf_addr] = vx & 1;
+            },
+            Op::Subn => {
+                // SUBN Vx, Vy, carry
+                trace!(self, "SUBN {:#x} {:#x}", vx, vy);
+
+                self.cpu.registers[vx_addr] = vy.wrapping_sub(vx);
+                self.cpu.registers[vf_addr] = (vy >= vx) as u8;
+            },
+            Op::Shl => {
+                // SHL Vx, Vy
+                trace!(self, "SHL {:#x} {:#x}", vx, vy);
+
+                // The original interpreter shifted Vy into Vx
+                let vx = if self.quirks.shift_uses_vy { vy } else { vx };
+
+                // Multiply vx by 2, VF gets the bit that was shifted out
+                self.cpu.registers[vx_addr] = vx << 1;
+                self.cpu.registers[vf_addr] = vx >> 7;
+            },
+            Op::SneReg => {
+                // SNE Vx, Vy
+                trace!(self, "SNE {:#x} {:#x}", vx, vy);
+
+                if vx != vy {
+                    self.skip();
                 }
             },
-            "a" | "A" => {
+            Op::LdI => {
                 // LD I, addr
-                println!("LD {:#x} {:#x}", self.cpu.VI, addr);
+                trace!(self, "LD {:#x} {:#x}", self.cpu.VI, addr);
 
                 self.cpu.VI = addr;
             },
-            "b"| "B" => {
+            Op::JpV0 => {
                 // JP V0, addr
-                println!("JP {:#x} {:#x}", self.cpu.registers[0], addr);
+                trace!(self, "JP {:#x} {:#x}", self.cpu.registers[0], addr);
+
+                // SUPER-CHIP read this as BXNN and jumped to XNN + VX
+                let offset = if self.quirks.jump_uses_vx { vx } else { self.cpu.registers[0] };
 
-                self.cpu.PC = addr + (self.cpu.registers[0] as u16);
+                // Jumps past the end of memory wrap around
+                self.cpu.PC = (addr + offset as u16) % MEMORY_SIZE as u16;
             },
-            "c" | "C" => {
+            Op::Rnd => {
                 // RND Vx, byte
-                println!("RND {:#x} {:#x}", vx, byte);
+                trace!(self, "RND {:#x} {:#x}", vx, byte);
 
-                let val = thread_rng().gen::<u8>();
-                
-                self.cpu.registers[vx_addr] = val & byte;
+                let val = self.rng.next_u8();
 
-                // println!("{}", val & byte);
+                self.cpu.registers[vx_addr] = val & byte;
             },
-            "d" | "D" => {
+            Op::Drw => {
                 // DRW Vx, Vy, nibble
-                println!("DRW {:#x} {:#x} {:#x}", vx, vy, nibble);
-                let vf = self.screen.xor_sprite(&self.memory, vi as usize, (vx, vy), nibble);
+                trace!(self, "DRW {:#x} {:#x} {:#x}", vx, vy, nibble);
+                let vf = if self.check_bounds(vi as usize, nibble as usize) {
+                    self.screen.xor_sprite(&self.memory, vi as usize, (vx, vy), nibble, self.quirks.clip_sprites)
+                }
+                else {
+                    // The rows past the end of memory are empty
+                    let mut sprite = [0; 15];
+                    for (idx, row) in sprite.iter_mut().enumerate().take(nibble as usize) {
+                        *row = self.read(vi as usize + idx);
+                    }
+                    self.screen.xor_sprite(&sprite, 0, (vx, vy), nibble, self.quirks.clip_sprites)
+                };
                 self.cpu.registers[vf_addr] = vf;
             },
-            "e" | "E" => {
-                match &opcode_str[2..] {
-                    "9E" | "9e" => {
-                        // SKP Vx
-                        // Skip next instruction if key with the value of Vx is pressed.
-                        println!("SKP {:#x}", vx);
-
-                        let desired_key = self.get_mapped_key(vx);
-                        println!("keys: {}", desired_key);
-                        for event in key_event_triggered.iter() {
-                            match event {
-                                Event::KeyDown { keycode: Some(key), ..} => {
-                                    println!("keys: {} {}", desired_key, key);
-                                    if desired_key == *key {
-                                        self.cpu.PC += 2;
-                                    }
-                                },
-                                _ => {}
-                            }
-                        }
-                    },
-                    "A1" | "a1" => {
-                        // SKNP Vx
-                        println!("SKNP {:#x}", vx);
-
-                        let desired_key = self.get_mapped_key(vx);
-                        println!("keys: {}", desired_key);
-                        for event in key_event_triggered.iter() {
-                            match event {
-                                Event::KeyDown { keycode: Some(key), ..} => {
-                                    // println!("HURRAH!!");
-                                    if desired_key != *key {
-                                        self.cpu.PC += 2;
-                                    }
-                                },
-                                _ => {println!("{:#?}", event)}
-                            }
-                        }
-                    },
-                    _ => {
-                        // Error
-                        println!("Unknown opcode {:#x}", opcode);
-                    }
+            Op::Skp => {
+                // SKP Vx
+                // Skip next instruction if key with the value of Vx is pressed.
+                trace!(self, "SKP {:#x}", vx);
+
+                if self.keys.is_pressed(vx) {
+                    self.skip();
                 }
             },
-            "f" | "F" => {
-                match &opcode_str[2..] {
-                    "07" => {
-                        // LD Vx, DT
-                        println!("LD {:#x} {:#x}", vx, self.cpu.timer.DT);
-                        self.cpu.registers[vx_addr] = self.cpu.timer.DT;
-                        println!("DT: {}", self.cpu.timer.DT);
-                    },
-                    "0a" | "0A" => {
-                        // LD Vx, K
-                        println!("LD {:#x} {:#x}", vx, 0x100);
-
-                        let mut key: u8 = 16;
-
-                        'outer: while key == 16 {
-                            // Wait for a keypress
-                            self.event_pump.wait_event();
-
-                            let event = self.event_pump.poll_event();
-
-                            if event != None {
-                                match event.unwrap() {
-                                    Event::Quit {..} => {
-                                        // Trigger exit ------
-                                        self.running = false;
-                                        break 'outer;
-                                    },
-                                    Event::KeyDown {keycode: Some(keycode), ..} => {
-                                        // let keycode = keycode.unwrap();
-                                        println!("{}", keycode);
-
-                                        break 'outer;
-                                    },
-                                    Event::TextInput {text, ..} => {
-                                        println!("k: {}", text);
-                                        
-                                        if "0123456789".contains(&text) {
-                                            key = text.parse::<u8>().expect("Wrong key");
-                                        }
-                                        else if (text == "A") | (text == "a") {
-                                            key = 10;
-                                        }
-                                        else if (text == "B") | (text == "b") {
-                                            key = 11;
-                                        }
-                                        else if (text == "C") | (text == "c") {
-                                            key = 12;
-                                        }
-                                        else if (text == "D") | (text == "d") {
-                                            key = 13;
-                                        }
-                                        else if (text == "E") | (text == "e") {
-                                            key = 14; 
-                                        }
-                                        else if (text == "F") | (text == "f") {
-                                            key = 15; 
-                                        }
-                                        else {
-                                            continue;
-                                        }
-
-                                        break 'outer;
-                                    }
-                                    _ => {
-                                        //println!("{:#?}", event);
-                                    }
-                                }
-                            }
-                            
-                        }
-
-                        println!("{}", key);
-                        self.cpu.registers[vx_addr] = key;
-                    },
-                    "15" => {
-                        // LD DT, Vx
-                        println!("LD {:#x} {:#x}", self.cpu.timer.DT, vx);
-                        self.cpu.timer.DT = vx;
-                        println!("DT: {}", self.cpu.timer.DT);
-                    },
-                    "18" => {
-                        // LD ST, Vx
-                        println!("LD {:#x} {:#x}", self.cpu.timer.ST, vx);
-                        self.cpu.timer.ST = vx;
-                    },
-                    "1e" | "1E" => {
-                        // ADD I, Vx
-                        println!("Add {:#x} {:#x}", self.cpu.VI, vx);
-                        self.cpu.VI += vx as u16;
-                    },
-                    "29" => {
-                        // LD F, Vx
-                        // Set I = location of sprite for digit Vx.
-                        println!("LD {:#x} {:#x}", vx * 5, vx);
-
-                        // vx * 5 corresponds to the index of the fonts array
-                        // Since, each font has a length of 5 bytes, so each 
-                        // font is 5 bytes apart
-                        self.cpu.VI = (vx * 5) as u16;
-
-                        // println!("font: {}", self.cpu.VI);
-                        // println!("{} - {} - {}", self.cpu.registers[0], self.cpu.registers[1], self.cpu.registers[2]);
-                    },
-                    "33" => {
-                        // LD B, Vx
-                        println!("LD B {:#x}", vx_addr);
-                        // Get the value at that is stored in Vx
-                        let mut value: u8 = vx;
-                        println!("{}", value);
-
-                        // Store value in regiser I, I+1, I+2
-                        for idx in 0..3 {
-                            let digit = value % 10;
-                            
-                            self.write((vi + 2 - idx) as usize, digit);
-
-                            value = value / 10; 
-                        }
-                    },
-                    "55" => {
-                        // LD [I], Vx
-                        println!("LD [I] {:#x}", vx);
+            Op::Sknp => {
+                // SKNP Vx
+                trace!(self, "SKNP {:#x}", vx);
 
-                        for idx in 0..vx_addr {
-                            self.write(vi as usize + idx, self.cpu.registers[idx]);
-                        }
-                    },
-                    "65" => {
-                        // LD Vx, [I]
-                        println!("LD {:#x} [I]", vx_addr);
+                if !self.keys.is_pressed(vx) {
+                    self.skip();
+                }
+            },
+            Op::LdVxDt => {
+                // LD Vx, DT
+                trace!(self, "LD {:#x} {:#x}", vx, self.cpu.timer.DT);
+                self.cpu.registers[vx_addr] = self.cpu.timer.DT;
+            },
+            Op::LdVxK => {
+                // LD Vx, K
+                trace!(self, "LD {:#x} K", vx_addr);
+
+                // Wait for a key to be pressed and released again. Execution doesn't
+                // block, the instruction is simply repeated until the keypad reports
+                // a release, so the timers keep running in the meantime.
+                if !self.keys.waiting {
+                    self.keys.waiting = true;
+                    self.keys.released = None;
+                }
 
-                        for idx in 0..(vx_addr+1) {
-                            self.cpu.registers[idx] = self.read(vi as usize + idx);
-                        }
+                match self.keys.released.take() {
+                    Some(key) => {
+                        self.keys.waiting = false;
+                        self.cpu.registers[vx_addr] = key;
                     },
-                    _ => {
-                        // Error
-                        println!("Unknown opcode {:#x}", opcode);
+                    None => {
+                        self.cpu.PC = (self.cpu.PC + MEMORY_SIZE as u16 - 2) % MEMORY_SIZE as u16;
                     }
                 }
+            },
+            Op::LdDtVx => {
+                // LD DT, Vx
+                trace!(self, "LD {:#x} {:#x}", self.cpu.timer.DT, vx);
+                self.cpu.timer.DT = vx;
+            },
+            Op::LdStVx => {
+                // LD ST, Vx
+                trace!(self, "LD {:#x} {:#x}", self.cpu.timer.ST, vx);
+                self.cpu.timer.ST = vx;
+            },
+            Op::AddI => {
+                // ADD I, Vx
+                trace!(self, "Add {:#x} {:#x}", self.cpu.VI, vx);
+                self.cpu.VI = self.cpu.VI.wrapping_add(vx as u16);
+            },
+            Op::LdF => {
+                // LD F, Vx
+                // Set I = location of sprite for digit Vx.
+                trace!(self, "LD {:#x} {:#x}", (vx & 0xF) as u16 * 5, vx);
+
+                // vx * 5 corresponds to the index of the fonts array
+                // Since, each font has a length of 5 bytes, so each
+                // font is 5 bytes apart. Only the low nibble picks the digit.
+                self.cpu.VI = (vx & 0xF) as u16 * 5;
+            },
+            Op::LdB => {
+                // LD B, Vx
+                trace!(self, "LD B {:#x}", vx_addr);
+                // Get the value at that is stored in Vx
+                let mut value: u8 = vx;
+
+                self.check_bounds(vi as usize, 3);
+
+                // Store value in regiser I, I+1, I+2
+                for idx in 0..3 {
+                    let digit = value % 10;
+
+                    self.write(vi as usize + 2 - idx, digit);
+
+                    value /= 10;
+                }
+            },
+            Op::Store => {
+                // LD [I], Vx
+                trace!(self, "LD [I] {:#x}", vx_addr);
+
+                self.check_bounds(vi as usize, vx_addr + 1);
+
+                for idx in 0..(vx_addr+1) {
+                    self.write(vi as usize + idx, self.cpu.registers[idx]);
+                }
+
+                if self.quirks.load_store_increments_i {
+                    self.cpu.VI = vi.wrapping_add(vx_addr as u16 + 1);
+                }
+            },
+            Op::Load => {
+                // LD Vx, [I]
+                trace!(self, "LD {:#x} [I]", vx_addr);
+
+                self.check_bounds(vi as usize, vx_addr + 1);
+
+                for idx in 0..(vx_addr+1) {
+                    self.cpu.registers[idx] = self.read(vi as usize + idx);
+                }
+
+                if self.quirks.load_store_increments_i {
+                    self.cpu.VI = vi.wrapping_add(vx_addr as u16 + 1);
+                }
+            },
+            Op::Unknown => {
+                // Error
+                self.unknown_opcode(opcode);
             }
-            _ => {
-                println!("Unknown opcode {:#x}", opcode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::quirks::Quirks;
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut emulator = Emulator::builder().seed(0).build();
+        #[cfg(feature = "std")]
+        emulator.set_print_warnings(false);
+        emulator.load_rom(program).unwrap();
+
+        emulator
+    }
+
+    fn with_quirks(quirks: Quirks) -> Emulator {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.set_quirks(quirks);
+
+        emulator
+    }
+
+    #[test]
+    fn decodes_every_operation() {
+        let ops = [
+            (0x00E0, Op::Cls), (0x00EE, Op::Ret), (0x0123, Op::Sys), (0x1234, Op::Jp),
+            (0x2345, Op::Call), (0x3456, Op::SeByte), (0x4567, Op::SneByte), (0x5670, Op::SeReg),
+            (0x6789, Op::LdByte), (0x789A, Op::AddByte), (0x8120, Op::LdReg), (0x8121, Op::Or),
+            (0x8122, Op::And), (0x8123, Op::Xor), (0x8124, Op::AddReg), (0x8125, Op::Sub),
+            (0x8126, Op::Shr), (0x8127, Op::Subn), (0x812E, Op::Shl), (0x9120, Op::SneReg),
+            (0xA123, Op::LdI), (0xB123, Op::JpV0), (0xC1FF, Op::Rnd), (0xD125, Op::Drw),
+            (0xE19E, Op::Skp), (0xE1A1, Op::Sknp), (0xF107, Op::LdVxDt), (0xF10A, Op::LdVxK),
+            (0xF115, Op::LdDtVx), (0xF118, Op::LdStVx), (0xF11E, Op::AddI), (0xF129, Op::LdF),
+            (0xF133, Op::LdB), (0xF155, Op::Store), (0xF165, Op::Load),
+            (0x5121, Op::Unknown), (0x8128, Op::Unknown), (0x9121, Op::Unknown),
+            (0xE100, Op::Unknown), (0xF1FF, Op::Unknown),
+        ];
+
+        for (opcode, op) in ops {
+            assert_eq!(decode(opcode), Instruction { op, opcode }, "{:#06x}", opcode);
+        }
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        // CALL #206; RET; ...; RET
+        let mut emulator = emulator(&[0x22, 0x06, 0x00, 0xEE, 0x00, 0x00, 0x00, 0xEE]);
+
+        emulator.step();
+        assert_eq!((emulator.cpu.PC, emulator.cpu.SP, emulator.cpu.stack[1]), (0x206, 1, 0x202));
+
+        emulator.step();
+        assert_eq!((emulator.cpu.PC, emulator.cpu.SP), (0x202, 0));
+        assert_eq!(emulator.next_warning(), None);
+
+        // The stack is empty now
+        emulator.step();
+        assert_eq!(emulator.next_warning(), Some(Warning::InvalidReturn { addr: 0x202 }));
+    }
+
+    #[test]
+    fn calls_past_the_top_of_the_stack() {
+        // CALL #200
+        let mut emulator = emulator(&[0x22, 0x00]);
+
+        for _ in 0..15 {
+            emulator.step();
+        }
+        assert_eq!((emulator.cpu.SP, emulator.next_warning()), (15, None));
+
+        emulator.step();
+        assert_eq!(emulator.next_warning(), Some(Warning::StackOverflow { addr: 0x200 }));
+    }
+
+    #[test]
+    fn jumps_and_skips() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.cpu.registers[1] = 0x42;
+        emulator.cpu.registers[2] = 0x42;
+
+        let skips = [(0x3142, true), (0x3143, false), (0x4142, false), (0x4143, true),
+            (0x5120, true), (0x5130, false), (0x9120, false), (0x9130, true)];
+        for (opcode, skipped) in skips {
+            emulator.cpu.PC = 0x300;
+            emulator.execute(opcode);
+            assert_eq!(emulator.cpu.PC, if skipped { 0x302 } else { 0x300 }, "{:#06x}", opcode);
+        }
+
+        emulator.execute(0x1ABC);
+        assert_eq!(emulator.cpu.PC, 0xABC);
+
+        emulator.cpu.registers[0] = 4;
+        emulator.execute(0xB300);
+        assert_eq!(emulator.cpu.PC, 0x304);
+    }
+
+    #[test]
+    fn arithmetic_and_logic() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        let mut run = |opcode: u16, vx: u8, vy: u8| {
+            emulator.cpu.registers[0xF] = 0xAA;
+            emulator.cpu.registers[1] = vx;
+            emulator.cpu.registers[2] = vy;
+            emulator.execute(opcode);
+            (emulator.cpu.registers[1], emulator.cpu.registers[0xF])
+        };
+
+        assert_eq!(run(0x6107, 0, 0), (0x07, 0xAA));
+        assert_eq!(run(0x7102, 0xFF, 0), (0x01, 0xAA));
+        assert_eq!(run(0x8120, 1, 9), (9, 0xAA));
+        assert_eq!(run(0x8121, 0b1100, 0b1010), (0b1110, 0xAA));
+        assert_eq!(run(0x8122, 0b1100, 0b1010), (0b1000, 0xAA));
+        assert_eq!(run(0x8123, 0b1100, 0b1010), (0b0110, 0xAA));
+        assert_eq!(run(0x8124, 0xF0, 0x20), (0x10, 1));
+        assert_eq!(run(0x8124, 0x10, 0x20), (0x30, 0));
+        assert_eq!(run(0x8125, 7, 5), (2, 1));
+        assert_eq!(run(0x8125, 7, 7), (0, 1));
+        assert_eq!(run(0x8125, 5, 7), (0xFE, 0));
+        assert_eq!(run(0x8127, 5, 7), (2, 1));
+        assert_eq!(run(0x8127, 7, 7), (0, 1));
+        assert_eq!(run(0x8127, 7, 5), (0xFE, 0));
+        assert_eq!(run(0x8126, 0b101, 0), (0b10, 1));
+        assert_eq!(run(0x812E, 0x81, 0), (0x02, 1));
+        assert_eq!(run(0x812E, 0x01, 0), (0x02, 0));
+    }
+
+    #[test]
+    fn flags_win_over_results_in_vf() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        let mut run = |opcode: u16, vf: u8, v1: u8| {
+            emulator.cpu.registers[0xF] = vf;
+            emulator.cpu.registers[1] = v1;
+            emulator.execute(opcode);
+            (emulator.cpu.registers[1], emulator.cpu.registers[0xF])
+        };
+
+        // VF as Vx, the result is thrown away and only the flag is left
+        assert_eq!(run(0x8F14, 0xFF, 2), (2, 1));
+        assert_eq!(run(0x8F14, 3, 2), (2, 0));
+        assert_eq!(run(0x8F15, 3, 5), (5, 0));
+        assert_eq!(run(0x8F15, 5, 3), (3, 1));
+        assert_eq!(run(0x8F17, 3, 5), (5, 1));
+        assert_eq!(run(0x8F17, 5, 3), (3, 0));
+        assert_eq!(run(0x8FF6, 0b10, 0), (0, 0));
+        assert_eq!(run(0x8FF6, 0b01, 0), (0, 1));
+        assert_eq!(run(0x8FFE, 0x80, 0), (0, 1));
+        assert_eq!(run(0x8FFE, 0x01, 0), (0, 0));
+
+        // VF as Vy, it is read before the flag is written
+        assert_eq!(run(0x81F4, 0x20, 0xF0), (0x10, 1));
+        assert_eq!(run(0x81F5, 3, 5), (2, 1));
+        assert_eq!(run(0x81F7, 3, 5), (0xFE, 0));
+    }
+
+    #[test]
+    fn random_numbers_are_masked() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+
+        for _ in 0..32 {
+            emulator.execute(0xC10F);
+            assert_eq!(emulator.cpu.registers[1] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn index_and_memory() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+
+        emulator.execute(0xA300);
+        emulator.cpu.registers[1] = 0x10;
+        emulator.execute(0xF11E);
+        assert_eq!(emulator.cpu.VI, 0x310);
+
+        emulator.cpu.registers[1] = 0x1A;
+        emulator.execute(0xF129);
+        assert_eq!(emulator.cpu.VI, 0xA * 5);
+
+        emulator.execute(0xA300);
+        emulator.cpu.registers[1] = 254;
+        emulator.execute(0xF133);
+        assert_eq!(emulator.memory[0x300..0x303], [2, 5, 4]);
+
+        emulator.cpu.registers[..3].copy_from_slice(&[7, 8, 9]);
+        emulator.execute(0xF255);
+        emulator.cpu.registers[..3].copy_from_slice(&[0, 0, 0]);
+        emulator.execute(0xF165);
+        assert_eq!(emulator.cpu.registers[..3], [7, 8, 0]);
+        assert_eq!(emulator.cpu.VI, 0x300);
+    }
+
+    #[test]
+    fn accesses_past_the_end_of_memory_warn() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.cpu.PC = 0x202;
+
+        emulator.execute(0xAFFF);
+        emulator.execute(0xF255);
+        assert_eq!(emulator.next_warning(), Some(Warning::OutOfBounds { addr: 0x200, target: MEMORY_SIZE }));
+        assert_eq!(emulator.memory[0xFFF], 0);
+    }
+
+    #[test]
+    fn timers() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.cpu.registers[1] = 2;
+
+        emulator.execute(0xF115);
+        emulator.execute(0xF118);
+        assert!(emulator.sound_active());
+
+        emulator.tick_timers();
+        emulator.execute(0xF207);
+        assert_eq!(emulator.cpu.registers[2], 1);
+
+        emulator.tick_timers();
+        emulator.tick_timers();
+        assert!(!emulator.sound_active());
+        assert_eq!(emulator.cpu.timer.DT, 0);
+    }
+
+    #[test]
+    fn drawing_and_clearing() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+
+        // The font's 0 at (0, 0)
+        emulator.execute(0xA000);
+        emulator.execute(0xD005);
+        assert_eq!(emulator.cpu.registers[0xF], 0);
+        assert_eq!(emulator.framebuffer().pixel_repr[0][..5], [1, 1, 1, 1, 0]);
+        assert_eq!(emulator.framebuffer().pixel_repr[1][..5], [1, 0, 0, 1, 0]);
+
+        // Drawing it again erases it
+        emulator.execute(0xD005);
+        assert_eq!(emulator.cpu.registers[0xF], 1);
+        assert_eq!(emulator.framebuffer().pixel_repr[0][..5], [0, 0, 0, 0, 0]);
+
+        emulator.execute(0xD005);
+        emulator.execute(0x00E0);
+        assert!(emulator.framebuffer().pixel_repr.iter().all(|row| row.iter().all(|pixel| *pixel == 0)));
+    }
+
+    #[test]
+    fn keys() {
+        // LD V1, K
+        let mut emulator = emulator(&[0xF1, 0x0A, 0x12, 0x02]);
+        emulator.cpu.registers[2] = 5;
+
+        emulator.execute(0xE29E);
+        emulator.execute(0xE2A1);
+        assert_eq!(emulator.cpu.PC, 0x202);
+
+        // Waits for a key to be pressed and released
+        emulator.cpu.PC = 0x200;
+        emulator.step();
+        emulator.set_key(5, true);
+        emulator.step();
+        assert_eq!(emulator.cpu.PC, 0x200);
+
+        emulator.execute(0xE29E);
+        assert_eq!(emulator.cpu.PC, 0x202);
+        emulator.cpu.PC = 0x200;
+
+        emulator.set_key(5, false);
+        emulator.step();
+        assert_eq!((emulator.cpu.PC, emulator.cpu.registers[1]), (0x202, 5));
+    }
+
+    #[test]
+    fn unknown_opcodes_warn() {
+        let mut emulator = emulator(&[0xE1, 0x00]);
+
+        emulator.step();
+        assert_eq!(emulator.next_warning(), Some(Warning::UnknownOpcode { opcode: 0xE100, addr: 0x200 }));
+        assert_eq!(emulator.cpu.PC, 0x202);
+    }
+
+    #[test]
+    fn shift_quirk() {
+        for (shift_uses_vy, result) in [(false, 0x08), (true, 0x02)] {
+            let mut emulator = with_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+            emulator.cpu.registers[1] = 0x10;
+            emulator.cpu.registers[2] = 0x04;
+
+            emulator.execute(0x8126);
+            assert_eq!(emulator.cpu.registers[1], result);
+        }
+    }
+
+    #[test]
+    fn load_store_quirk() {
+        for (load_store_increments_i, i) in [(false, 0x300), (true, 0x303)] {
+            let mut emulator = with_quirks(Quirks { load_store_increments_i, ..Quirks::default() });
+
+            emulator.execute(0xA300);
+            emulator.execute(0xF255);
+            assert_eq!(emulator.cpu.VI, i);
+
+            emulator.execute(0xA300);
+            emulator.execute(0xF265);
+            assert_eq!(emulator.cpu.VI, i);
+        }
+    }
+
+    #[test]
+    fn jump_quirk() {
+        for (jump_uses_vx, pc) in [(false, 0x321), (true, 0x322)] {
+            let mut emulator = with_quirks(Quirks { jump_uses_vx, ..Quirks::default() });
+            emulator.cpu.registers[0] = 1;
+            emulator.cpu.registers[3] = 2;
+
+            emulator.execute(0xB320);
+            assert_eq!(emulator.cpu.PC, pc);
+        }
+    }
+
+    #[test]
+    fn logic_quirk() {
+        for (logic_resets_vf, vf) in [(false, 7), (true, 0)] {
+            let mut emulator = with_quirks(Quirks { logic_resets_vf, ..Quirks::default() });
+
+            for opcode in [0x8121, 0x8122, 0x8123] {
+                emulator.cpu.registers[0xF] = 7;
+                emulator.execute(opcode);
+                assert_eq!(emulator.cpu.registers[0xF], vf);
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn clip_quirk() {
+        for (clip_sprites, wrapped) in [(false, 1), (true, 0)] {
+            let mut emulator = with_quirks(Quirks { clip_sprites, ..Quirks::default() });
+            emulator.memory[0x300] = 0xFF;
+            emulator.cpu.registers[1] = 60;
+
+            // An 8 pixel row at x = 60
+            emulator.execute(0xA300);
+            emulator.execute(0xD121);
+            assert_eq!(emulator.framebuffer().pixel_repr[0][63], 1);
+            assert_eq!(emulator.framebuffer().pixel_repr[0][0], wrapped);
+        }
+
+        // Sprites drawn past the screen wrap around either way
+        let mut emulator = with_quirks(Quirks { clip_sprites: true, ..Quirks::default() });
+        emulator.memory[0x300] = 0x80;
+        emulator.cpu.registers[1] = 64 + 2;
+        emulator.execute(0xA300);
+        emulator.execute(0xD121);
+        assert_eq!(emulator.framebuffer().pixel_repr[0][2], 1);
+    }
+
+    #[test]
+    fn display_wait_quirk() {
+        for (display_wait, executed) in [(false, 10), (true, 1)] {
+            // DRW V0, V0, 1; JP #200
+            let mut emulator = emulator(&[0xD0, 0x01, 0x12, 0x00]);
+            emulator.set_quirks(Quirks { display_wait, ..Quirks::default() });
+
+            emulator.run_frame();
+            assert_eq!(emulator.instructions_executed(), executed);
+        }
+    }
+}
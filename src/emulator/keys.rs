@@ -0,0 +1,43 @@
+//! The keypad of the chip8.
+
+/// The hexadecimal keypad of the chip8, keys 0x0 - 0xF. The frontend reports presses and
+/// releases here, the interpreter only ever looks at this state.
+#[derive(Clone, Default)]
+pub struct Keypad {
+    /// Whether each key is held down
+    pub pressed: [bool; 16],
+    /// Set by FX0A while it waits for a key, it completes once a key is released
+    pub waiting: bool,
+    /// The last key released while FX0A was waiting
+    pub released: Option<u8>,
+}
+
+impl Keypad {
+    /// A keypad with no key pressed
+    pub fn new() -> Keypad {
+        Keypad::default()
+    }
+
+    /// Presses or releases a key, only the low 4 bits of `key` count
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        let key = (key & 0xF) as usize;
+
+        if self.waiting && self.pressed[key] && !pressed {
+            self.released = Some(key as u8);
+        }
+
+        self.pressed[key] = pressed;
+    }
+
+    /// Whether a key is held down, only the low 4 bits of `key` count
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.pressed[(key & 0xF) as usize]
+    }
+
+    /// Releases every key, as if the player let go of all of them
+    pub fn release_all(&mut self) {
+        for key in 0..16 {
+            self.set_key(key, false);
+        }
+    }
+}
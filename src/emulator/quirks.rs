@@ -0,0 +1,123 @@
+//! The behaviours chip8 interpreters disagree on, and the machines they come from.
+
+/// The chip8 has been reimplemented many times since the COSMAC VIP and the
+/// interpreters disagree on a handful of instructions. Games written for one of
+/// them often misbehave on the others, so these behaviours are configurable.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register that was stored/loaded
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    /// Sprites drawn partially off screen are cut off instead of wrapping around
+    pub clip_sprites: bool,
+    /// DXYN waits for the next frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+}
+
+/// The machines a ROM can be written for
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Platform {
+    /// The original instruction set of the COSMAC VIP
+    Chip8,
+    /// SUPER-CHIP, the HP48 extensions: high resolution, scrolling, big sprites
+    SuperChip,
+    /// XO-CHIP, Octo's extensions: more memory, colors and audio
+    XoChip,
+}
+
+/// The names accepted by Quirks::preset, in the order they are listed in the help
+pub const PRESET_NAMES: [&str; 4] = ["chip8", "vip", "schip", "xochip"];
+
+impl Quirks {
+    /// Looks a preset up by name:
+    ///  - chip8: The behaviour most modern games expect, and what this emulator always did
+    ///  - vip: The original COSMAC VIP interpreter
+    ///  - schip: SUPER-CHIP 1.1 on the HP48
+    ///  - xochip: XO-CHIP as implemented by Octo
+    pub fn preset(name: &str) -> Option<Quirks> {
+        let is = |names: &[&str]| names.iter().any(|candidate| candidate.eq_ignore_ascii_case(name));
+
+        let quirks = match () {
+            _ if is(&["chip8", "chip-8", "modern"]) => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                clip_sprites: false,
+                display_wait: false,
+            },
+            _ if is(&["vip", "cosmac"]) => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                clip_sprites: true,
+                display_wait: true,
+            },
+            _ if is(&["schip", "superchip"]) => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            _ if is(&["xochip", "xo-chip", "octo"]) => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                clip_sprites: false,
+                display_wait: false,
+            },
+            _ => return None,
+        };
+
+        Some(quirks)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Platform::Chip8.default_quirks()
+    }
+}
+
+impl Platform {
+    /// Looks a platform up by name, `chip8`, `schip` or `xochip` and a few spellings of them
+    pub fn from_name(name: &str) -> Option<Platform> {
+        let is = |names: &[&str]| names.iter().any(|candidate| candidate.eq_ignore_ascii_case(name));
+
+        if is(&["chip8", "chip-8"]) {
+            Some(Platform::Chip8)
+        }
+        else if is(&["schip", "superchip", "super-chip"]) {
+            Some(Platform::SuperChip)
+        }
+        else if is(&["xochip", "xo-chip"]) {
+            Some(Platform::XoChip)
+        }
+        else {
+            None
+        }
+    }
+
+    /// The short name `from_name` and `Quirks::preset` accept
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    /// The quirks games written for this platform usually expect
+    pub fn default_quirks(&self) -> Quirks {
+        Quirks::preset(self.name()).unwrap()
+    }
+}
@@ -0,0 +1,255 @@
+use crate::emulator::{Emulator, RandomSource, MEMORY_SIZE};
+use crate::emulator::error::Error;
+use crate::emulator::framebuffer::{PIXELS_ALONG_X, PIXELS_ALONG_Y};
+
+// Save states are a flat binary dump of the machine:
+//
+//   "C8ST" version(1)
+//   V0-VF(16) I(2) stack(16 * 2) DT(1) ST(1) PC(2) SP(1)
+//   program size(4) memory size(2) memory(n) screen(64 * 32)
+//
+// Multi-byte values are big endian. The quirks and the speed aren't part of the state,
+// they are configuration and stay whatever they are when a state is loaded.
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 1;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.pos + len > self.data.len() {
+            return Err(Error::TruncatedState);
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// The size of a save state in bytes
+pub const STATE_SIZE: usize = 4 + 1 + 16 + 2 + 16 * 2 + 1 + 1 + 2 + 1 + 4 + 2 + MEMORY_SIZE
+    + (PIXELS_ALONG_X * PIXELS_ALONG_Y) as usize;
+
+struct Writer<'a> {
+    data: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+}
+
+impl<R: RandomSource> Emulator<R> {
+    /// Captures the whole machine (CPU, memory and display) so it can be restored later
+    #[cfg(feature = "std")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = vec![0; STATE_SIZE];
+        self.save_state_into(&mut data).expect("the buffer holds a state");
+
+        data
+    }
+
+    /// Like save_state, but writes into the first STATE_SIZE bytes of `data` and returns
+    /// how many that were
+    pub fn save_state_into(&self, data: &mut [u8]) -> Result<usize, Error> {
+        if data.len() < STATE_SIZE {
+            return Err(Error::BufferTooSmall(data.len()));
+        }
+
+        let mut writer = Writer { data: &mut data[..STATE_SIZE], pos: 0 };
+
+        writer.bytes(MAGIC);
+        writer.bytes(&[VERSION]);
+
+        writer.bytes(&self.cpu.registers);
+        writer.bytes(&self.cpu.VI.to_be_bytes());
+        for entry in self.cpu.stack.iter() {
+            writer.bytes(&entry.to_be_bytes());
+        }
+        writer.bytes(&[self.cpu.timer.DT, self.cpu.timer.ST]);
+        writer.bytes(&self.cpu.PC.to_be_bytes());
+        writer.bytes(&[self.cpu.SP]);
+
+        writer.bytes(&(self.program_size as u32).to_be_bytes());
+        writer.bytes(&(self.memory.len() as u16).to_be_bytes());
+        writer.bytes(&self.memory);
+
+        for row in self.screen.pixel_repr.iter() {
+            writer.bytes(row);
+        }
+
+        Ok(STATE_SIZE)
+    }
+
+    /// Restores a state written by save_state. The emulator is left untouched if the
+    /// state can't be read.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(Error::NotASaveState);
+        }
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedStateVersion(version));
+        }
+
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.bytes(16)?);
+        let vi = reader.u16()?;
+        let mut stack = [0; 16];
+        for entry in stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+        let pc = reader.u16()?;
+        let sp = reader.u8()?;
+
+        // The interpreter relies on these being in range
+        if pc as usize >= MEMORY_SIZE || sp as usize >= stack.len()
+            || stack.iter().any(|entry| *entry as usize >= MEMORY_SIZE) {
+            return Err(Error::InvalidState);
+        }
+
+        let program_size = reader.u32()? as usize;
+        let memory_size = reader.u16()? as usize;
+        if memory_size != self.memory.len() {
+            return Err(Error::StateMemoryMismatch(memory_size));
+        }
+        let memory = reader.bytes(memory_size)?;
+        let screen = reader.bytes((PIXELS_ALONG_X * PIXELS_ALONG_Y) as usize)?;
+
+        // Pixels are either on or off, drawing XORs them with 1
+        if screen.iter().any(|pixel| *pixel > 1) {
+            return Err(Error::InvalidState);
+        }
+
+        self.cpu.registers = registers;
+        self.cpu.VI = vi;
+        self.cpu.stack = stack;
+        self.cpu.timer.DT = dt;
+        self.cpu.timer.ST = st;
+        self.cpu.PC = pc;
+        self.cpu.SP = sp;
+        self.program_size = program_size;
+        self.memory.copy_from_slice(memory);
+        self.forget_decoded();
+
+        for (row, saved_row) in self.screen.pixel_repr.iter_mut().zip(screen.chunks(PIXELS_ALONG_X as usize)) {
+            row.copy_from_slice(saved_row);
+        }
+        self.screen.dirty = true;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Where the fields are in a state, see the layout at the top
+    const PC: usize = 57;
+    const SP: usize = 59;
+    const SCREEN: usize = STATE_SIZE - (PIXELS_ALONG_X * PIXELS_ALONG_Y) as usize;
+
+    fn running() -> Emulator {
+        // LD V0, #0A; LD F, V0; CALL #208; ...; DRW V0, V0, 5; LD DT, V0; JP #20C
+        let mut emulator = Emulator::builder().seed(0).build();
+        emulator.load_rom(&[0x60, 0x0A, 0xF0, 0x29, 0x22, 0x08, 0x00, 0x00,
+            0xD0, 0x05, 0xF0, 0x15, 0x12, 0x0C]).unwrap();
+        emulator.run_frame();
+
+        emulator
+    }
+
+    fn state(emulator: &Emulator) -> [u8; STATE_SIZE] {
+        let mut data = [0; STATE_SIZE];
+        assert_eq!(emulator.save_state_into(&mut data), Ok(STATE_SIZE));
+
+        data
+    }
+
+    #[test]
+    fn states_round_trip() {
+        let saved = running();
+        let data = state(&saved);
+
+        let mut emulator = Emulator::new();
+        emulator.load_state(&data).unwrap();
+
+        assert_eq!(emulator.cpu.registers, saved.cpu.registers);
+        assert_eq!((emulator.cpu.VI, emulator.cpu.PC, emulator.cpu.SP), (saved.cpu.VI, saved.cpu.PC, saved.cpu.SP));
+        assert_eq!(emulator.cpu.stack, saved.cpu.stack);
+        assert_eq!(emulator.cpu.timer.DT, saved.cpu.timer.DT);
+        assert_eq!(emulator.memory, saved.memory);
+        assert_eq!(emulator.screen.pixel_repr, saved.screen.pixel_repr);
+        assert_eq!(state(&emulator), data);
+    }
+
+    #[test]
+    fn invalid_states_are_rejected() {
+        let data = state(&running());
+        let check = |change: &dyn Fn(&mut [u8; STATE_SIZE]), error: Error| {
+            let mut broken = data;
+            change(&mut broken);
+
+            let mut emulator = Emulator::new();
+            assert_eq!(emulator.load_state(&broken), Err(error));
+            // Nothing was loaded
+            assert_eq!(emulator.cpu.PC, 0x200);
+        };
+
+        check(&|data| data[0] = b'X', Error::NotASaveState);
+        check(&|data| data[4] = 2, Error::UnsupportedStateVersion(2));
+        check(&|data| data[PC..PC + 2].copy_from_slice(&0x1000u16.to_be_bytes()), Error::InvalidState);
+        check(&|data| data[SP] = 16, Error::InvalidState);
+        check(&|data| data[SP + 1 + 4] = 0x20, Error::StateMemoryMismatch(0x2000));
+        check(&|data| data[SCREEN + 100] = 2, Error::InvalidState);
+
+        let mut emulator = Emulator::new();
+        assert_eq!(emulator.load_state(&data[..STATE_SIZE - 1]), Err(Error::TruncatedState));
+        assert_eq!(emulator.load_state(&data[..3]), Err(Error::TruncatedState));
+    }
+
+    #[test]
+    fn states_only_go_into_buffers_that_hold_them() {
+        let emulator = running();
+
+        let mut short = [0xAA; STATE_SIZE - 1];
+        assert_eq!(emulator.save_state_into(&mut short), Err(Error::BufferTooSmall(STATE_SIZE - 1)));
+        assert!(short.iter().all(|byte| *byte == 0xAA));
+
+        // Only the start of a larger buffer is written
+        let mut long = [0xAA; STATE_SIZE + 2];
+        assert_eq!(emulator.save_state_into(&mut long), Ok(STATE_SIZE));
+        assert_eq!(long[..STATE_SIZE], state(&emulator));
+        assert_eq!(long[STATE_SIZE..], [0xAA, 0xAA]);
+    }
+}
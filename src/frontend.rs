@@ -0,0 +1,377 @@
+pub mod browser;
+pub mod display;
+pub mod filter;
+pub mod font;
+pub mod keymap;
+pub mod osd;
+pub mod palette;
+pub mod speed;
+pub mod watch;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::EventPump;
+
+use chip8::emulator::Emulator;
+use crate::rom::read_rom_file;
+use browser::Browser;
+use display::Display;
+use keymap::Keymap;
+use osd::Osd;
+use speed::Speed;
+use watch::Watcher;
+
+// The timers and the screen are updated at 60Hz
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Number of save state slots, selected with F6
+const STATE_SLOTS: u8 = 10;
+
+// How often a watched ROM is checked for changes, in frames
+const WATCH_INTERVAL: u64 = 30;
+
+// Why the frontend stopped running a ROM
+pub enum Exit {
+    Quit,
+    // A ROM file was dropped onto the window
+    Load(PathBuf),
+    // The ROM browser was asked for (F1)
+    Browse,
+}
+
+// The SDL window, keyboard and the loop driving the emulator in real time
+pub struct Frontend {
+    pub screen: Display,
+    pub event_pump: EventPump,
+    pub keymap: Keymap,
+    pub osd: Osd,
+    // Save states are written next to this file as <file>.state<slot>
+    pub state_base: Option<PathBuf>,
+    pub slot: u8,
+    // Reload the ROM (state_base) whenever it changes on disk
+    pub watch: bool,
+    pub speed: Speed,
+    // Cleared to stop running the current ROM
+    running: bool,
+    // Instructions executed in the current frame, when stepping through single ones
+    cycle: u32,
+    watcher: Option<Watcher>,
+    exit: Exit,
+}
+
+impl Frontend {
+    pub fn new(width: u32, height: u32) -> Frontend {
+        let screen = Display::new(width, height);
+
+        Frontend {
+            event_pump: screen.sdl_context.event_pump()
+                .expect("Could not get the SDL event pump."),
+            screen,
+            keymap: Keymap::default(),
+            osd: Osd::new(),
+            state_base: None,
+            slot: 0,
+            watch: false,
+            speed: Speed::new(),
+            running: false,
+            cycle: 0,
+            watcher: None,
+            exit: Exit::Quit,
+        }
+    }
+
+    // Runs the emulator until the window is closed, for the given number of frames or
+    // until another ROM is asked for
+    pub fn run(&mut self, emulator: &mut Emulator, frames: Option<u64>) -> Exit {
+        let mut frame_count: u64 = 0;
+        let mut ticks: u64 = 0;
+        self.exit = Exit::Quit;
+        self.cycle = 0;
+
+        self.running = true;
+        self.watcher = self.state_base.as_deref().map(Watcher::new);
+
+        while self.running && frames.is_none_or(|frames| frame_count < frames) {
+            let frame_start = Instant::now();
+            ticks += 1;
+
+            self.handle_events(emulator);
+
+            if self.watch && ticks.is_multiple_of(WATCH_INTERVAL) {
+                self.reload_if_changed(emulator);
+            }
+
+            // Every frame still ticks the timers once, so the game itself doesn't notice
+            // that it runs faster or slower
+            for _ in 0..self.speed.frames_to_run() {
+                if !self.running || frames.is_some_and(|frames| frame_count >= frames) {
+                    break;
+                }
+
+                self.run_frame(emulator);
+                frame_count += 1;
+
+                if frame_start.elapsed() >= FRAME_DURATION {
+                    break;
+                }
+            }
+
+            for warning in emulator.take_warnings() {
+                self.osd.message(warning.to_string());
+            }
+            self.osd.update(emulator.instructions_executed());
+
+            // Render Canvas (only uploads the framebuffer if it changed)
+            let overlay = self.osd.lines(&self.status());
+            self.screen.render(emulator.framebuffer_mut(), &overlay);
+
+            // Sleep for whatever is left of this frame
+            let frame_duration = self.speed.frame_duration();
+
+            let elapsed = frame_start.elapsed();
+            if elapsed < frame_duration {
+                ::std::thread::sleep(frame_duration - elapsed);
+            }
+        }
+
+        std::mem::replace(&mut self.exit, Exit::Quit)
+    }
+
+    // Runs the rest of the current frame, which is a whole one unless single
+    // instructions were stepped through while paused
+    fn run_frame(&mut self, emulator: &mut Emulator) {
+        if self.cycle == 0 {
+            emulator.run_frame();
+            return;
+        }
+
+        while self.cycle != 0 {
+            self.step(emulator);
+        }
+    }
+
+    // Executes a single instruction, the timers tick once a frame worth of them ran
+    fn step(&mut self, emulator: &mut Emulator) {
+        emulator.step();
+
+        self.cycle += 1;
+        if self.cycle >= emulator.speed() {
+            self.cycle = 0;
+            emulator.tick_timers();
+        }
+    }
+
+    // Shows the ROMs in `dir` until one is picked (or dropped onto the window), None if
+    // the window was closed
+    pub fn browse(&mut self, dir: &Path) -> Option<PathBuf> {
+        let mut browser = match Browser::new(dir) {
+            Ok(browser) => browser,
+            Err(err) => {
+                eprintln!("{}", err);
+                return None;
+            }
+        };
+
+        self.screen.canvas.window_mut().set_title("Chip8 Emulator")
+            .expect("Could not set the window title.");
+        // The emulator's picture has to be drawn again once a ROM runs
+        self.screen.dirty = true;
+
+        loop {
+            browser.draw(&mut self.screen.canvas);
+
+            match self.event_pump.wait_event() {
+                Event::Quit {..} |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return None,
+                Event::DropFile { filename, .. } => return Some(PathBuf::from(filename)),
+                Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
+                    Keycode::Up => browser.move_selection(-1),
+                    Keycode::Down => browser.move_selection(1),
+                    Keycode::PageUp => browser.move_selection(-10),
+                    Keycode::PageDown => browser.move_selection(10),
+                    Keycode::Home => browser.move_selection(i32::MIN / 2),
+                    Keycode::End => browser.move_selection(i32::MAX / 2),
+                    Keycode::Backspace | Keycode::Left => browser.parent(),
+                    Keycode::Return | Keycode::Right => {
+                        if let Some(path) = browser.open() {
+                            return Some(path);
+                        }
+                    },
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_events(&mut self, emulator: &mut Emulator) {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+
+        for event in events {
+            match event {
+                Event::Quit {..} |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    self.running = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    self.exit = Exit::Browse;
+                    self.running = false;
+                },
+                Event::DropFile { filename, .. } => {
+                    self.exit = Exit::Load(PathBuf::from(filename));
+                    self.running = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    let name = self.screen.next_palette();
+                    self.notify(format!("Palette: {}", name));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                    let description = self.screen.next_filter();
+                    self.notify(format!("Flicker filter: {}", description));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => {
+                    let integer_scaling = !self.screen.integer_scaling;
+                    self.screen.set_integer_scaling(integer_scaling);
+                    self.notify(format!("Integer scaling: {}", if integer_scaling { "on" } else { "off" }));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                    match self.state_file() {
+                        Some(path) => match fs::write(&path, emulator.save_state()) {
+                            Ok(()) => self.notify(format!("State saved to {}", path.display())),
+                            Err(err) => self.warn(format!("Could not save state to {}: {}", path.display(), err)),
+                        },
+                        None => self.warn("Save states need a ROM file".to_string()),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                    self.slot = (self.slot + 1) % STATE_SLOTS;
+                    self.notify(format!("State slot: {}", self.slot));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    match self.state_file() {
+                        Some(path) => {
+                            let result = fs::read(&path)
+                                .map_err(|err| err.to_string())
+                                .and_then(|data| emulator.load_state(&data).map_err(|err| err.to_string()));
+
+                            match result {
+                                Ok(()) => self.notify(format!("State loaded from {}", path.display())),
+                                Err(err) => self.warn(format!("Could not load state from {}: {}", path.display(), err)),
+                            }
+                        },
+                        None => self.warn("Save states need a ROM file".to_string()),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    self.speed.paused = !self.speed.paused;
+                    self.notify(if self.speed.paused { "Paused" } else { "Resumed" }.to_string());
+                },
+                Event::KeyDown { keycode: Some(Keycode::F10), keymod, .. } => {
+                    // Shift steps a single instruction instead of a frame
+                    if !self.speed.paused {
+                        self.speed.paused = true;
+                        self.notify("Paused".to_string());
+                    }
+                    else if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        self.step(emulator);
+                    }
+                    else {
+                        self.run_frame(emulator);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    self.speed.slow_motion = !self.speed.slow_motion;
+                    self.notify(format!("Slow motion: {}", if self.speed.slow_motion { "on" } else { "off" }));
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    self.speed.fast_forward_held = true;
+                },
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
+                    self.speed.fast_forward_held = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    self.osd.visible = !self.osd.visible;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    self.screen.toggle_fullscreen();
+                },
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } |
+                Event::Window { win_event: WindowEvent::Exposed, .. } => {
+                    // The whole window has to be redrawn
+                    self.screen.dirty = true;
+                },
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                    // Key releases won't reach us anymore
+                    emulator.release_keys();
+                    self.speed.fast_forward_held = false;
+                },
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    if let Some(key) = self.keymap.lookup(keycode) {
+                        emulator.set_key(key, true);
+                    }
+                },
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(key) = self.keymap.lookup(keycode) {
+                        emulator.set_key(key, false);
+                    }
+                },
+                _ => {
+                }
+            }
+        }
+    }
+
+    // Shows a message on screen and in the terminal
+    fn notify(&mut self, text: String) {
+        println!("{}", text);
+        self.osd.message(text);
+    }
+
+    fn warn(&mut self, text: String) {
+        eprintln!("{}", text);
+        self.osd.message(text);
+    }
+
+    // The state of the frontend, for the status lines of the OSD
+    fn status(&self) -> Vec<String> {
+        let mut status = vec![format!("Slot {}", self.slot)];
+
+        status.extend(self.speed.describe());
+
+        status
+    }
+
+    // Restarts the emulator with the new contents of the ROM if the file was modified
+    fn reload_if_changed(&mut self, emulator: &mut Emulator) {
+        let watcher = match self.watcher.as_mut() {
+            Some(watcher) => watcher,
+            None => return,
+        };
+        if !watcher.changed() {
+            return;
+        }
+
+        let path = watcher.path.clone();
+
+        match read_rom_file(&path.to_string_lossy()) {
+            Ok(program) => match emulator.load_rom(&program) {
+                Ok(()) => self.notify(format!("Reloaded {}", path.display())),
+                Err(err) => self.warn(format!("Could not reload the ROM: {}", err)),
+            },
+            // Keeps running the old program, the next write will trigger another reload
+            Err(err) => self.warn(format!("Could not reload the ROM: {}", err)),
+        }
+    }
+
+    fn state_file(&self) -> Option<PathBuf> {
+        let base = self.state_base.as_ref()?;
+        let mut name = base.file_name()?.to_os_string();
+        name.push(format!(".state{}", self.slot));
+
+        Some(base.with_file_name(name))
+    }
+}
@@ -0,0 +1,233 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use crate::frontend::font::{draw_text, GLYPH_HEIGHT};
+
+// Files with these extensions are listed as ROMs, Octo source and cartridges are
+// compiled when they are loaded
+const ROM_EXTENSIONS: [&str; 6] = ["ch8", "c8", "sc8", "xo8", "8o", "gif"];
+
+const TEXT_COLOR: Color = Color::RGB(0xCC, 0xCC, 0xCC);
+const DIR_COLOR: Color = Color::RGB(0x66, 0xAA, 0xFF);
+const SELECTED_COLOR: Color = Color::RGB(0x33, 0x33, 0x55);
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+}
+
+// A directory listing drawn into the window, to pick a ROM with the keyboard
+pub struct Browser {
+    pub dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl Browser {
+    pub fn new(dir: &Path) -> Result<Browser, String> {
+        let mut browser = Browser {
+            dir: PathBuf::new(),
+            entries: Vec::new(),
+            selected: 0,
+        };
+
+        browser.change_dir(dir)?;
+
+        Ok(browser)
+    }
+
+    // Lists the subdirectories and ROMs of `dir`, directories first
+    fn change_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let dir = dir.canonicalize()
+            .map_err(|err| format!("cannot open '{}': {}", dir.display(), err))?;
+        let listing = fs::read_dir(&dir)
+            .map_err(|err| format!("cannot list '{}': {}", dir.display(), err))?;
+
+        let mut entries: Vec<Entry> = listing
+            .filter_map(|entry| entry.ok())
+            .map(|entry| Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+                path: entry.path(),
+            })
+            .filter(|entry| !entry.name.starts_with('.'))
+            .filter(|entry| entry.is_dir || is_rom(&entry.path))
+            .collect();
+
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+
+        if let Some(parent) = dir.parent() {
+            entries.insert(0, Entry {
+                name: "..".to_string(),
+                path: parent.to_path_buf(),
+                is_dir: true,
+            });
+        }
+
+        self.dir = dir;
+        self.entries = entries;
+        self.selected = 0;
+
+        Ok(())
+    }
+
+    pub fn move_selection(&mut self, delta: i32) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let last = self.entries.len() as i32 - 1;
+        self.selected = (self.selected as i32 + delta).clamp(0, last) as usize;
+    }
+
+    // Enters the selected directory, or returns the selected ROM
+    pub fn open(&mut self) -> Option<PathBuf> {
+        let entry = self.entries.get(self.selected)?;
+
+        if !entry.is_dir {
+            return Some(entry.path.clone());
+        }
+
+        let path = entry.path.clone();
+        if let Err(err) = self.change_dir(&path) {
+            eprintln!("{}", err);
+        }
+
+        None
+    }
+
+    pub fn parent(&mut self) {
+        if let Some(parent) = self.dir.parent().map(Path::to_path_buf) {
+            if let Err(err) = self.change_dir(&parent) {
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        let (width, height) = canvas.output_size()
+            .expect("Could not get the window size.");
+
+        // Text grows with the window, a line is a glyph plus some space
+        let scale = (height / 200).max(1);
+        let line_height = (GLYPH_HEIGHT + 3) * scale;
+        let margin = 2 * scale;
+
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+
+        draw_text(canvas, &self.dir.to_string_lossy(), margin as i32, margin as i32, scale, Color::WHITE);
+
+        // Keep the selection on screen, below the title line
+        let visible = ((height.saturating_sub(line_height + margin)) / line_height).max(1) as usize;
+        let first = self.selected.saturating_sub(visible - 1);
+
+        for (idx, entry) in self.entries.iter().enumerate().skip(first).take(visible) {
+            let y = (margin + line_height * (idx - first + 1) as u32) as i32;
+
+            if idx == self.selected {
+                canvas.set_draw_color(SELECTED_COLOR);
+                canvas.fill_rect(Rect::new(0, y - scale as i32, width, line_height))
+                    .expect("Could not draw the selection.");
+            }
+
+            let (name, color) = if entry.is_dir {
+                (format!("{}/", entry.name), DIR_COLOR)
+            }
+            else {
+                (entry.name.clone(), TEXT_COLOR)
+            };
+
+            draw_text(canvas, &name, margin as i32, y, scale, color);
+        }
+
+        if self.entries.is_empty() {
+            draw_text(canvas, "No ROMs here", margin as i32, (margin + line_height) as i32, scale, TEXT_COLOR);
+        }
+
+        canvas.present();
+    }
+}
+
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.iter().any(|rom| rom.eq_ignore_ascii_case(extension)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn rom_dir(test: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chip8-browser-{}-{}", test, process::id()));
+        for name in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, [0x12, 0x00]).unwrap();
+        }
+
+        dir
+    }
+
+    fn names(browser: &Browser) -> Vec<&str> {
+        browser.entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn directories_are_listed_before_roms() {
+        let dir = rom_dir("listing", &["pong.ch8", "Blitz.CH8", "readme.txt", ".hidden.ch8", "games/tetris.sc8"]);
+        let browser = Browser::new(&dir).unwrap();
+
+        assert_eq!(names(&browser), ["..", "games", "Blitz.CH8", "pong.ch8"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_selection_stays_within_the_listing() {
+        let dir = rom_dir("selection", &["a.ch8", "b.ch8"]);
+        let mut browser = Browser::new(&dir).unwrap();
+
+        browser.move_selection(-1);
+        assert_eq!(browser.selected, 0);
+        browser.move_selection(i32::MAX / 2);
+        assert_eq!(browser.selected, 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn opening_enters_directories_and_returns_roms() {
+        let dir = rom_dir("open", &["games/tetris.sc8"]);
+        let mut browser = Browser::new(&dir).unwrap();
+
+        browser.move_selection(1);
+        assert_eq!(browser.open(), None);
+        assert_eq!(names(&browser), ["..", "tetris.sc8"]);
+
+        browser.move_selection(1);
+        assert_eq!(browser.open(), Some(browser.dir.join("tetris.sc8")));
+
+        browser.parent();
+        assert_eq!(browser.dir, dir.canonicalize().unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn roms_are_recognised_by_their_extension() {
+        assert!(is_rom(Path::new("games/pong.ch8")));
+        assert!(is_rom(Path::new("PONG.XO8")));
+        assert!(is_rom(Path::new("cart.gif")));
+        assert!(!is_rom(Path::new("pong.txt")));
+        assert!(!is_rom(Path::new("ch8")));
+    }
+}
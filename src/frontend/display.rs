@@ -0,0 +1,241 @@
+use sdl2::Sdl;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::video::FullscreenType;
+
+use chip8::emulator::framebuffer::{Framebuffer, PIXELS_ALONG_X, PIXELS_ALONG_Y};
+use crate::frontend::filter::{Filter, FrameFilter};
+use crate::frontend::osd;
+use crate::frontend::palette::Palette;
+
+// Our Display class, it draws the framebuffer of the emulator into an SDL window
+pub struct Display {
+    pub sdl_context: Sdl, 
+    pub canvas: WindowCanvas,
+    // width: u32,
+    // height: u32,
+    // The framebuffer is uploaded here once per frame and scaled up to the window by the renderer
+    pub texture: Texture,
+    // Set when the window itself needs a redraw (resized, palette changed, ...)
+    pub dirty: bool,
+    pub palette: Palette,
+    pub filter: FrameFilter,
+    // Only scale the framebuffer by whole multiples, so every chip8 pixel has the same size
+    pub integer_scaling: bool,
+    // The OSD lines drawn over the last frame
+    overlay: Vec<String>,
+}
+
+impl Display {
+    pub fn new(width: u32, height: u32) -> Display {
+        let sdl_context = sdl2::init()
+            .expect("Couldn't initialize SDL2.");
+        let video_subsystem = sdl_context.video()
+            .expect("Couldn't initialize video subsystem.");
+        
+        // This function initializes the video subsystem, setting up a connection to the window manager, etc, 
+        // and determines the available display modes and pixel formats, 
+        // but does not initialize a window or graphics mode.
+        let window = video_subsystem.window("Chip8 Emulator", width, height)
+            .position_centered()
+            .resizable()
+            .build()
+            .expect("Could not initialize video system.");
+
+        let canvas = window.into_canvas().build()
+            .expect("Could not make a canvas.");
+
+        // A streaming texture holding one texel per chip8 pixel, the renderer takes care
+        // of stretching it over the whole window.
+        let texture = canvas.texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, PIXELS_ALONG_X, PIXELS_ALONG_Y)
+            .expect("Could not create the framebuffer texture.");
+
+        Display {
+            sdl_context,
+            canvas,
+            // width,
+            // height,
+            texture,
+            dirty: true,
+            palette: Palette::default(),
+            filter: FrameFilter::new(Filter::None),
+            integer_scaling: false,
+            overlay: Vec::new(),
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.dirty = true;
+    }
+
+    // Switches to the next built-in palette, returns its name
+    pub fn next_palette(&mut self) -> &'static str {
+        self.set_palette(self.palette.next());
+
+        self.palette.name
+    }
+
+    pub fn set_filter(&mut self, mode: Filter) {
+        self.filter.set_mode(mode);
+        self.dirty = true;
+    }
+
+    // Switches to the next flicker filter, returns its description
+    pub fn next_filter(&mut self) -> String {
+        self.set_filter(self.filter.mode.next());
+
+        self.filter.mode.describe()
+    }
+
+    pub fn toggle_fullscreen(&mut self) -> bool {
+        let window = self.canvas.window_mut();
+        let fullscreen = window.fullscreen_state() == FullscreenType::Off;
+        let mode = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+
+        window.set_fullscreen(mode)
+            .expect("Could not switch fullscreen mode.");
+        self.dirty = true;
+
+        fullscreen
+    }
+
+    pub fn set_integer_scaling(&mut self, integer_scaling: bool) {
+        self.integer_scaling = integer_scaling;
+        self.dirty = true;
+    }
+
+    // The area of the window the framebuffer is drawn to
+    fn viewport(&self) -> Rect {
+        let (width, height) = self.canvas.output_size()
+            .expect("Could not get the window size.");
+
+        viewport(width, height, self.integer_scaling)
+    }
+
+    pub fn render(&mut self, framebuffer: &mut Framebuffer, overlay: &[String]) {
+        // Uploads the framebuffer to the texture and presents it with the OSD lines on top,
+        // but only if something was drawn since the last frame, the filter is still fading
+        // pixels out or the OSD changed.
+        let fading = self.filter.update(&framebuffer.pixel_repr);
+
+        if !self.dirty && !framebuffer.dirty && !fading && self.overlay == overlay {
+            return;
+        }
+
+        let pixel_repr = &framebuffer.pixel_repr;
+        let colors = &self.palette.colors;
+        let filter = &self.filter;
+
+        self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            fill_texture(buffer, pitch, pixel_repr, filter, colors);
+        }).expect("Could not lock the framebuffer texture.");
+
+        let viewport = self.viewport();
+
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, viewport)
+            .expect("Could not copy the framebuffer texture.");
+        osd::draw_lines(&mut self.canvas, viewport, overlay);
+        self.canvas.present();
+
+        self.overlay = overlay.to_vec();
+
+        self.dirty = false;
+        framebuffer.dirty = false;
+    }
+}
+
+// Writes the framebuffer as RGB24 texels into `buffer`, whose rows are `pitch` bytes apart
+fn fill_texture(buffer: &mut [u8], pitch: usize, pixel_repr: &[[u8; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize],
+                filter: &FrameFilter, colors: &[Color; 4]) {
+    for (y, repr_row) in pixel_repr.iter().enumerate() {
+        for (x, pixel_val) in repr_row.iter().enumerate() {
+            let color = filter.color_at(x, y, *pixel_val, colors);
+            let offset = y * pitch + x * 3;
+
+            buffer[offset] = color.r;
+            buffer[offset + 1] = color.g;
+            buffer[offset + 2] = color.b;
+        }
+    }
+}
+
+// The area of a window of the given size the framebuffer is drawn to. It keeps the 2:1
+// aspect ratio of the chip8 display, the rest of the window is filled with black bars.
+fn viewport(width: u32, height: u32, integer_scaling: bool) -> Rect {
+    let mut scale = f32::min(
+        width as f32 / PIXELS_ALONG_X as f32,
+        height as f32 / PIXELS_ALONG_Y as f32,
+    );
+
+    // Windows smaller than the chip8 display still get a (blurry) picture
+    if integer_scaling && scale >= 1.0 {
+        scale = scale.floor();
+    }
+
+    let view_width = ((PIXELS_ALONG_X as f32 * scale) as u32).max(1);
+    let view_height = ((PIXELS_ALONG_Y as f32 * scale) as u32).max(1);
+    let x = (width.saturating_sub(view_width) / 2) as i32;
+    let y = (height.saturating_sub(view_height) / 2) as i32;
+
+    Rect::new(x, y, view_width, view_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texels_are_written_row_by_row_with_the_pitch() {
+        const WIDTH: usize = PIXELS_ALONG_X as usize;
+        const HEIGHT: usize = PIXELS_ALONG_Y as usize;
+        // SDL may pad the rows of a texture
+        const PITCH: usize = WIDTH * 3 + 8;
+
+        let colors = [Color::RGB(1, 2, 3), Color::RGB(4, 5, 6), Color::RGB(7, 8, 9), Color::RGB(10, 11, 12)];
+        let mut pixel_repr = [[0; WIDTH]; HEIGHT];
+        pixel_repr[0][1] = 1;
+        pixel_repr[1][0] = 3;
+        pixel_repr[HEIGHT - 1][WIDTH - 1] = 2;
+
+        let mut buffer = vec![0xEE; PITCH * HEIGHT];
+        fill_texture(&mut buffer, PITCH, &pixel_repr, &FrameFilter::new(Filter::None), &colors);
+
+        assert_eq!(buffer[0..6], [1, 2, 3, 4, 5, 6]);
+        assert_eq!(buffer[PITCH..PITCH + 3], [10, 11, 12]);
+        assert_eq!(buffer[PITCH * (HEIGHT - 1) + (WIDTH - 1) * 3..][..3], [7, 8, 9]);
+        // The padding is left alone
+        assert_eq!(buffer[WIDTH * 3..PITCH], [0xEE; 8]);
+    }
+
+    #[test]
+    fn the_viewport_fills_a_window_of_the_same_aspect_ratio() {
+        assert_eq!(viewport(640, 320, false), Rect::new(0, 0, 640, 320));
+        assert_eq!(viewport(640, 320, true), Rect::new(0, 0, 640, 320));
+    }
+
+    #[test]
+    fn the_viewport_is_centered_between_black_bars() {
+        // Too wide: bars left and right
+        assert_eq!(viewport(800, 320, false), Rect::new(80, 0, 640, 320));
+        // Too high: bars above and below
+        assert_eq!(viewport(640, 480, false), Rect::new(0, 80, 640, 320));
+    }
+
+    #[test]
+    fn integer_scaling_rounds_the_scale_down() {
+        // 700 / 64 = 10.94
+        assert_eq!(viewport(700, 400, false), Rect::new(0, 25, 700, 350));
+        assert_eq!(viewport(700, 400, true), Rect::new(30, 40, 640, 320));
+    }
+
+    #[test]
+    fn windows_smaller_than_the_display_still_get_a_picture() {
+        assert_eq!(viewport(32, 16, true), Rect::new(0, 0, 32, 16));
+        assert_eq!(viewport(1, 1, false), Rect::new(0, 0, 1, 1));
+    }
+}
@@ -0,0 +1,252 @@
+use sdl2::pixels::Color;
+
+use chip8::emulator::framebuffer;
+
+const PIXELS_ALONG_X: usize = framebuffer::PIXELS_ALONG_X as usize;
+const PIXELS_ALONG_Y: usize = framebuffer::PIXELS_ALONG_Y as usize;
+
+// Games erase and redraw their sprites by XORing them, so a moving sprite is off for part of
+// every frame and the screen flickers. These filters emulate the persistence of the phosphor
+// of old screens. They only change what gets rendered, pixel_repr is left untouched.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    None,
+    // A pixel that is switched off keeps this fraction of its brightness every frame
+    Decay(f32),
+    // A pixel is shown lit if it was set in any of the last N frames (at most 8)
+    Persist(u8),
+}
+
+pub const DEFAULT_DECAY: f32 = 0.6;
+pub const DEFAULT_PERSIST: u8 = 3;
+
+// Below this brightness a decaying pixel is considered off
+const DECAY_CUTOFF: f32 = 0.02;
+
+pub struct FrameFilter {
+    pub mode: Filter,
+    intensity: [[f32; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+    // One bit per past frame, the most recent frame is the lowest bit
+    history: [[u8; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+    // The last non-zero value of every pixel, so the right plane colour fades out
+    last_value: [[u8; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+}
+
+impl Filter {
+    // Cycles None -> Decay -> Persist -> None
+    pub fn next(&self) -> Filter {
+        match self {
+            Filter::None => Filter::Decay(DEFAULT_DECAY),
+            Filter::Decay(_) => Filter::Persist(DEFAULT_PERSIST),
+            Filter::Persist(_) => Filter::None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Filter::None => "off".to_string(),
+            Filter::Decay(decay) => format!("ghosting (decay {:.2})", decay),
+            Filter::Persist(frames) => format!("persistence ({} frames)", frames),
+        }
+    }
+}
+
+impl FrameFilter {
+    pub fn new(mode: Filter) -> FrameFilter {
+        FrameFilter {
+            mode,
+            intensity: [[0.0; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+            history: [[0; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+            last_value: [[0; PIXELS_ALONG_X]; PIXELS_ALONG_Y],
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: Filter) {
+        *self = FrameFilter::new(mode);
+    }
+
+    // Feeds the current frame into the filter, has to be called exactly once per frame.
+    // Returns true while the filtered image is still changing on its own (pixels fading out).
+    pub fn update(&mut self, pixel_repr: &[[u8; PIXELS_ALONG_X]; PIXELS_ALONG_Y]) -> bool {
+        let mut changing = false;
+
+        for (y, repr_row) in pixel_repr.iter().enumerate() {
+            for (x, &value) in repr_row.iter().enumerate() {
+                if value != 0 {
+                    self.last_value[y][x] = value;
+                }
+
+                match self.mode {
+                    Filter::None => {},
+                    Filter::Decay(decay) => {
+                        if value != 0 {
+                            self.intensity[y][x] = 1.0;
+                        }
+                        else if self.intensity[y][x] > DECAY_CUTOFF {
+                            self.intensity[y][x] *= decay;
+                            changing = true;
+                        }
+                        else {
+                            self.intensity[y][x] = 0.0;
+                        }
+                    },
+                    Filter::Persist(frames) => {
+                        let mask = (((1u16 << frames.clamp(1, 8)) - 1) & 0xFF) as u8;
+                        let before = self.history[y][x] & mask;
+
+                        self.history[y][x] = (self.history[y][x] << 1) | (value != 0) as u8;
+
+                        if before != 0 && value == 0 {
+                            changing = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        changing
+    }
+
+    pub fn color_at(&self, x: usize, y: usize, value: u8, colors: &[Color; 4]) -> Color {
+        let background = colors[0];
+
+        match self.mode {
+            Filter::None => colors[(value & 0b11) as usize],
+            Filter::Decay(_) => {
+                let color = colors[(self.last_value[y][x] & 0b11) as usize];
+
+                blend(background, color, self.intensity[y][x])
+            },
+            Filter::Persist(frames) => {
+                let mask = (((1u16 << frames.clamp(1, 8)) - 1) & 0xFF) as u8;
+
+                if value != 0 {
+                    colors[(value & 0b11) as usize]
+                }
+                else if self.history[y][x] & mask != 0 {
+                    colors[(self.last_value[y][x] & 0b11) as usize]
+                }
+                else {
+                    background
+                }
+            }
+        }
+    }
+}
+
+fn blend(from: Color, to: Color, amount: f32) -> Color {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+
+    Color::RGB(lerp(from.r, to.r), lerp(from.g, to.g), lerp(from.b, to.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 4] = [
+        Color::RGB(0x00, 0x00, 0x00), Color::RGB(0xFF, 0xFF, 0xFF),
+        Color::RGB(0xFF, 0x00, 0x00), Color::RGB(0x00, 0xFF, 0x00),
+    ];
+
+    fn frame(value: u8) -> [[u8; PIXELS_ALONG_X]; PIXELS_ALONG_Y] {
+        let mut pixel_repr = [[0; PIXELS_ALONG_X]; PIXELS_ALONG_Y];
+        pixel_repr[0][0] = value;
+        pixel_repr
+    }
+
+    #[test]
+    fn no_filter_shows_the_frame_as_is() {
+        let mut filter = FrameFilter::new(Filter::None);
+
+        assert!(!filter.update(&frame(1)));
+        assert_eq!(filter.color_at(0, 0, 1, &COLORS), COLORS[1]);
+        assert!(!filter.update(&frame(0)));
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[0]);
+    }
+
+    #[test]
+    fn decay_fades_a_pixel_out() {
+        let mut filter = FrameFilter::new(Filter::Decay(0.5));
+
+        filter.update(&frame(2));
+        assert_eq!(filter.color_at(0, 0, 2, &COLORS), COLORS[2]);
+
+        // Switched off, the pixel keeps half its brightness every frame in its own colour
+        assert!(filter.update(&frame(0)));
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), Color::RGB(0x80, 0x00, 0x00));
+        assert!(filter.update(&frame(0)));
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), Color::RGB(0x40, 0x00, 0x00));
+
+        // Until it drops below the cutoff and stays off
+        let mut frames = 0;
+        while filter.update(&frame(0)) {
+            frames += 1;
+        }
+        assert_eq!(frames, 4);
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[0]);
+        assert!(!filter.update(&frame(0)));
+    }
+
+    #[test]
+    fn decay_lights_a_pixel_up_again_at_once() {
+        let mut filter = FrameFilter::new(Filter::Decay(DEFAULT_DECAY));
+
+        filter.update(&frame(1));
+        filter.update(&frame(0));
+        filter.update(&frame(1));
+
+        assert_eq!(filter.color_at(0, 0, 1, &COLORS), COLORS[1]);
+    }
+
+    #[test]
+    fn persist_keeps_a_pixel_lit_for_the_given_frames() {
+        let mut filter = FrameFilter::new(Filter::Persist(3));
+
+        filter.update(&frame(3));
+
+        for _ in 0..2 {
+            assert!(filter.update(&frame(0)));
+            assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[3]);
+        }
+
+        assert!(filter.update(&frame(0)));
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[0]);
+        assert!(!filter.update(&frame(0)));
+    }
+
+    #[test]
+    fn persist_is_clamped_to_eight_frames() {
+        let mut filter = FrameFilter::new(Filter::Persist(20));
+
+        filter.update(&frame(1));
+        for _ in 0..7 {
+            filter.update(&frame(0));
+        }
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[1]);
+
+        filter.update(&frame(0));
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[0]);
+    }
+
+    #[test]
+    fn changing_the_mode_forgets_the_past_frames() {
+        let mut filter = FrameFilter::new(Filter::Persist(3));
+
+        filter.update(&frame(1));
+        filter.set_mode(Filter::Persist(3));
+        filter.update(&frame(0));
+
+        assert_eq!(filter.color_at(0, 0, 0, &COLORS), COLORS[0]);
+    }
+
+    #[test]
+    fn filters_cycle_through_every_mode() {
+        assert_eq!(Filter::None.next(), Filter::Decay(DEFAULT_DECAY));
+        assert_eq!(Filter::Decay(0.1).next(), Filter::Persist(DEFAULT_PERSIST));
+        assert_eq!(Filter::Persist(1).next(), Filter::None);
+
+        assert_eq!(Filter::Decay(0.6).describe(), "ghosting (decay 0.60)");
+        assert_eq!(Filter::Persist(3).describe(), "persistence (3 frames)");
+    }
+}
@@ -0,0 +1,109 @@
+use sdl2::keyboard::Keycode;
+
+// Maps keyboard keys onto the 16 keys of the chip8 keypad, keys[n] is the key for 0xn
+#[derive(Clone, Copy)]
+pub struct Keymap {
+    pub keys: [Keycode; 16],
+}
+
+impl Keymap {
+    // The keys labelled 0-9 and A-F
+    pub fn hex() -> Keymap {
+        Keymap {
+            keys: [
+                Keycode::Num0, Keycode::Num1, Keycode::Num2, Keycode::Num3,
+                Keycode::Num4, Keycode::Num5, Keycode::Num6, Keycode::Num7,
+                Keycode::Num8, Keycode::Num9, Keycode::A, Keycode::B,
+                Keycode::C, Keycode::D, Keycode::E, Keycode::F,
+            ],
+        }
+    }
+
+    // The 4x4 block at the left of a QWERTY keyboard, laid out like the COSMAC VIP keypad:
+    //   1 2 3 4      1 2 3 C
+    //   Q W E R  ->  4 5 6 D
+    //   A S D F      7 8 9 E
+    //   Z X C V      A 0 B F
+    pub fn qwerty() -> Keymap {
+        Keymap {
+            keys: [
+                Keycode::X, Keycode::Num1, Keycode::Num2, Keycode::Num3,
+                Keycode::Q, Keycode::W, Keycode::E, Keycode::A,
+                Keycode::S, Keycode::D, Keycode::Z, Keycode::C,
+                Keycode::Num4, Keycode::R, Keycode::F, Keycode::V,
+            ],
+        }
+    }
+
+    // Accepts "hex", "qwerty" or 16 comma separated SDL key names for 0x0 - 0xF,
+    // for example "x,1,2,3,q,w,e,a,s,d,z,c,4,r,f,v"
+    pub fn parse(text: &str) -> Result<Keymap, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "hex" => return Ok(Keymap::hex()),
+            "qwerty" | "cosmac" => return Ok(Keymap::qwerty()),
+            _ => {}
+        }
+
+        let names: Vec<&str> = text.split(',').map(|name| name.trim()).collect();
+        if names.len() != 16 {
+            return Err(format!("expected 16 comma separated keys, got {}", names.len()));
+        }
+
+        let mut keys = [Keycode::Space; 16];
+        for (key, name) in keys.iter_mut().zip(names.iter()) {
+            *key = Keycode::from_name(name)
+                .ok_or_else(|| format!("unknown key name '{}'", name))?;
+        }
+
+        Ok(Keymap { keys })
+    }
+
+    // The chip8 key a keyboard key is mapped to, if any
+    pub fn lookup(&self, keycode: Keycode) -> Option<u8> {
+        self.keys.iter()
+            .position(|key| *key == keycode)
+            .map(|key| key as u8)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::hex()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_parsed_by_name_in_any_case() {
+        assert_eq!(Keymap::parse("HEX").unwrap().keys, Keymap::hex().keys);
+        assert_eq!(Keymap::parse("qwerty").unwrap().keys, Keymap::qwerty().keys);
+        assert_eq!(Keymap::parse("Cosmac").unwrap().keys, Keymap::qwerty().keys);
+    }
+
+    #[test]
+    fn a_keymap_needs_sixteen_keys() {
+        assert_eq!(Keymap::parse("x,1,2,3").err().unwrap(), "expected 16 comma separated keys, got 4");
+        assert_eq!(Keymap::parse("").err().unwrap(), "expected 16 comma separated keys, got 1");
+    }
+
+    #[test]
+    fn unknown_key_names_are_rejected() {
+        let text = " nokey ,1,2,3,q,w,e,a,s,d,z,c,4,r,f,v";
+
+        assert_eq!(Keymap::parse(text).err().unwrap(), "unknown key name 'nokey'");
+    }
+
+    #[test]
+    fn keys_are_looked_up_by_position() {
+        let keymap = Keymap::qwerty();
+
+        assert_eq!(keymap.lookup(Keycode::X), Some(0x0));
+        assert_eq!(keymap.lookup(Keycode::Num4), Some(0xC));
+        assert_eq!(keymap.lookup(Keycode::V), Some(0xF));
+        assert_eq!(keymap.lookup(Keycode::Num0), None);
+        assert_eq!(Keymap::hex().lookup(Keycode::Num0), Some(0x0));
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+use crate::frontend::font::{draw_text, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+// How long a message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+// Older messages are dropped when there are more than this
+const MAX_MESSAGES: usize = 4;
+
+// The speed numbers are averaged over this long
+const SAMPLE_DURATION: Duration = Duration::from_secs(1);
+
+const TEXT_COLOR: Color = Color::RGB(0xFF, 0xFF, 0xFF);
+const BACKING_COLOR: Color = Color::RGBA(0x00, 0x00, 0x00, 0xA0);
+
+// The on-screen display, text drawn over the picture of the emulator. Messages are
+// always shown, the status lines (speed, save state slot, ...) only when visible.
+pub struct Osd {
+    pub visible: bool,
+    messages: Vec<(String, Instant)>,
+    fps: f32,
+    instructions_per_second: f32,
+    sample_start: Instant,
+    sample_frames: u32,
+    sample_instructions: u64,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd {
+            visible: false,
+            messages: Vec::new(),
+            fps: 0.0,
+            instructions_per_second: 0.0,
+            sample_start: Instant::now(),
+            sample_frames: 0,
+            sample_instructions: 0,
+        }
+    }
+
+    pub fn message(&mut self, text: String) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+
+        self.messages.push((text, Instant::now()));
+    }
+
+    // To be called once per frame with the instruction counter of the emulator
+    pub fn update(&mut self, instructions_executed: u64) {
+        self.update_at(Instant::now(), instructions_executed);
+    }
+
+    fn update_at(&mut self, now: Instant, instructions_executed: u64) {
+        self.messages.retain(|(_, shown)| now.duration_since(*shown) < MESSAGE_DURATION);
+
+        if self.sample_frames == 0 {
+            self.sample_start = now;
+            self.sample_instructions = instructions_executed;
+        }
+        self.sample_frames += 1;
+
+        let elapsed = now.duration_since(self.sample_start);
+        if elapsed >= SAMPLE_DURATION {
+            let seconds = elapsed.as_secs_f32();
+            self.fps = (self.sample_frames - 1) as f32 / seconds;
+            self.instructions_per_second = instructions_executed.saturating_sub(self.sample_instructions) as f32 / seconds;
+            self.sample_frames = 0;
+        }
+    }
+
+    // The lines to draw this frame, with `status` describing the state of the frontend
+    pub fn lines(&self, status: &[String]) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        if self.visible {
+            lines.push(format!("{:.0} FPS  {:.0} IPS", self.fps, self.instructions_per_second));
+            lines.extend(status.iter().cloned());
+        }
+
+        lines.extend(self.messages.iter().map(|(text, _)| text.clone()));
+
+        lines
+    }
+}
+
+// Draws lines of text in the top left corner of `area`, on a dark backing so they can
+// be read on top of any picture
+pub fn draw_lines(canvas: &mut WindowCanvas, area: Rect, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+
+    let scale = (area.height() / 160).max(1);
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    let margin = 2 * scale;
+
+    let longest = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u32;
+    let width = (longest * (GLYPH_WIDTH + 1) * scale + 2 * margin).min(area.width());
+    let height = (lines.len() as u32 * line_height + 2 * margin).min(area.height());
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(BACKING_COLOR);
+    canvas.fill_rect(Rect::new(area.x(), area.y(), width, height))
+        .expect("Could not draw the OSD.");
+    canvas.set_blend_mode(BlendMode::None);
+
+    for (idx, line) in lines.iter().enumerate() {
+        let x = area.x() + margin as i32;
+        let y = area.y() + (margin + idx as u32 * line_height) as i32;
+
+        draw_text(canvas, line, x, y, scale, TEXT_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Vec<String> {
+        vec!["Slot 3".to_string(), "Paused".to_string()]
+    }
+
+    #[test]
+    fn a_hidden_osd_only_shows_messages() {
+        let mut osd = Osd::new();
+        assert!(osd.lines(&status()).is_empty());
+
+        osd.message("State saved".to_string());
+        assert_eq!(osd.lines(&status()), ["State saved"]);
+    }
+
+    #[test]
+    fn a_visible_osd_shows_the_speed_and_status_above_the_messages() {
+        let mut osd = Osd::new();
+        osd.visible = true;
+        osd.message("Resumed".to_string());
+
+        assert_eq!(osd.lines(&status()), ["0 FPS  0 IPS", "Slot 3", "Paused", "Resumed"]);
+    }
+
+    #[test]
+    fn the_speed_is_averaged_over_a_second() {
+        let mut osd = Osd::new();
+        osd.visible = true;
+        let start = Instant::now();
+
+        for frame in 0..=60 {
+            osd.update_at(start + SAMPLE_DURATION * frame / 60, frame as u64 * 500);
+        }
+
+        assert_eq!(osd.lines(&[])[0], "60 FPS  30000 IPS");
+    }
+
+    #[test]
+    fn messages_disappear_after_a_while() {
+        let mut osd = Osd::new();
+        osd.message("Reloaded pong.ch8".to_string());
+
+        osd.update_at(Instant::now() + MESSAGE_DURATION / 2, 0);
+        assert_eq!(osd.lines(&[]).len(), 1);
+
+        osd.update_at(Instant::now() + MESSAGE_DURATION, 0);
+        assert!(osd.lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn only_the_latest_messages_are_kept() {
+        let mut osd = Osd::new();
+
+        for idx in 0..MAX_MESSAGES + 2 {
+            osd.message(format!("Message {}", idx));
+        }
+
+        assert_eq!(osd.lines(&[]), ["Message 2", "Message 3", "Message 4", "Message 5"]);
+    }
+}
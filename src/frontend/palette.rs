@@ -0,0 +1,158 @@
+use sdl2::pixels::Color;
+
+// A palette maps the value of a pixel in pixel_repr to a colour.
+//  - colors[0]: background (no plane set)
+//  - colors[1]: foreground (first plane set)
+//  - colors[2]: second plane set
+//  - colors[3]: both planes set
+// The plain chip8 only ever uses the first two entries, the other two are there for multi-plane modes.
+#[derive(Clone, Copy)]
+pub struct Palette {
+    pub name: &'static str,
+    pub colors: [Color; 4],
+}
+
+pub const PALETTES: [Palette; 6] = [
+    Palette {
+        name: "classic",
+        colors: [Color::RGB(0x00, 0x00, 0x00), Color::RGB(0xFF, 0xFF, 0xFF),
+                 Color::RGB(0xAA, 0xAA, 0xAA), Color::RGB(0x55, 0x55, 0x55)],
+    },
+    Palette {
+        name: "green",
+        colors: [Color::RGB(0x04, 0x14, 0x08), Color::RGB(0x33, 0xFF, 0x66),
+                 Color::RGB(0x1A, 0x99, 0x3D), Color::RGB(0xB3, 0xFF, 0xC6)],
+    },
+    Palette {
+        name: "amber",
+        colors: [Color::RGB(0x1A, 0x0E, 0x00), Color::RGB(0xFF, 0xB0, 0x00),
+                 Color::RGB(0x99, 0x5C, 0x00), Color::RGB(0xFF, 0xDD, 0x88)],
+    },
+    Palette {
+        name: "lcd",
+        colors: [Color::RGB(0x9B, 0xBC, 0x0F), Color::RGB(0x0F, 0x38, 0x0F),
+                 Color::RGB(0x30, 0x62, 0x30), Color::RGB(0x8B, 0xAC, 0x0F)],
+    },
+    Palette {
+        name: "high-contrast",
+        colors: [Color::RGB(0x00, 0x00, 0x00), Color::RGB(0xFF, 0xFF, 0x00),
+                 Color::RGB(0x00, 0xFF, 0xFF), Color::RGB(0xFF, 0xFF, 0xFF)],
+    },
+    // Okabe-Ito colours, which stay distinguishable under the common forms of colour blindness
+    Palette {
+        name: "colorblind",
+        colors: [Color::RGB(0x00, 0x00, 0x00), Color::RGB(0xE6, 0x9F, 0x00),
+                 Color::RGB(0x56, 0xB4, 0xE9), Color::RGB(0x00, 0x9E, 0x73)],
+    },
+];
+
+impl Palette {
+    pub fn find(name: &str) -> Option<Palette> {
+        PALETTES.iter()
+            .find(|palette| palette.name.eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    // Builds a palette out of user supplied colours, the plane colours are mixed from the two
+    pub fn custom(foreground: Color, background: Color) -> Palette {
+        Palette {
+            name: "custom",
+            colors: [background, foreground, mix(foreground, background), foreground],
+        }
+    }
+
+    // The built-in palette after this one, wrapping around at the end
+    pub fn next(&self) -> Palette {
+        let idx = PALETTES.iter()
+            .position(|palette| palette.name == self.name)
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+
+        PALETTES[idx % PALETTES.len()]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        PALETTES[0]
+    }
+}
+
+// Parses colours written as RRGGBB, with or without a leading '#'
+pub fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+
+    Some(Color::RGB(r, g, b))
+}
+
+fn mix(a: Color, b: Color) -> Color {
+    let avg = |x: u8, y: u8| ((x as u16 + y as u16) / 2) as u8;
+
+    Color::RGB(avg(a.r, b.r), avg(a.g, b.g), avg(a.b, b.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_are_found_by_name_in_any_case() {
+        assert_eq!(Palette::find("Amber").map(|palette| palette.name), Some("amber"));
+        assert_eq!(Palette::find("HIGH-CONTRAST").map(|palette| palette.name), Some("high-contrast"));
+        assert!(Palette::find("purple").is_none());
+    }
+
+    #[test]
+    fn next_cycles_through_every_palette() {
+        let mut palette = Palette::default();
+        let mut names = vec![palette.name];
+
+        for _ in 1..PALETTES.len() {
+            palette = palette.next();
+            names.push(palette.name);
+        }
+
+        assert_eq!(names, PALETTES.iter().map(|palette| palette.name).collect::<Vec<_>>());
+        assert_eq!(palette.next().name, PALETTES[0].name);
+    }
+
+    #[test]
+    fn a_custom_palette_continues_with_the_first_built_in_one() {
+        let custom = Palette::custom(Color::RGB(0xFF, 0xFF, 0xFF), Color::RGB(0x00, 0x00, 0x00));
+
+        assert_eq!(custom.next().name, PALETTES[0].name);
+    }
+
+    #[test]
+    fn custom_palettes_mix_the_plane_colours() {
+        let custom = Palette::custom(Color::RGB(0xFF, 0x80, 0x00), Color::RGB(0x01, 0x00, 0x10));
+
+        assert_eq!(custom.colors, [
+            Color::RGB(0x01, 0x00, 0x10), Color::RGB(0xFF, 0x80, 0x00),
+            Color::RGB(0x80, 0x40, 0x08), Color::RGB(0xFF, 0x80, 0x00),
+        ]);
+    }
+
+    #[test]
+    fn colours_are_parsed_with_or_without_a_hash() {
+        assert_eq!(parse_color("#FFB000"), Some(Color::RGB(0xFF, 0xB0, 0x00)));
+        assert_eq!(parse_color("0f380f"), Some(Color::RGB(0x0F, 0x38, 0x0F)));
+    }
+
+    #[test]
+    fn malformed_colours_are_rejected() {
+        assert_eq!(parse_color("#FFF"), None);
+        assert_eq!(parse_color("FFB0000"), None);
+        assert_eq!(parse_color("GGGGGG"), None);
+        assert_eq!(parse_color("#+1+2+3"), None);
+        assert_eq!(parse_color("ééé"), None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use crate::frontend::FRAME_DURATION;
+
+// Slow motion makes every frame last this many times as long
+const SLOW_MOTION_FACTOR: u32 = 4;
+
+// Frames run per frame shown while fast forwarding, unless told otherwise
+pub const DEFAULT_FAST_FORWARD: u32 = 4;
+
+// How fast the emulator runs compared to real time
+pub struct Speed {
+    pub paused: bool,
+    pub slow_motion: bool,
+    // Frames run per frame shown while fast forward is held, 0 runs as fast as possible
+    pub fast_forward: u32,
+    pub fast_forward_held: bool,
+}
+
+impl Speed {
+    pub fn new() -> Speed {
+        Speed {
+            paused: false,
+            slow_motion: false,
+            fast_forward: DEFAULT_FAST_FORWARD,
+            fast_forward_held: false,
+        }
+    }
+
+    // Fast forward runs several frames before showing one, uncapped runs as many
+    // as fit into the time of a frame (u32::MAX)
+    pub fn frames_to_run(&self) -> u32 {
+        match (self.paused, self.fast_forward_held, self.fast_forward) {
+            (true, _, _) => 0,
+            (false, true, 0) => u32::MAX,
+            (false, true, multiplier) => multiplier,
+            (false, false, _) => 1,
+        }
+    }
+
+    // How long a frame is shown, fast forward wins over slow motion
+    pub fn frame_duration(&self) -> Duration {
+        if self.slow_motion && !self.fast_forward_held {
+            FRAME_DURATION * SLOW_MOTION_FACTOR
+        }
+        else {
+            FRAME_DURATION
+        }
+    }
+
+    // The line shown on the OSD, None at normal speed
+    pub fn describe(&self) -> Option<String> {
+        if self.paused {
+            Some("Paused".to_string())
+        }
+        else if self.fast_forward_held {
+            match self.fast_forward {
+                0 => Some("Fast forward (uncapped)".to_string()),
+                multiplier => Some(format!("Fast forward x{}", multiplier)),
+            }
+        }
+        else if self.slow_motion {
+            Some(format!("Slow motion 1/{}", SLOW_MOTION_FACTOR))
+        }
+        else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_speed_runs_a_frame_per_frame() {
+        let speed = Speed::new();
+
+        assert_eq!(speed.frames_to_run(), 1);
+        assert_eq!(speed.frame_duration(), FRAME_DURATION);
+        assert_eq!(speed.describe(), None);
+    }
+
+    #[test]
+    fn pausing_runs_no_frames() {
+        let mut speed = Speed::new();
+        speed.paused = true;
+        speed.fast_forward_held = true;
+
+        assert_eq!(speed.frames_to_run(), 0);
+        assert_eq!(speed.describe().unwrap(), "Paused");
+    }
+
+    #[test]
+    fn fast_forward_runs_several_frames() {
+        let mut speed = Speed::new();
+        speed.fast_forward_held = true;
+
+        assert_eq!(speed.frames_to_run(), DEFAULT_FAST_FORWARD);
+        assert_eq!(speed.describe().unwrap(), "Fast forward x4");
+
+        speed.fast_forward = 0;
+        assert_eq!(speed.frames_to_run(), u32::MAX);
+        assert_eq!(speed.describe().unwrap(), "Fast forward (uncapped)");
+    }
+
+    #[test]
+    fn slow_motion_lengthens_the_frames_unless_fast_forwarding() {
+        let mut speed = Speed::new();
+        speed.slow_motion = true;
+
+        assert_eq!(speed.frames_to_run(), 1);
+        assert_eq!(speed.frame_duration(), FRAME_DURATION * 4);
+        assert_eq!(speed.describe().unwrap(), "Slow motion 1/4");
+
+        speed.fast_forward_held = true;
+        assert_eq!(speed.frame_duration(), FRAME_DURATION);
+        assert_eq!(speed.describe().unwrap(), "Fast forward x4");
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Notices when a file is written to, by comparing its modification time
+pub struct Watcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Watcher {
+        Watcher {
+            path: path.to_path_buf(),
+            modified: modified_time(path),
+        }
+    }
+
+    // True once for every change of the file since the last call. A file that can't be
+    // read (for example while an editor replaces it) doesn't count as changed.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::process;
+    use std::time::Duration;
+
+    fn touch(path: &Path, seconds: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    #[test]
+    fn every_write_is_noticed_once() {
+        let path = env::temp_dir().join(format!("chip8-watch-{}.ch8", process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        touch(&path, 1_000);
+
+        let mut watcher = Watcher::new(&path);
+        assert!(!watcher.changed());
+
+        touch(&path, 2_000);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // Missing files are waited for
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+
+        fs::write(&path, [0x00, 0xE0]).unwrap();
+        touch(&path, 3_000);
+        assert!(watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+//! A reinforcement learning environment in the style of OpenAI gym.
+//!
+//! The agent sees the screen and presses keypad keys. What counts as a reward and
+//! when an episode is over depends on the game, so both are read out of memory by
+//! functions the caller supplies.
+//!
+//! ```
+//! use chip8::gym::Environment;
+//!
+//! // LD V0, #00; ADD V0, #01; LD I, #300; LD [I], V0; JP #202
+//! let rom = [0x60, 0x00, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+//!
+//! let mut env = Environment::new()
+//!     .frame_skip(4)
+//!     .reward(|memory| memory[0x300] as f32)
+//!     .done(|memory| memory[0x300] >= 200);
+//!
+//! env.reset(&rom, 1).unwrap();
+//!
+//! let mut total = 0.0;
+//! loop {
+//!     // Hold key 5
+//!     let (_screen, reward, done) = env.step(1 << 0x5);
+//!     total += reward;
+//!     if done {
+//!         break;
+//!     }
+//! }
+//! assert!(total > 0.0);
+//! ```
+
+use crate::emulator::error::Error;
+use crate::emulator::framebuffer::{PIXELS_ALONG_X, PIXELS_ALONG_Y};
+use crate::emulator::quirks::Quirks;
+use crate::emulator::Emulator;
+
+/// The screen, one byte per pixel (0 or 1), indexed `[y][x]`
+pub type Observation = [[u8; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize];
+
+/// The keys held down during a step, bit n is key n of the keypad
+pub type Action = u16;
+
+/// All 2^16 key combinations are valid actions, most games only need a handful.
+pub const ACTION_COUNT: usize = 1 << 16;
+
+// Send, so that environments can be moved to the threads that run them
+type RewardFn = Box<dyn FnMut(&[u8]) -> f32 + Send>;
+type DoneFn = Box<dyn FnMut(&[u8]) -> bool + Send>;
+
+/// Runs a ROM one step at a time for an agent, see the module documentation
+pub struct Environment {
+    emulator: Emulator,
+    quirks: Option<Quirks>,
+    speed: Option<u32>,
+    // Frames run per step, with the same keys held
+    frame_skip: u32,
+    // Episodes end after this many frames, even if `done` doesn't say so
+    max_frames: Option<u64>,
+    frames: u64,
+    reward: RewardFn,
+    done: DoneFn,
+}
+
+impl Environment {
+    /// An environment without rewards that never ends by itself, running one frame per step
+    pub fn new() -> Environment {
+        let mut emulator = Emulator::new();
+        emulator.set_print_warnings(false);
+
+        Environment {
+            emulator,
+            quirks: None,
+            speed: None,
+            frame_skip: 1,
+            max_frames: None,
+            frames: 0,
+            reward: Box::new(|_| 0.0),
+            done: Box::new(|_| false),
+        }
+    }
+
+    /// Runs the ROM with these quirks instead of the default ones
+    pub fn quirks(mut self, quirks: Quirks) -> Environment {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Instructions executed per frame
+    pub fn speed(mut self, instructions_per_frame: u32) -> Environment {
+        self.speed = Some(instructions_per_frame);
+        self
+    }
+
+    /// Frames run per step, the rewards of all of them are added up
+    pub fn frame_skip(mut self, frames: u32) -> Environment {
+        self.frame_skip = frames.max(1);
+        self
+    }
+
+    /// Ends episodes after this many frames
+    pub fn max_frames(mut self, frames: u64) -> Environment {
+        self.max_frames = Some(frames);
+        self
+    }
+
+    /// Computes the reward of a frame from the memory after it
+    pub fn reward(mut self, reward: impl FnMut(&[u8]) -> f32 + Send + 'static) -> Environment {
+        self.reward = Box::new(reward);
+        self
+    }
+
+    /// Tells from the memory after a frame whether the episode is over
+    pub fn done(mut self, done: impl FnMut(&[u8]) -> bool + Send + 'static) -> Environment {
+        self.done = Box::new(done);
+        self
+    }
+
+    /// Starts a new episode of `rom`, the seed makes episodes reproducible
+    pub fn reset(&mut self, rom: &[u8], seed: u64) -> Result<Observation, Error> {
+        let mut builder = Emulator::builder().seed(seed);
+        if let Some(quirks) = self.quirks {
+            builder = builder.quirks(quirks);
+        }
+        if let Some(speed) = self.speed {
+            builder = builder.speed(speed);
+        }
+
+        let mut emulator = builder.build();
+        // Thousands of episodes would flood stderr with the same warnings
+        emulator.set_print_warnings(false);
+        emulator.load_rom(rom)?;
+
+        self.emulator = emulator;
+        self.frames = 0;
+
+        Ok(self.observation())
+    }
+
+    /// Holds the keys of `action` down for frame_skip frames, returns the screen after
+    /// them, the sum of their rewards and whether the episode is over
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        for key in 0..16 {
+            self.emulator.set_key(key, action & (1 << key) != 0);
+        }
+
+        let mut total = 0.0;
+        let mut done = false;
+
+        for _ in 0..self.frame_skip {
+            self.emulator.run_frame();
+            self.frames += 1;
+
+            total += (self.reward)(self.emulator.memory());
+            done = (self.done)(self.emulator.memory())
+                || self.max_frames.is_some_and(|max_frames| self.frames >= max_frames);
+
+            if done {
+                break;
+            }
+        }
+
+        (self.observation(), total, done)
+    }
+
+    /// The screen as it is now
+    pub fn observation(&self) -> Observation {
+        self.emulator.framebuffer().pixel_repr
+    }
+
+    /// The emulator of the current episode, to look at registers or memory
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    // LD V0, #00; ADD V0, #01; LD I, #300; LD [I], V0; JP #202
+    const COUNTER: [u8; 10] = [0x60, 0x00, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+
+    #[test]
+    fn environments_run_on_other_threads() {
+        let workers: Vec<_> = (0..4)
+            .map(|seed| {
+                let mut env = Environment::new()
+                    .speed(5)
+                    .max_frames(10)
+                    .reward(|memory| memory[0x300] as f32);
+
+                thread::spawn(move || {
+                    env.reset(&COUNTER, seed).unwrap();
+                    let mut total = 0.0;
+                    loop {
+                        let (_, reward, done) = env.step(0);
+                        total += reward;
+                        if done {
+                            return total;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let totals: Vec<f32> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+        assert!(totals.iter().all(|total| *total > 0.0 && *total == totals[0]));
+    }
+}
@@ -0,0 +1,42 @@
+//! A chip8 interpreter that can be embedded into other programs.
+//!
+//! ```
+//! use chip8::Emulator;
+//! use chip8::emulator::quirks::Platform;
+//!
+//! let mut emulator = Emulator::builder()
+//!     .platform(Platform::Chip8)
+//!     .seed(1)
+//!     .build();
+//!
+//! // LD V0, #0A; LD F, V0; DRW V0, V0, 5; JP #206
+//! emulator.load_rom(&[0x60, 0x0A, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+//! emulator.run_frame();
+//!
+//! assert_eq!(emulator.framebuffer().pixel_repr[10][10], 1);
+//! ```
+//!
+//! The SDL frontend, the config file and the command line tool are behind the
+//! `frontend` feature, which is on by default. Without the `std` feature the
+//! interpreter builds for `no_std` targets and doesn't allocate: no tracing, no
+//! assembler or disassembler, and `save_state_into` takes the place of `save_state`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![warn(missing_docs)]
+
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod decompile;
+pub mod detect;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod emulator;
+#[cfg(feature = "std")]
+pub mod gym;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod octo;
+
+pub use emulator::{Emulator, EmulatorBuilder};
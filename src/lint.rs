@@ -0,0 +1,422 @@
+//! Looks for mistakes in a ROM without running it: branches that go nowhere sensible,
+//! code nothing reaches, sprites and stores that run off the end of memory or into the
+//! program, and calls that nest deeper than the stack. Only the paths `disasm::analyze`
+//! finds are checked, and I is only known where an LD I, addr sets it on every path.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::disasm::{analyze, mnemonic, Analysis, LabelKind};
+use crate::emulator::MEMORY_SIZE;
+
+// The stack has 16 entries but the first one is never used, so 15 calls can be nested
+const MAX_CALL_DEPTH: usize = 15;
+
+/// Something that looks wrong with the instruction at `addr`
+pub struct Lint {
+    /// Where the instruction is loaded
+    pub addr: usize,
+    /// What looks wrong
+    pub message: String,
+}
+
+// What is known about I before an instruction runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Index {
+    Known(usize),
+    Unknown,
+}
+
+struct Linter<'a> {
+    program: &'a [u8],
+    analysis: Analysis,
+    lints: Vec<Lint>,
+}
+
+/// Lints a program loaded at `start`, execution begins at `start`. The lints are in the
+/// order of their addresses.
+pub fn lint(program: &[u8], start: usize) -> Vec<Lint> {
+    let mut linter = Linter {
+        program,
+        analysis: analyze(program, start),
+        lints: Vec::new(),
+    };
+
+    linter.branches();
+    linter.long_skips();
+    linter.unreachable();
+    linter.memory();
+    linter.calls();
+
+    linter.lints.sort_by_key(|lint| lint.addr);
+    linter.lints
+}
+
+impl<'a> Linter<'a> {
+    fn opcode_at(&self, addr: usize) -> Option<u16> {
+        let offset = addr.checked_sub(self.analysis.start)?;
+        let bytes = self.program.get(offset..offset + 2)?;
+
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn warn(&mut self, addr: usize, message: String) {
+        self.lints.push(Lint { addr, message });
+    }
+
+    // The instructions of the program with their opcodes
+    fn code(&self) -> Vec<(usize, u16)> {
+        self.analysis.code.iter()
+            .map(|addr| (*addr, self.opcode_at(*addr).unwrap()))
+            .collect()
+    }
+
+    // Jumps and calls to odd addresses or outside the program
+    fn branches(&mut self) {
+        for (addr, opcode) in self.code() {
+            if !matches!(opcode >> 12, 0x1 | 0x2) {
+                continue;
+            }
+
+            let target = (opcode & 0x0FFF) as usize;
+            let text = mnemonic(opcode).unwrap();
+
+            if target < self.analysis.start || target >= self.analysis.end {
+                self.warn(addr, format!("{} goes outside the program ({:03X}-{:03X})",
+                    text, self.analysis.start, self.analysis.end - 1));
+            }
+            else if target & 1 != 0 {
+                self.warn(addr, format!("{} goes to an odd address", text));
+            }
+        }
+    }
+
+    // F000 NNNN is the only instruction that takes four bytes, skipping it takes two
+    // skips on interpreters that don't know it
+    fn long_skips(&mut self) {
+        for (addr, opcode) in self.code() {
+            let skip = matches!(opcode >> 12, 0x3 | 0x4 | 0x5 | 0x9 | 0xE);
+
+            if skip && self.opcode_at(addr + 2) == Some(0xF000) {
+                self.warn(addr, format!("{} is followed by F000 NNNN, only XO-CHIP skips all \
+                    four bytes of it, others run NNNN as an instruction", mnemonic(opcode).unwrap()));
+            }
+        }
+    }
+
+    // Runs of bytes that aren't reached but decode as instructions and end like a routine
+    // does. Sprites are usually loaded into I somewhere, runs are cut at their labels.
+    fn unreachable(&mut self) {
+        let mut addr = self.analysis.start;
+
+        while addr < self.analysis.end {
+            if self.analysis.is_code(addr) {
+                addr += 2;
+                continue;
+            }
+
+            let run = addr;
+            addr += 1;
+            while addr < self.analysis.end && !self.analysis.is_code(addr)
+                && self.analysis.labels.get(&addr) != Some(&LabelKind::Data) {
+                addr += 1;
+            }
+
+            let data = self.analysis.labels.get(&run) == Some(&LabelKind::Data);
+            if !data && self.looks_like_code(run, addr) {
+                self.warn(run, format!("the instructions up to {:03X} are never reached", addr - 1));
+            }
+        }
+    }
+
+    fn looks_like_code(&self, start: usize, end: usize) -> bool {
+        if (end - start) & 1 != 0 || end - start < 4 {
+            return false;
+        }
+
+        let opcodes: Vec<u16> = (start..end).step_by(2).filter_map(|addr| self.opcode_at(addr)).collect();
+        let valid = opcodes.iter().all(|opcode| *opcode != 0 && mnemonic(*opcode).is_some());
+        let last = *opcodes.last().unwrap();
+
+        valid && (last >> 12 == 0x1 || last == 0x00EE)
+    }
+
+    // Follows I through the program to check the sprites DXYN draws and the bytes FX33
+    // and FX55 store
+    fn memory(&mut self) {
+        let mut index: BTreeMap<usize, Index> = BTreeMap::new();
+        let mut pending = vec![(self.analysis.start, Index::Unknown)];
+
+        while let Some((addr, value)) = pending.pop() {
+            if !self.analysis.is_code(addr) {
+                continue;
+            }
+
+            // Paths that disagree on I leave it unknown
+            let merged = match index.get(&addr) {
+                Some(known) if *known == value => continue,
+                Some(_) => Index::Unknown,
+                None => value,
+            };
+            if index.get(&addr) == Some(&merged) {
+                continue;
+            }
+            index.insert(addr, merged);
+
+            let opcode = self.opcode_at(addr).unwrap();
+            let target = (opcode & 0x0FFF) as usize;
+            let (next, skip) = (addr + 2, addr + 4);
+
+            let after = match (opcode >> 12, opcode & 0x00FF) {
+                (0xA, _) => Index::Known(target),
+                // These move I by a register, or by a quirk for FX55 and FX65
+                (0xF, 0x1E) | (0xF, 0x29) | (0xF, 0x55) | (0xF, 0x65) => Index::Unknown,
+                _ => merged,
+            };
+
+            match opcode >> 12 {
+                0x0 if opcode == 0x00EE || opcode == 0x00FD => {},
+                0x1 => pending.push((target, after)),
+                // The subroutine may change I
+                0x2 => {
+                    pending.push((target, after));
+                    pending.push((next, Index::Unknown));
+                },
+                0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                    pending.push((next, after));
+                    pending.push((skip, after));
+                },
+                0xB => {
+                    let mut entry = target;
+                    while self.analysis.is_code(entry) && self.opcode_at(entry).unwrap() >> 12 == 0x1 {
+                        pending.push((entry, after));
+                        entry += 2;
+                    }
+                },
+                _ => pending.push((next, after)),
+            }
+        }
+
+        for (addr, value) in index {
+            if let Index::Known(i) = value {
+                self.access(addr, i);
+            }
+        }
+    }
+
+    // Checks the memory the instruction at `addr` accesses through I
+    fn access(&mut self, addr: usize, i: usize) {
+        let opcode = self.opcode_at(addr).unwrap();
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let text = mnemonic(opcode).unwrap();
+
+        match (opcode >> 12, opcode & 0x00FF) {
+            (0xD, _) => {
+                // DXY0 draws a 16x16 sprite on SCHIP
+                let rows = match opcode & 0x000F {
+                    0 => 32,
+                    rows => rows as usize,
+                };
+
+                if i + rows > MEMORY_SIZE {
+                    self.warn(addr, format!("{} reads the sprite at {:03X} past the end of memory", text, i));
+                }
+            },
+            (0xF, 0x33) => self.store(addr, &text, i, 3),
+            (0xF, 0x55) => self.store(addr, &text, i, x + 1),
+            _ => {},
+        }
+    }
+
+    fn store(&mut self, addr: usize, text: &str, i: usize, len: usize) {
+        // An instruction is two bytes, writing either one of them changes it
+        let overwritten = (i..i + len)
+            .find(|byte| self.analysis.is_code(*byte) || (*byte > 0 && self.analysis.is_code(byte - 1)));
+
+        if let Some(byte) = overwritten {
+            self.warn(addr, format!("{} writes into the code at {:03X}", text, byte & !1));
+        }
+        else if i + len > MEMORY_SIZE {
+            self.warn(addr, format!("{} writes past the end of memory", text));
+        }
+    }
+
+    // Recursion and call chains deeper than the stack
+    fn calls(&mut self) {
+        let start = self.analysis.start;
+        let mut calls = BTreeMap::new();
+        let mut entries = vec![start];
+
+        while let Some(entry) = entries.pop() {
+            if calls.contains_key(&entry) {
+                continue;
+            }
+
+            let sites = self.call_sites(entry);
+            entries.extend(sites.iter().map(|(_, target)| *target));
+            calls.insert(entry, sites);
+        }
+
+        let mut depths = BTreeMap::new();
+        let mut visiting = BTreeSet::new();
+        self.depth(start, &calls, &mut depths, &mut visiting);
+
+        if depths[&start] <= MAX_CALL_DEPTH {
+            return;
+        }
+
+        // Follows the deepest chain down to the call that overflows the stack
+        let mut entry = start;
+        for level in 1.. {
+            let (site, target) = *calls[&entry].iter()
+                .find(|(_, target)| depths.get(target).is_some_and(|depth| depth + 1 == depths[&entry]))
+                .unwrap();
+
+            if level > MAX_CALL_DEPTH {
+                self.warn(site, format!("{} nests {} calls deep, the stack only holds {}",
+                    mnemonic(self.opcode_at(site).unwrap()).unwrap(), level, MAX_CALL_DEPTH));
+                break;
+            }
+
+            entry = target;
+        }
+    }
+
+    // The deepest nesting of calls made from `entry`, reports the calls that recurse
+    fn depth(&mut self, entry: usize, calls: &BTreeMap<usize, Vec<(usize, usize)>>,
+        depths: &mut BTreeMap<usize, usize>, visiting: &mut BTreeSet<usize>) -> usize {
+        if let Some(depth) = depths.get(&entry) {
+            return *depth;
+        }
+
+        visiting.insert(entry);
+
+        let mut depth = 0;
+        for (site, target) in &calls[&entry] {
+            if visiting.contains(target) {
+                self.warn(*site, format!("{} recurses, every call takes an entry of the \
+                    stack and it only holds {}", mnemonic(self.opcode_at(*site).unwrap()).unwrap(),
+                    MAX_CALL_DEPTH));
+                continue;
+            }
+
+            depth = depth.max(1 + self.depth(*target, calls, depths, visiting));
+        }
+
+        visiting.remove(&entry);
+        depths.insert(entry, depth);
+
+        depth
+    }
+
+    // The calls (address of the CALL, subroutine) the code from `entry` up to its returns
+    // makes. Jumps are followed, also into other subroutines, those are tail calls.
+    fn call_sites(&self, entry: usize) -> Vec<(usize, usize)> {
+        let mut sites = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(addr) = pending.pop() {
+            if !self.analysis.is_code(addr) || !seen.insert(addr) {
+                continue;
+            }
+
+            let opcode = self.opcode_at(addr).unwrap();
+            let target = (opcode & 0x0FFF) as usize;
+
+            match opcode >> 12 {
+                0x0 if opcode == 0x00EE || opcode == 0x00FD => {},
+                0x1 => pending.push(target),
+                0x2 => {
+                    sites.push((addr, target));
+                    pending.push(addr + 2);
+                },
+                0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                    pending.push(addr + 2);
+                    pending.push(addr + 4);
+                },
+                0xB => {
+                    let mut entry = target;
+                    while self.analysis.is_code(entry) && self.opcode_at(entry).unwrap() >> 12 == 0x1 {
+                        pending.push(entry);
+                        entry += 2;
+                    }
+                },
+                _ => pending.push(addr + 2),
+            }
+        }
+
+        sites.sort_unstable();
+        sites
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(program: &[u8]) -> Vec<(usize, String)> {
+        lint(program, 0x200).into_iter().map(|lint| (lint.addr, lint.message)).collect()
+    }
+
+    fn only(program: &[u8], addr: usize, message: &str) {
+        let lints = lints(program);
+        assert_eq!(lints.len(), 1, "{:?}", lints);
+        assert_eq!(lints[0].0, addr);
+        assert!(lints[0].1.contains(message), "{}", lints[0].1);
+    }
+
+    // A chain of `depth` calls, each subroutine calls the next one
+    fn nested_calls(depth: usize) -> Vec<u8> {
+        let mut program = vec![0x22, 0x04, 0x12, 0x02];
+        for sub in 0..depth - 1 {
+            let next = 0x204 + (sub + 1) * 4;
+            program.extend_from_slice(&[0x20 | (next >> 8) as u8, next as u8, 0x00, 0xEE]);
+        }
+        program.extend_from_slice(&[0x00, 0xEE]);
+
+        program
+    }
+
+    #[test]
+    fn branches_to_odd_addresses_or_outside() {
+        only(&[0x12, 0x03, 0x00, 0x00], 0x200, "goes to an odd address");
+        only(&[0x13, 0x00], 0x200, "goes outside the program");
+        assert!(lints(&[0x12, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn skips_over_long_instructions() {
+        only(&[0x30, 0x01, 0xF0, 0x00, 0x12, 0x06, 0x12, 0x06], 0x200, "followed by F000 NNNN");
+        assert!(lints(&[0x30, 0x01, 0x60, 0x00, 0x12, 0x06, 0x12, 0x06]).is_empty());
+    }
+
+    #[test]
+    fn unreachable_code_but_not_sprites() {
+        only(&[0x12, 0x06, 0x60, 0x01, 0x12, 0x02, 0x12, 0x06], 0x202, "never reached");
+        assert!(lints(&[0xA2, 0x04, 0x12, 0x02, 0x60, 0x01, 0x12, 0x02]).is_empty());
+    }
+
+    #[test]
+    fn sprites_past_the_end_of_memory() {
+        only(&[0xAF, 0xFE, 0xD0, 0x15, 0x12, 0x04], 0x202, "past the end of memory");
+        assert!(lints(&[0xAF, 0xFB, 0xD0, 0x15, 0x12, 0x04]).is_empty());
+    }
+
+    #[test]
+    fn stores_into_code_or_past_memory() {
+        only(&[0xA2, 0x00, 0xF1, 0x55, 0x12, 0x04], 0x202, "writes into the code at 200");
+        only(&[0xAF, 0xFF, 0xF0, 0x33, 0x12, 0x04], 0x202, "writes past the end of memory");
+        assert!(lints(&[0xA3, 0x00, 0xF0, 0x33, 0x12, 0x04]).is_empty());
+    }
+
+    #[test]
+    fn recursion_and_deep_calls() {
+        only(&[0x22, 0x04, 0x12, 0x02, 0x22, 0x04, 0x00, 0xEE], 0x204, "recurses");
+
+        let program = nested_calls(MAX_CALL_DEPTH + 1);
+        let last = 0x204 + (MAX_CALL_DEPTH - 1) * 4;
+        only(&program, last, "nests 16 calls deep");
+
+        assert!(lints(&nested_calls(MAX_CALL_DEPTH)).is_empty());
+    }
+}
@@ -1,95 +1,395 @@
-mod emulator;
+mod cartridge;
+mod cli;
+mod config;
+mod debugger;
+mod frontend;
+mod report;
+mod rom;
+mod romdb;
 
 use std::env;
 use std::fs;
-use sdl2::event::Event;
-use std::time::Duration;
-use sdl2::keyboard::Keycode;
-use emulator::Emulator;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
 
-// For simplicity we'll assume width and height are multiples of our final mapping
-const WIDTH: u32 = 768;
-const HEIGHT: u32 = 576;
+use cli::{Command, RunOptions};
+use config::Config;
+use debugger::Debugger;
+use chip8::{asm, decompile, detect, disasm, lint};
+use chip8::emulator::{Emulator, PROGRAM_START};
+use chip8::emulator::framebuffer::{PIXELS_ALONG_X, PIXELS_ALONG_Y};
+use chip8::emulator::quirks::Quirks;
+use frontend::{Exit, Frontend};
+use frontend::palette::Palette;
+use rom::{read_rom_file, rom_hash, rom_name};
+
+// The initial window size is the chip8 display scaled up by this factor
+const DEFAULT_SCALE: u32 = 12;
+
+// Headless runs stop after this many frames (10 seconds) unless told otherwise
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+
+// Benchmarks run for this many frames (about half an hour of emulated time)
+const DEFAULT_BENCHMARK_FRAMES: u64 = 100_000;
 
 fn main() {
-    // Read file 
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!("Run with --help to see the usage.");
+            process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Run(mut options) => run(&mut options),
+        Command::Debug(mut options) => debug(&mut options),
+        Command::Disasm { rom, linear } => disasm(&rom, linear),
+        Command::Decompile { rom } => decompile(&rom),
+        Command::Lint { rom } => lint(&rom),
+        Command::Asm { source, output } => asm(&source, output),
+        Command::Info { rom } => info(&rom),
+        Command::Report(options) => report::report(&options),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
 
-    if args.len() < 2 {
-        println!("Usage: cargo run <filename>");
-        return
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
     }
+}
+
+// Fills in the options that weren't given on the command line from the config file
+// and the built-in ROM database
+fn apply_config(program: &[u8], options: &mut RunOptions) -> Result<(), String> {
+    let hash = rom_hash(program);
+    let known = romdb::lookup(&hash);
 
-    let file_path = &args[1];
-    let mut program: Vec<u8> = fs::read(file_path).expect("Cannot read the file");
+    if let Some(info) = &known {
+        match &info.author {
+            Some(author) => println!("Recognised '{}' by {}", info.title, author),
+            None => println!("Recognised '{}'", info.title),
+        }
+        if let Some(keys) = &info.keys {
+            println!("Keys: {}", keys);
+        }
+    }
 
-    // let mut screen = Display::new(WIDTH, HEIGHT);
-    let mut emulator = Emulator::new(WIDTH, HEIGHT);
+    let path = match &options.config {
+        _ if options.no_config => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => Config::default_path(),
+    };
 
-    emulator.read_rom(&mut program);
-    emulator.run();
+    if let (Some(path), Some(_)) = (&path, &options.config) {
+        if !path.exists() {
+            return Err(format!("config '{}' doesn't exist", path.display()));
+        }
+    }
+
+    let config = match &path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    if let (Some(path), Some(profile)) = (&path, config.roms.get(&hash)) {
+        let name = profile.name.as_deref().unwrap_or(&options.rom);
+        println!("Using the settings for '{}' from {}", name, path.display());
+    }
+
+    if let Some(detection) = configure_rom(program, &config, options)? {
+        println!("Detected platform: {}", detection.describe());
+    }
+
+    Ok(())
 }
 
-impl Emulator {
-    fn run(&mut self) {
-        let mut instructions: Vec<u16> = Vec::new();
+// Layers the config and the built-in ROM database under the options. When neither the
+// command line, the config nor the database chose, the ROM gets the platform and quirks
+// its instructions suggest, and the detection is returned.
+fn configure_rom(program: &[u8], config: &Config, options: &mut RunOptions) -> Result<Option<detect::Detection>, String> {
+    let hash = rom_hash(program);
+    let known = romdb::lookup(&hash).map(|info| info.profile()).unwrap_or_default();
+    config.apply(&hash, &known, options)?;
 
-        // Convert to opcodes
-        for idx in (0..(self.program_size - 1)).step_by(2) {
-            let b1: u8 = self.read(self.PROGRAM_START + idx);
-            let b2: u8 = self.read(self.program_size + idx + 1);
+    if options.platform.is_some() || options.quirks.is_some() {
+        return Ok(None);
+    }
 
-            let opcode = (b1 as u16) << 8 | b2 as u16;
+    let detection = detect::detect(program);
+    options.platform = Some(detection.platform);
+    options.quirks = Quirks::preset(detection.quirks);
 
-            instructions.push(opcode);
-        }            
+    Ok(Some(detection))
+}
 
-        self.running = true;
-        self.cpu.PC = self.PROGRAM_START as u16;
+// Sets an emulator up as described by the options (and the config) and loads the ROM into it
+fn create_emulator(options: &mut RunOptions) -> Result<Emulator, String> {
+    // Cartridges come with the settings they were made with, the command line still wins
+    if cartridge::is_cartridge(&options.rom) {
+        cartridge::read_cartridge(&options.rom)?.options.apply(options)?;
+    }
 
-        let mut count = 0;
+    let program = read_rom_file(&options.rom)?;
+    apply_config(&program, options)?;
 
-        while self.running {
-            let mut key_event_triggered: Vec<Event> = Vec::new();
+    let mut emulator = build_emulator(options);
+    // Nothing else shows the problems of a ROM while it runs on the command line
+    emulator.set_print_warnings(true);
 
-            // Handle Events
-            for event in self.event_pump.poll_iter() {
-                match event {
-                    Event::Quit {..} |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                        self.running = false;
-                    },
-                    Event::KeyDown {..} => {
-                        //println!("{:#?}", event);
-                        key_event_triggered.push(event);
-                    }
-                    _ => {
-                    }
-                }
-            }
+    if let Some(path) = &options.trace {
+        let out: Box<dyn io::Write + Send> = if path == "-" {
+            Box::new(io::stdout())
+        }
+        else {
+            let file = fs::File::create(path)
+                .map_err(|err| format!("cannot create trace file '{}': {}", path, err))?;
+            Box::new(io::BufWriter::new(file))
+        };
+        emulator.set_trace(Some(out));
+    }
 
-            // Fetch
-            let pc: usize = self.cpu.PC as usize;
-            let opcode = self.convert_to_opcode(self.read(pc), self.read(pc + 1));
-            self.cpu.PC = (self.cpu.PC + 2) % ((self.memory.len() - 1) as u16);
+    emulator.load_rom(&program)
+        .map_err(|err| format!("cannot load ROM '{}': {}", options.rom, err))?;
 
-            print!("Execute [{:#x}]: ", opcode);
+    if let Some(path) = &options.state {
+        let state = fs::read(path)
+            .map_err(|err| format!("cannot read save state '{}': {}", path, err))?;
+        emulator.load_state(&state)
+            .map_err(|err| format!("cannot load save state '{}': {}", path, err))?;
+    }
+
+    Ok(emulator)
+}
+
+// An emulator with the platform, quirks, speed and seed of the options
+fn build_emulator(options: &RunOptions) -> Emulator {
+    let mut builder = Emulator::builder();
+    if let Some(platform) = options.platform {
+        builder = builder.platform(platform);
+    }
+    if let Some(quirks) = options.quirks {
+        builder = builder.quirks(quirks);
+    }
+    if let Some(speed) = options.speed {
+        builder = builder.speed(speed);
+    }
+    if let Some(seed) = options.seed {
+        builder = builder.seed(seed);
+    }
+
+    builder.build()
+}
+
+fn run(options: &mut RunOptions) -> Result<(), String> {
+    if options.benchmark {
+        return benchmark(options);
+    }
 
-            // Decode & Execute
-            self.execute(opcode, key_event_triggered);
+    if options.headless {
+        let mut emulator = create_emulator(options)?;
+        let frames = options.frames.unwrap_or(DEFAULT_HEADLESS_FRAMES);
 
-            // Render Canvas
-            self.screen.render();
+        for _ in 0..frames {
+            emulator.run_frame();
+        }
+
+        print!("{}", emulator.framebuffer().to_ascii());
+        return Ok(());
+    }
+
+    let scale = options.scale.unwrap_or(DEFAULT_SCALE);
+    let mut frontend = Frontend::new(PIXELS_ALONG_X * scale, PIXELS_ALONG_Y * scale);
+
+    frontend.screen.set_integer_scaling(options.integer_scaling);
+    if let Some(fast_forward) = options.fast_forward {
+        frontend.speed.fast_forward = fast_forward;
+    }
+    if options.fullscreen {
+        frontend.screen.toggle_fullscreen();
+    }
+
+    // A directory opens the ROM browser, and so does F1 later on. ROMs dropped onto the
+    // window are loaded right away.
+    let mut rom = PathBuf::from(&options.rom);
+    let mut browse_dir = PathBuf::from(".");
+
+    loop {
+        if rom.is_dir() {
+            browse_dir = rom.clone();
+            rom = match frontend.browse(&browse_dir) {
+                Some(rom) => rom,
+                None => return Ok(()),
+            };
+        }
+
+        // Every ROM starts from the command line options, the config is applied per ROM
+        let mut rom_options = options.clone();
+        rom_options.rom = rom.to_string_lossy().into_owned();
 
-            if self.cpu.timer.DT != 0 {
-                // count += 1;
+        let mut emulator = match create_emulator(&mut rom_options) {
+            Ok(emulator) => emulator,
+            // Picked in the window, so go back to picking instead of quitting
+            Err(err) if options.rom != rom_options.rom => {
+                eprintln!("error: {}", err);
+                rom = browse_dir.clone();
+                continue;
+            },
+            Err(err) => return Err(err),
+        };
 
-                //if count % 30 == 0 {
-                    self.cpu.timer.DT -= 1;
-                // }
-            }
+        configure_frontend(&mut frontend, &rom_options);
 
-            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        match frontend.run(&mut emulator, options.frames) {
+            Exit::Quit => return Ok(()),
+            Exit::Load(path) => rom = path,
+            Exit::Browse => rom = browse_dir.clone(),
         }
     }
 }
+
+// Runs the frames without a window or waiting in between, and measures how long that took
+fn benchmark(options: &mut RunOptions) -> Result<(), String> {
+    let mut emulator = create_emulator(options)?;
+    let frames = options.frames.unwrap_or(DEFAULT_BENCHMARK_FRAMES);
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        emulator.run_frame();
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    let instructions = emulator.instructions_executed();
+    println!("{} frames, {} instructions in {:.2}s: {:.1} million instructions per second",
+        frames, instructions, seconds, instructions as f64 / seconds / 1_000_000.0);
+
+    Ok(())
+}
+
+// Applies the per-ROM options that live in the frontend
+fn configure_frontend(frontend: &mut Frontend, options: &RunOptions) {
+    // Custom colours override the matching entries of the chosen palette
+    let mut palette = options.palette.unwrap_or_default();
+    if options.foreground.is_some() || options.background.is_some() {
+        palette = Palette::custom(
+            options.foreground.unwrap_or(palette.colors[1]),
+            options.background.unwrap_or(palette.colors[0]),
+        );
+    }
+
+    frontend.screen.set_palette(palette);
+    if let Some(filter) = options.filter {
+        frontend.screen.set_filter(filter);
+    }
+    frontend.keymap = options.keymap.unwrap_or_default();
+    frontend.state_base = Some(PathBuf::from(&options.rom));
+    frontend.watch = options.watch;
+
+    let title = format!("Chip8 Emulator - {}", rom_name(&options.rom));
+    frontend.screen.canvas.window_mut().set_title(&title)
+        .expect("Could not set the window title.");
+}
+
+fn debug(options: &mut RunOptions) -> Result<(), String> {
+    let mut emulator = create_emulator(options)?;
+
+    Debugger::new().run(&mut emulator);
+
+    Ok(())
+}
+
+fn disasm(path: &str, linear: bool) -> Result<(), String> {
+    let program = read_rom_file(path)?;
+
+    if linear {
+        print!("{}", disasm::listing(&program, PROGRAM_START));
+    }
+    else {
+        print!("{}", disasm::disassemble(&program, PROGRAM_START));
+    }
+
+    Ok(())
+}
+
+fn decompile(path: &str) -> Result<(), String> {
+    let program = read_rom_file(path)?;
+
+    print!("{}", decompile::decompile(&program, PROGRAM_START));
+
+    Ok(())
+}
+
+fn lint(path: &str) -> Result<(), String> {
+    let program = read_rom_file(path)?;
+    let lints = lint::lint(&program, PROGRAM_START);
+
+    for lint in &lints {
+        println!("{:03X}: {}", lint.addr, lint.message);
+    }
+
+    match lints.len() {
+        0 => println!("No problems found"),
+        1 => println!("1 problem found"),
+        count => println!("{} problems found", count),
+    }
+
+    Ok(())
+}
+
+// Writes the ROM and a symbol file with the labels next to it
+fn asm(source: &str, output: Option<String>) -> Result<(), String> {
+    let assembly = asm::assemble_file(Path::new(source)).map_err(|err| err.to_string())?;
+
+    let output = output.map_or_else(|| Path::new(source).with_extension("ch8"), PathBuf::from);
+    let symbols = output.with_extension("sym");
+
+    fs::write(&output, &assembly.program)
+        .map_err(|err| format!("cannot write ROM '{}': {}", output.display(), err))?;
+    fs::write(&symbols, assembly.symbol_file())
+        .map_err(|err| format!("cannot write symbols '{}': {}", symbols.display(), err))?;
+
+    println!("{}: {} bytes, {} labels", output.display(), assembly.program.len(), assembly.labels.len());
+
+    Ok(())
+}
+
+fn info(path: &str) -> Result<(), String> {
+    let program = read_rom_file(path)?;
+
+    let opcodes: Vec<u16> = program.chunks_exact(2)
+        .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+        .collect();
+    let unknown = opcodes.iter()
+        .filter(|opcode| disasm::mnemonic(**opcode).is_none())
+        .count();
+
+    let hash = rom_hash(&program);
+
+    println!("File:         {}", path);
+    if let Some(rom) = romdb::lookup(&hash) {
+        println!("Title:        {}", rom.title);
+        if let Some(author) = &rom.author {
+            println!("Author:       {}", author);
+        }
+        if let Some(platform) = &rom.platform {
+            println!("Platform:     {}", platform);
+        }
+        if let Some(keys) = &rom.keys {
+            println!("Keys:         {}", keys);
+        }
+    }
+    println!("Size:         {} bytes", program.len());
+    println!("SHA-1:        {}", hash);
+    println!("Loaded at:    {:#05x} - {:#05x}", 0x200, 0x200 + program.len() - 1);
+    println!("Words:        {} ({} don't decode to chip8 instructions, usually data)", opcodes.len(), unknown);
+
+    Ok(())
+}
@@ -0,0 +1,1057 @@
+//! A compiler for Octo, the high level assembly language most modern chip8, SUPER-CHIP
+//! and XO-CHIP programs are written in.
+//!
+//! Statements are whitespace separated words: `: name` defines a label, `vx := value`,
+//! `vx += vy` and friends are arithmetic, `loop ... again`, `while`, `if ... then` and
+//! `if ... begin ... else ... end` are control flow, a bare label is a call. `:const`,
+//! `:alias`, `:macro`, `:calc`, `:unpack`, `:next`, `:org` and `:byte` are supported,
+//! string mode and assertions aren't. Execution starts at the label `main`.
+//!
+//! ```
+//! use chip8::octo::compile;
+//!
+//! let program = compile("
+//!     : main
+//!         v0 := 5
+//!         loop
+//!             v0 += -1
+//!             if v0 == 0 then return
+//!         again
+//! ").unwrap();
+//!
+//! assert_eq!(program, [0x12, 0x02, 0x60, 0x05, 0x70, 0xFF, 0x40, 0x00, 0x00, 0xEE, 0x12, 0x04]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::emulator::PROGRAM_START;
+
+// XO-CHIP programs can fill 64K of memory
+const ADDRESS_SPACE: usize = 0x10000;
+
+// Macros that expand into themselves are stopped after this many expansions
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Where compiling stopped
+#[derive(Debug)]
+pub struct OctoError {
+    /// Counted from 1
+    pub line: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+// How a reference to a label that isn't defined yet is patched in once it is
+#[derive(Clone, Copy)]
+enum Fixup {
+    // The low 12 bits of the instruction at the address
+    Addr12,
+    // Both bytes at the address, for i := long
+    Addr16,
+    // The byte after the address, the high half of :unpack with this nibble in front
+    UnpackHi(u8),
+    // The byte after the address, the high half of :unpack long
+    UnpackLongHi,
+    // The byte after the address, the low half of :unpack
+    UnpackLo,
+}
+
+// The right hand side of a comparison
+enum Operand {
+    Register(u8),
+    Value(u8),
+}
+
+type Result<T> = std::result::Result<T, OctoError>;
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for OctoError {}
+
+/// Compiles Octo source into a program loaded at 0x200
+pub fn compile(source: &str) -> Result<Vec<u8>> {
+    let mut compiler = Compiler::new(tokenize(source));
+    compiler.compile()?;
+
+    Ok(compiler.rom)
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        for word in line.split_whitespace() {
+            if word.starts_with('#') {
+                break;
+            }
+            tokens.push(Token { text: word.to_string(), line: idx + 1 });
+        }
+    }
+
+    tokens
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    // The line of the last token, for errors
+    line: usize,
+    // From PROGRAM_START on
+    rom: Vec<u8>,
+    here: usize,
+    labels: BTreeMap<String, usize>,
+    constants: BTreeMap<String, f64>,
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, Macro>,
+    expansions: usize,
+    // Label -> where it is used before it is defined
+    protos: BTreeMap<String, Vec<(usize, Fixup, usize)>>,
+    // The start of each open loop and the jumps out of it of its whiles
+    loops: Vec<(usize, Vec<usize>)>,
+    // The jumps of open if ... begin and else blocks, patched at their else or end
+    branches: Vec<usize>,
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>) -> Compiler {
+        Compiler {
+            tokens,
+            pos: 0,
+            line: 1,
+            rom: Vec::new(),
+            here: PROGRAM_START,
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            expansions: 0,
+            protos: BTreeMap::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        Err(OctoError { line: self.line, message })
+    }
+
+    fn compile(&mut self) -> Result<()> {
+        // The jump to main, patched at the end
+        self.emit_word(0x1000)?;
+
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some((name, uses)) = self.protos.iter().next() {
+            self.line = uses[0].2;
+            return self.error(format!("'{}' isn't defined", name));
+        }
+        if !self.loops.is_empty() {
+            return self.error("a loop is missing its again".to_string());
+        }
+        if !self.branches.is_empty() {
+            return self.error("an if ... begin is missing its end".to_string());
+        }
+
+        let main = match self.labels.get("main") {
+            Some(main) => *main,
+            None => return self.error("the program has no main label".to_string()),
+        };
+        self.patch(PROGRAM_START, Fixup::Addr12, main)?;
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                self.line = token.line;
+                Ok(token.text.clone())
+            },
+            None => self.error("unexpected end of the program".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected '{}', got '{}'", expected, token));
+        }
+
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<()> {
+        if self.here >= ADDRESS_SPACE {
+            return self.error("the program doesn't fit into memory".to_string());
+        }
+
+        let offset = self.here - PROGRAM_START;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<()> {
+        self.emit((word >> 8) as u8)?;
+        self.emit(word as u8)
+    }
+
+    fn patch(&mut self, addr: usize, fixup: Fixup, value: usize) -> Result<()> {
+        let offset = addr - PROGRAM_START;
+
+        match fixup {
+            Fixup::Addr12 => {
+                if value > 0xFFF {
+                    return self.error(format!("{:#x} is out of reach, only 0x000 to 0xFFF can be used here", value));
+                }
+                self.rom[offset] = self.rom[offset] & 0xF0 | (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            },
+            Fixup::Addr16 => {
+                self.rom[offset] = (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            },
+            Fixup::UnpackHi(nibble) => self.rom[offset + 1] = nibble << 4 | (value >> 8) as u8 & 0x0F,
+            Fixup::UnpackLongHi => self.rom[offset + 1] = (value >> 8) as u8,
+            Fixup::UnpackLo => self.rom[offset + 1] = value as u8,
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        let token = self.next()?;
+
+        if let Some(register) = self.register(&token) {
+            return self.register_statement(register);
+        }
+
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)
+            },
+            ":const" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let value = self.number(&token)?;
+                self.define_constant(name, value)
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let register = self.expect_register(&token)?;
+                self.aliases.insert(name, register);
+                Ok(())
+            },
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.define_constant(name, value)
+            },
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.next()?;
+                    self.calc()?
+                }
+                else {
+                    let token = self.next()?;
+                    self.number(&token)?
+                };
+                let byte = self.ranged(value, -128, 255)?;
+                self.emit(byte as u8)
+            },
+            ":org" => {
+                let token = self.next()?;
+                let value = self.number(&token)?;
+                let addr = self.ranged(value, PROGRAM_START as i64, ADDRESS_SPACE as i64 - 1)?;
+                self.here = addr as usize;
+                Ok(())
+            },
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)
+            },
+            ":unpack" => {
+                // v0 and v1 get the address, with a nibble in front of it or all 16 bits
+                let token = self.next()?;
+                let high = match token.as_str() {
+                    "long" => Fixup::UnpackLongHi,
+                    _ => {
+                        let value = self.number(&token)?;
+                        Fixup::UnpackHi(self.ranged(value, 0, 15)? as u8)
+                    },
+                };
+
+                let token = self.next()?;
+                let addr = self.here;
+                self.emit_word(0x6000)?;
+                self.emit_word(0x6100)?;
+
+                self.reference(&token, addr, high)?;
+                self.reference(&token, addr + 2, Fixup::UnpackLo)
+            },
+            ":call" => {
+                let token = self.next()?;
+                self.instruction_with_addr(0x2000, &token)
+            },
+            ":macro" => self.define_macro(),
+            // Only meaningful to Octo's debugger
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            },
+            ";" | "return" => self.emit_word(0x00EE),
+            "clear" => self.emit_word(0x00E0),
+            "exit" => self.emit_word(0x00FD),
+            "lores" => self.emit_word(0x00FE),
+            "hires" => self.emit_word(0x00FF),
+            "scroll-left" => self.emit_word(0x00FC),
+            "scroll-right" => self.emit_word(0x00FB),
+            "scroll-down" | "scroll-up" => {
+                let rows = self.nibble()?;
+                let opcode = if token == "scroll-down" { 0x00C0 } else { 0x00D0 };
+                self.emit_word(opcode | rows)
+            },
+            "audio" => self.emit_word(0xF002),
+            "plane" => {
+                let plane = self.nibble()?;
+                if plane > 3 {
+                    return self.error(format!("plane {} doesn't exist, there are 0 to 3", plane));
+                }
+                self.emit_word(0xF001 | plane << 8)
+            },
+            "jump" => {
+                let token = self.next()?;
+                self.instruction_with_addr(0x1000, &token)
+            },
+            "jump0" => {
+                let token = self.next()?;
+                self.instruction_with_addr(0xB000, &token)
+            },
+            "native" => {
+                let token = self.next()?;
+                self.instruction_with_addr(0x0000, &token)
+            },
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let rows = self.nibble()?;
+                self.emit_word(0xD000 | x << 8 | y << 4 | rows)
+            },
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit_word(0xF033 | x << 8)
+            },
+            "save" | "load" => {
+                let x = self.next_register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.next_register()?;
+                    let opcode = if token == "save" { 0x5002 } else { 0x5003 };
+                    return self.emit_word(opcode | x << 8 | y << 4);
+                }
+                let opcode = if token == "save" { 0xF055 } else { 0xF065 };
+                self.emit_word(opcode | x << 8)
+            },
+            "saveflags" | "loadflags" => {
+                let x = self.next_register()?;
+                let opcode = if token == "saveflags" { 0xF075 } else { 0xF085 };
+                self.emit_word(opcode | x << 8)
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                let opcode = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit_word(opcode | x << 8)
+            },
+            "i" => self.i_statement(),
+            "loop" => {
+                self.loops.push((self.here, Vec::new()));
+                Ok(())
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error("while outside of a loop".to_string());
+                }
+                self.skip_if(false)?;
+                let jump = self.here;
+                self.emit_word(0x1000)?;
+                self.loops.last_mut().unwrap().1.push(jump);
+                Ok(())
+            },
+            "again" => {
+                let (start, whiles) = match self.loops.pop() {
+                    Some(open) => open,
+                    None => return self.error("again without a loop".to_string()),
+                };
+                let jump = self.here;
+                self.emit_word(0x1000)?;
+                self.patch(jump, Fixup::Addr12, start)?;
+                for jump in whiles {
+                    self.patch(jump, Fixup::Addr12, self.here)?;
+                }
+                Ok(())
+            },
+            "if" => self.if_statement(),
+            "else" => {
+                let jump = match self.branches.pop() {
+                    Some(jump) => jump,
+                    None => return self.error("else without an if ... begin".to_string()),
+                };
+                self.branches.push(self.here);
+                self.emit_word(0x1000)?;
+                self.patch(jump, Fixup::Addr12, self.here)
+            },
+            "end" => {
+                let jump = match self.branches.pop() {
+                    Some(jump) => jump,
+                    None => return self.error("end without an if ... begin".to_string()),
+                };
+                self.patch(jump, Fixup::Addr12, self.here)
+            },
+            _ if self.macros.contains_key(&token) => self.expand_macro(&token),
+            _ if token.starts_with(':') => self.error(format!("'{}' isn't supported", token)),
+            _ => match parse_number(&token).or_else(|| self.constants.get(&token).copied()) {
+                // Bare numbers are data
+                Some(value) => {
+                    let byte = self.ranged(value, -128, 255)?;
+                    self.emit(byte as u8)
+                },
+                // And bare names calls
+                None if is_name(&token) => self.instruction_with_addr(0x2000, &token),
+                None => self.error(format!("unexpected '{}'", token)),
+            },
+        }
+    }
+
+    // vx := ..., vx += ... and the other operations on a register
+    fn register_statement(&mut self, x: u8) -> Result<()> {
+        let x = x as u16;
+        let operator = self.next()?;
+        let token = self.next()?;
+
+        if let Some(y) = self.register(&token) {
+            let y = y as u16;
+            let operation = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("'{}' doesn't work with two registers", operator)),
+            };
+            return self.emit_word(0x8000 | x << 8 | y << 4 | operation);
+        }
+
+        match (operator.as_str(), token.as_str()) {
+            (":=", "random") => {
+                let mask = self.next_byte()?;
+                self.emit_word(0xC000 | x << 8 | mask)
+            },
+            (":=", "key") => self.emit_word(0xF00A | x << 8),
+            (":=", "delay") => self.emit_word(0xF007 | x << 8),
+            (":=", _) => {
+                let byte = self.byte(&token)?;
+                self.emit_word(0x6000 | x << 8 | byte)
+            },
+            ("+=", _) => {
+                let byte = self.byte(&token)?;
+                self.emit_word(0x7000 | x << 8 | byte)
+            },
+            ("-=", _) => {
+                let byte = self.byte(&token)?;
+                self.emit_word(0x7000 | x << 8 | (byte as u8).wrapping_neg() as u16)
+            },
+            _ => self.error(format!("'{}' doesn't work with '{}'", operator, token)),
+        }
+    }
+
+    fn i_statement(&mut self) -> Result<()> {
+        let operator = self.next()?;
+        let token = self.next()?;
+
+        match (operator.as_str(), token.as_str()) {
+            ("+=", _) => {
+                let x = self.expect_register(&token)? as u16;
+                self.emit_word(0xF01E | x << 8)
+            },
+            (":=", "hex") => {
+                let x = self.next_register()?;
+                self.emit_word(0xF029 | x << 8)
+            },
+            (":=", "bighex") => {
+                let x = self.next_register()?;
+                self.emit_word(0xF030 | x << 8)
+            },
+            (":=", "long") => {
+                let token = self.next()?;
+                self.emit_word(0xF000)?;
+                let addr = self.here;
+                self.emit_word(0x0000)?;
+                self.reference(&token, addr, Fixup::Addr16)
+            },
+            (":=", _) => self.instruction_with_addr(0xA000, &token),
+            _ => self.error(format!("'i {} {}' isn't an instruction", operator, token)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<()> {
+        // Finds out whether it is then or begin before the condition is compiled
+        let mut end = self.pos;
+        while end < self.tokens.len() && !matches!(self.tokens[end].text.as_str(), "then" | "begin") {
+            end += 1;
+        }
+        if end == self.tokens.len() {
+            return self.error("if without then or begin".to_string());
+        }
+
+        if self.tokens[end].text == "then" {
+            // Skips the next statement unless the condition holds
+            self.skip_if(true)?;
+            self.expect("then")
+        }
+        else {
+            // Skips the jump past the block if the condition holds
+            self.skip_if(false)?;
+            self.expect("begin")?;
+            self.branches.push(self.here);
+            self.emit_word(0x1000)
+        }
+    }
+
+    // Compiles a condition into an instruction that skips the next one if the condition
+    // holds, or if it doesn't when negated. Comparisons go through VF.
+    fn skip_if(&mut self, negated: bool) -> Result<()> {
+        let x = self.next_register()?;
+        let operator = self.next()?;
+
+        let (opcode, negation) = match operator.as_str() {
+            "key" => (0xE09E | x << 8, 0xE0A1 | x << 8),
+            "-key" => (0xE0A1 | x << 8, 0xE09E | x << 8),
+            "==" | "!=" => {
+                let (equal, unequal) = match self.operand()? {
+                    Operand::Register(y) => (0x5000 | x << 8 | (y as u16) << 4, 0x9000 | x << 8 | (y as u16) << 4),
+                    Operand::Value(byte) => (0x3000 | x << 8 | byte as u16, 0x4000 | x << 8 | byte as u16),
+                };
+                if operator == "==" { (equal, unequal) } else { (unequal, equal) }
+            },
+            "<" | ">" | "<=" | ">=" => {
+                let y = self.operand()?;
+                let x = Operand::Register(x as u8);
+
+                // VF ends up 1 if the first operand is >= the second
+                let ((first, second), expected) = match operator.as_str() {
+                    "<" => ((x, y), 0),
+                    ">=" => ((x, y), 1),
+                    ">" => ((y, x), 0),
+                    _ => ((y, x), 1),
+                };
+                self.compare(first, second)?;
+
+                (0x3F00 | expected, 0x4F00 | expected)
+            },
+            _ => return self.error(format!("'{}' isn't a comparison", operator)),
+        };
+
+        self.emit_word(if negated { negation } else { opcode })
+    }
+
+    // Sets VF to 1 if a >= b, 0 otherwise
+    fn compare(&mut self, a: Operand, b: Operand) -> Result<()> {
+        match (a, b) {
+            // vf := a; vf -= b
+            (a, Operand::Register(b)) => {
+                match a {
+                    Operand::Register(a) => self.emit_word(0x8F00 | (a as u16) << 4)?,
+                    Operand::Value(a) => self.emit_word(0x6F00 | a as u16)?,
+                }
+                self.emit_word(0x8F05 | (b as u16) << 4)
+            },
+            // vf := b; vf =- a
+            (Operand::Register(a), Operand::Value(b)) => {
+                self.emit_word(0x6F00 | b as u16)?;
+                self.emit_word(0x8F07 | (a as u16) << 4)
+            },
+            (Operand::Value(_), Operand::Value(_)) => self.error("a comparison needs a register".to_string()),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        let token = self.next()?;
+        match self.register(&token) {
+            Some(register) => Ok(Operand::Register(register)),
+            None => Ok(Operand::Value(self.byte(&token)? as u8)),
+        }
+    }
+
+    // An instruction with an address in its low 12 bits, the label can be defined later
+    fn instruction_with_addr(&mut self, opcode: u16, token: &str) -> Result<()> {
+        let addr = self.here;
+        self.emit_word(opcode)?;
+        self.reference(token, addr, Fixup::Addr12)
+    }
+
+    // Patches in the value of a number or label now, or once the label is defined
+    fn reference(&mut self, token: &str, addr: usize, fixup: Fixup) -> Result<()> {
+        if let Some(value) = self.lookup_addr(token) {
+            return self.patch(addr, fixup, value);
+        }
+
+        if !is_name(token) || self.register(token).is_some() {
+            return self.error(format!("'{}' isn't an address", token));
+        }
+
+        self.protos.entry(token.to_string()).or_default().push((addr, fixup, self.line));
+        Ok(())
+    }
+
+    fn lookup_addr(&self, token: &str) -> Option<usize> {
+        if let Some(addr) = self.labels.get(token) {
+            return Some(*addr);
+        }
+
+        parse_number(token).or_else(|| self.constants.get(token).copied())
+            .filter(|value| *value >= 0.0)
+            .map(|value| value as usize)
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<()> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.error(format!("'{}' is defined twice", name));
+        }
+
+        self.labels.insert(name.clone(), addr);
+
+        for (at, fixup, _) in self.protos.remove(&name).unwrap_or_default() {
+            self.patch(at, fixup, addr)?;
+        }
+
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: String, value: f64) -> Result<()> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.error(format!("'{}' is defined twice", name));
+        }
+
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    // :macro name params... { body }
+    fn define_macro(&mut self) -> Result<()> {
+        let name = self.name()?;
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(Token { text: token, line: self.line });
+        }
+
+        self.macros.insert(name, Macro { params, body, calls: 0 });
+        Ok(())
+    }
+
+    // Replaces a use of a macro with its body, with the arguments in place of the
+    // parameters and CALLS counting the uses
+    fn expand_macro(&mut self, name: &str) -> Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return self.error(format!("macro '{}' expands too often, does it use itself?", name));
+        }
+
+        let count = self.macros[name].params.len();
+        let mut args = BTreeMap::new();
+        for idx in 0..count {
+            let arg = self.next()?;
+            args.insert(self.macros[name].params[idx].clone(), arg);
+        }
+
+        let line = self.line;
+        let expansion = self.macros.get_mut(name).unwrap();
+        let calls = expansion.calls.to_string();
+        expansion.calls += 1;
+
+        let body: Vec<Token> = expansion.body.iter()
+            .map(|token| {
+                let text = match token.text.as_str() {
+                    "CALLS" => calls.clone(),
+                    text => args.get(text).cloned().unwrap_or_else(|| text.to_string()),
+                };
+                // Errors in the expansion point at the use of the macro
+                Token { text, line }
+            })
+            .collect();
+
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    // The expression of a :calc after its {, up to the }. Operators have no precedence
+    // and group from the right, as in Octo.
+    fn calc(&mut self) -> Result<f64> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64> {
+        let left = self.calc_term()?;
+
+        match self.peek() {
+            Some(operator) if is_binary_operator(operator) => {
+                let operator = self.next()?;
+                let right = self.calc_expression()?;
+                Ok(binary(&operator, left, right))
+            },
+            _ => Ok(left),
+        }
+    }
+
+    fn calc_term(&mut self) -> Result<f64> {
+        let token = self.next()?;
+
+        match token.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            "@" => {
+                let addr = self.calc_term()?;
+                let offset = (addr as usize).wrapping_sub(PROGRAM_START);
+                Ok(self.rom.get(offset).copied().unwrap_or(0) as f64)
+            },
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => match unary(&token) {
+                Some(operation) => Ok(operation(self.calc_term()?)),
+                None => match self.labels.get(&token) {
+                    Some(addr) => Ok(*addr as f64),
+                    None => self.number(&token),
+                },
+            },
+        }
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+
+        let digit = token.strip_prefix('v').or_else(|| token.strip_prefix('V'))?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&self, token: &str) -> Result<u8> {
+        match self.register(token) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register, got '{}'", token)),
+        }
+    }
+
+    fn next_register(&mut self) -> Result<u16> {
+        let token = self.next()?;
+        Ok(self.expect_register(&token)? as u16)
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let token = self.next()?;
+        if !is_name(&token) || self.register(&token).is_some() {
+            return self.error(format!("'{}' can't be used as a name", token));
+        }
+
+        Ok(token)
+    }
+
+    // A literal or a constant
+    fn number(&self, token: &str) -> Result<f64> {
+        match parse_number(token).or_else(|| self.constants.get(token).copied()) {
+            Some(value) => Ok(value),
+            None => self.error(format!("'{}' isn't a number or a constant", token)),
+        }
+    }
+
+    fn ranged(&self, value: f64, min: i64, max: i64) -> Result<i64> {
+        let value = value.floor() as i64;
+        if value < min || value > max {
+            return self.error(format!("{} is out of range ({} to {})", value, min, max));
+        }
+
+        Ok(value)
+    }
+
+    // Negative bytes are two's complement
+    fn byte(&self, token: &str) -> Result<u16> {
+        let value = self.number(token)?;
+        Ok(self.ranged(value, -128, 255)? as u8 as u16)
+    }
+
+    fn next_byte(&mut self) -> Result<u16> {
+        let token = self.next()?;
+        self.byte(&token)
+    }
+
+    fn nibble(&mut self) -> Result<u16> {
+        let token = self.next()?;
+        let value = self.number(&token)?;
+        Ok(self.ranged(value, 0, 15)? as u16)
+    }
+}
+
+// Decimal, 0x hex or 0b binary, maybe negative
+fn parse_number(token: &str) -> Option<f64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    }
+    else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    }
+    else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    }
+    else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value } as f64)
+}
+
+fn is_name(token: &str) -> bool {
+    !token.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == ':')
+        && !matches!(token, "{" | "}" | "(" | ")" | ";")
+}
+
+fn is_binary_operator(token: &str) -> bool {
+    matches!(token, "-" | "+" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "pow" | "min" | "max"
+        | "<" | ">" | "<=" | ">=" | "==" | "!=")
+}
+
+fn binary(operator: &str, a: f64, b: f64) -> f64 {
+    let int = |value: f64| value as i64;
+    let truth = |value: bool| if value { 1.0 } else { 0.0 };
+
+    match operator {
+        "-" => a - b,
+        "+" => a + b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        "&" => (int(a) & int(b)) as f64,
+        "|" => (int(a) | int(b)) as f64,
+        "^" => (int(a) ^ int(b)) as f64,
+        "<<" => int(a).wrapping_shl(int(b) as u32) as f64,
+        ">>" => int(a).wrapping_shr(int(b) as u32) as f64,
+        "pow" => a.powf(b),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "<" => truth(a < b),
+        ">" => truth(a > b),
+        "<=" => truth(a <= b),
+        ">=" => truth(a >= b),
+        "==" => truth(a == b),
+        _ => truth(a != b),
+    }
+}
+
+fn unary(operator: &str) -> Option<fn(f64) -> f64> {
+    let operation: fn(f64) -> f64 = match operator {
+        "-" => |value| -value,
+        "~" => |value| !(value as i64) as f64,
+        "!" => |value| if value == 0.0 { 1.0 } else { 0.0 },
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "exp" => f64::exp,
+        "log" => f64::ln,
+        "abs" => f64::abs,
+        "sqrt" => f64::sqrt,
+        "sign" => f64::signum,
+        "ceil" => f64::ceil,
+        "floor" => f64::floor,
+        _ => return None,
+    };
+
+    Some(operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().message
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let program = compile("
+            : main
+                i := sprite
+                jump main
+            : sprite
+                0xFF
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0xA2, 0x06, 0x12, 0x02, 0xFF]);
+        assert!(error(": main jump nowhere").contains("'nowhere' isn't defined"));
+        assert!(error(": main : main").contains("defined twice"));
+    }
+
+    #[test]
+    fn while_jumps_past_again() {
+        let program = compile("
+            : main
+                loop
+                    while v0 != 5
+                    v0 += 1
+                again
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0x40, 0x05, 0x12, 0x0A, 0x70, 0x01, 0x12, 0x02]);
+        assert!(error(": main loop").contains("missing its again"));
+        assert!(error(": main again").contains("again without a loop"));
+    }
+
+    #[test]
+    fn again_reports_loops_out_of_reach() {
+        assert!(error(": main return :org 0x1000 loop again").contains("out of reach"));
+    }
+
+    #[test]
+    fn if_then_and_if_begin_else_end() {
+        let program = compile("
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 key begin
+                    v2 := 3
+                else
+                    v2 := 4
+                end
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0x40, 0x01, 0x61, 0x02, 0xE0, 0x9E, 0x12, 0x0E,
+            0x62, 0x03, 0x12, 0x10, 0x62, 0x04]);
+        assert!(error(": main if v0 == 1 begin").contains("missing its end"));
+    }
+
+    #[test]
+    fn comparisons_go_through_vf() {
+        let program = compile("
+            : main
+                if v1 < v2 then v0 := 1
+                if v1 > 5 then v0 := 2
+                if v1 >= 5 then v0 := 3
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02,
+            0x8F, 0x10, 0x8F, 0x25, 0x4F, 0x00, 0x60, 0x01,
+            0x6F, 0x05, 0x8F, 0x15, 0x4F, 0x00, 0x60, 0x02,
+            0x6F, 0x05, 0x8F, 0x17, 0x4F, 0x01, 0x60, 0x03]);
+        assert!(error(": main if 1 < 2 then return").contains("expected a register"));
+    }
+
+    #[test]
+    fn macros_count_their_uses() {
+        let program = compile("
+            :macro number register { register := CALLS }
+            : main
+                number v0
+                number v1
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0x60, 0x00, 0x61, 0x01]);
+        assert!(error(":macro forever { forever } : main forever").contains("expands too often"));
+    }
+
+    #[test]
+    fn calc_groups_from_the_right() {
+        let program = compile("
+            :calc right { 2 * 3 + 4 }
+            :calc left { ( 2 * 3 ) + 4 }
+            : main
+                v0 := right
+                v1 := left
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0x60, 0x0E, 0x61, 0x0A]);
+    }
+
+    #[test]
+    fn unpack_splits_an_address_into_v0_and_v1() {
+        let program = compile("
+            : main
+                :unpack 0xA data
+                :unpack long data
+                return
+            : data
+        ").unwrap();
+
+        assert_eq!(program, [0x12, 0x02, 0x60, 0xA2, 0x61, 0x0C, 0x60, 0x02, 0x61, 0x0C, 0x00, 0xEE]);
+    }
+}
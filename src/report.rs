@@ -0,0 +1,395 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+
+use chip8::emulator::error::Warning;
+use chip8::emulator::framebuffer::{PIXELS_ALONG_X, PIXELS_ALONG_Y};
+use crate::cartridge::{is_cartridge, read_cartridge};
+use crate::cli::{ReportOptions, RunOptions};
+use crate::config::Config;
+use crate::frontend::browser::is_rom;
+use crate::rom::read_rom_file;
+use crate::{build_emulator, configure_rom, DEFAULT_HEADLESS_FRAMES};
+
+// Reports have to come out the same on every run to be compared, so CXNN is seeded
+const DEFAULT_SEED: u64 = 0;
+
+type Screen = [[u8; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize];
+
+enum Outcome {
+    Ran,
+    // The ROM couldn't be read or configured
+    Failed(String),
+    // The emulator panicked, which is always a bug of the emulator
+    Panicked { frame: u64, message: String },
+}
+
+// How often a kind of problem came up, and the first time it did
+#[derive(Default)]
+struct Tally {
+    count: u64,
+    first: Option<Warning>,
+}
+
+struct RomReport {
+    name: String,
+    platform: String,
+    outcome: Outcome,
+    // Opcode -> where it was first seen
+    unknown_opcodes: BTreeMap<u16, u16>,
+    stack_errors: Tally,
+    out_of_bounds: Tally,
+    // Whether a pixel was lit at the end of any frame
+    drew: bool,
+    screen: Screen,
+}
+
+thread_local! {
+    // Set while a ROM runs on this thread, its panics go into the report instead of stderr
+    static RUNNING_ROM: Cell<bool> = const { Cell::new(false) };
+    // The message of the last panic of a ROM on this thread, set by the panic hook
+    static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn report(options: &ReportOptions) -> Result<(), String> {
+    let dir = Path::new(&options.dir);
+
+    let mut roms = Vec::new();
+    find_roms(dir, &mut roms)?;
+    roms.sort();
+
+    if roms.is_empty() {
+        return Err(format!("no ROMs in '{}'", dir.display()));
+    }
+
+    let jobs = options.jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()))
+        .min(roms.len());
+
+    install_panic_hook();
+
+    let next = AtomicUsize::new(0);
+    let reports: Mutex<Vec<Option<RomReport>>> = Mutex::new(roms.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let path = match roms.get(idx) {
+                    Some(path) => path,
+                    None => break,
+                };
+
+                let name = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().into_owned();
+                let report = run_rom(name, path, &options.run);
+
+                reports.lock().unwrap()[idx] = Some(report);
+            });
+        }
+    });
+
+    let reports: Vec<RomReport> = reports.into_inner().unwrap().into_iter().flatten().collect();
+    let text = format_reports(&reports);
+
+    match &options.output {
+        Some(path) => fs::write(path, text)
+            .map_err(|err| format!("cannot write report '{}': {}", path, err)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        },
+    }
+}
+
+// Collects the ROMs in `dir` and its subdirectories
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|err| format!("cannot read directory '{}': {}", dir.display(), err))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        }
+        else if is_rom(&path) {
+            roms.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// Panics of ROMs are part of the report, the default hook would print them in between.
+// Hooks are global, so this one is only installed once and hands every other panic to the
+// hook that was there before.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !RUNNING_ROM.with(Cell::get) {
+                return previous(info);
+            }
+
+            let message = match info.location() {
+                Some(location) => format!("{} ({}:{})", panic_message(info.payload()), location.file(), location.line()),
+                None => panic_message(info.payload()),
+            };
+            PANIC.with(|panic| *panic.borrow_mut() = Some(message));
+        }));
+    });
+}
+
+fn run_rom(name: String, path: &Path, options: &RunOptions) -> RomReport {
+    let mut options = options.clone();
+    let given = has_platform(&options);
+
+    let path = path.to_string_lossy();
+    let program = read_rom_file(&path).and_then(|program| {
+        // Cartridges come with the settings they were made with, the command line still wins
+        if is_cartridge(&path) {
+            read_cartridge(&path)?.options.apply(&mut options)?;
+        }
+
+        Ok(program)
+    });
+
+    let source = match () {
+        _ if given => Some("given"),
+        _ if has_platform(&options) => Some("cartridge"),
+        _ => None,
+    };
+
+    match program {
+        Ok(program) => run_program(name, &program, options, source),
+        Err(err) => RomReport { outcome: Outcome::Failed(err), ..RomReport::new(name) },
+    }
+}
+
+fn has_platform(options: &RunOptions) -> bool {
+    options.platform.is_some() || options.quirks.is_some()
+}
+
+// Runs a program and records what it ran into. `source` tells where the platform came from
+// if it was chosen before, otherwise the database or the detection choose it.
+fn run_program(name: String, program: &[u8], mut options: RunOptions, source: Option<&str>) -> RomReport {
+    let mut report = RomReport::new(name);
+
+    // Reports have to come out the same on every machine, so the user config is left out
+    let detected = match configure_rom(program, &Config::default(), &mut options) {
+        Ok(detection) => detection.is_some(),
+        Err(err) => {
+            report.outcome = Outcome::Failed(err);
+            return report;
+        },
+    };
+
+    if options.seed.is_none() {
+        options.seed = Some(DEFAULT_SEED);
+    }
+
+    let platform = options.platform.map_or("chip8", |platform| platform.name());
+    let source = source.unwrap_or(if detected { "detected" } else { "database" });
+    report.platform = format!("{} ({})", platform, source);
+
+    let mut emulator = build_emulator(&options);
+    if let Err(err) = emulator.load_rom(program) {
+        report.outcome = Outcome::Failed(err.to_string());
+        return report;
+    }
+
+    let frames = options.frames.unwrap_or(DEFAULT_HEADLESS_FRAMES);
+
+    RUNNING_ROM.with(|running| running.set(true));
+    for frame in 0..frames {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            emulator.run_frame_with_warnings(|warning| report.add(warning));
+        }));
+
+        if result.is_err() {
+            let message = PANIC.with(|panic| panic.borrow_mut().take()).unwrap_or_default();
+            report.outcome = Outcome::Panicked { frame, message };
+            break;
+        }
+
+        let screen = &emulator.framebuffer().pixel_repr;
+        report.drew |= screen.iter().flatten().any(|pixel| *pixel != 0);
+    }
+    RUNNING_ROM.with(|running| running.set(false));
+
+    report.screen = emulator.framebuffer().pixel_repr;
+
+    report
+}
+
+impl Tally {
+    fn add(&mut self, warning: Warning) {
+        self.count += 1;
+        self.first = self.first.or(Some(warning));
+    }
+
+    fn describe(&self) -> String {
+        match self.first {
+            Some(first) => format!("{}, first: {}", self.count, first),
+            None => "none".to_string(),
+        }
+    }
+}
+
+impl RomReport {
+    fn new(name: String) -> RomReport {
+        RomReport {
+            name,
+            platform: String::new(),
+            outcome: Outcome::Ran,
+            unknown_opcodes: BTreeMap::new(),
+            stack_errors: Tally::default(),
+            out_of_bounds: Tally::default(),
+            drew: false,
+            screen: [[0; PIXELS_ALONG_X as usize]; PIXELS_ALONG_Y as usize],
+        }
+    }
+
+    fn add(&mut self, warning: Warning) {
+        match warning {
+            Warning::UnknownOpcode { opcode, addr } => {
+                self.unknown_opcodes.entry(opcode).or_insert(addr);
+            },
+            Warning::InvalidReturn { .. } | Warning::StackOverflow { .. } => self.stack_errors.add(warning),
+            Warning::OutOfBounds { .. } => self.out_of_bounds.add(warning),
+        }
+    }
+
+    fn has_problems(&self) -> bool {
+        !matches!(self.outcome, Outcome::Ran) || !self.unknown_opcodes.is_empty()
+            || self.stack_errors.count > 0 || self.out_of_bounds.count > 0
+    }
+}
+
+fn format_reports(reports: &[RomReport]) -> String {
+    let mut text = String::new();
+
+    for report in reports {
+        let _ = writeln!(text, "{}", report.name);
+
+        match &report.outcome {
+            Outcome::Failed(err) => {
+                let _ = writeln!(text, "  Result:          failed: {}\n", err);
+                continue;
+            },
+            Outcome::Panicked { frame, message } => {
+                let _ = writeln!(text, "  Result:          panicked in frame {}: {}", frame, message);
+            },
+            Outcome::Ran => {
+                let _ = writeln!(text, "  Result:          ran");
+            },
+        }
+
+        let unknown_opcodes = if report.unknown_opcodes.is_empty() {
+            "none".to_string()
+        }
+        else {
+            report.unknown_opcodes.iter()
+                .map(|(opcode, addr)| format!("{:#06x} at {:#x}", opcode, addr))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let _ = writeln!(text, "  Platform:        {}", report.platform);
+        let _ = writeln!(text, "  Unknown opcodes: {}", unknown_opcodes);
+        let _ = writeln!(text, "  Stack errors:    {}", report.stack_errors.describe());
+        let _ = writeln!(text, "  Out of bounds:   {}", report.out_of_bounds.describe());
+        let _ = writeln!(text, "  Drew:            {}", if report.drew { "yes" } else { "no" });
+        let _ = writeln!(text, "{}", thumbnail(&report.screen));
+    }
+
+    let count = |matches: fn(&RomReport) -> bool| reports.iter().filter(|report| matches(report)).count();
+
+    let _ = writeln!(text, "{} ROMs: {} without problems, {} failed, {} panicked, {} never drew",
+        reports.len(),
+        count(|report| !report.has_problems()),
+        count(|report| matches!(report.outcome, Outcome::Failed(_))),
+        count(|report| matches!(report.outcome, Outcome::Panicked { .. })),
+        count(|report| matches!(report.outcome, Outcome::Ran) && !report.drew));
+
+    text
+}
+
+// The last frame at half the height, two pixels per character
+fn thumbnail(screen: &Screen) -> String {
+    let border = format!("  +{}+\n", "-".repeat(PIXELS_ALONG_X as usize));
+    let mut text = border.clone();
+
+    for rows in screen.chunks(2) {
+        text.push_str("  |");
+        for (top, bottom) in rows[0].iter().zip(rows[1].iter()) {
+            text.push(match (*top != 0, *bottom != 0) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            });
+        }
+        text.push_str("|\n");
+    }
+
+    text.push_str(&border);
+
+    text
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    }
+    else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    }
+    else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::emulator::quirks::Platform;
+
+    #[test]
+    fn reports_what_a_rom_ran_into() {
+        // LD I, #000; DRW V0, V0, 5; an unknown opcode; CALL itself until the stack overflows
+        let rom = [0xA0, 0x00, 0xD0, 0x05, 0x51, 0x21, 0x22, 0x06];
+        let options = RunOptions { frames: Some(5), ..RunOptions::default() };
+
+        let report = run_program("test".to_string(), &rom, options, None);
+
+        assert!(matches!(report.outcome, Outcome::Ran));
+        assert_eq!(report.platform, "chip8 (detected)");
+        assert!(report.drew);
+        assert_eq!(report.unknown_opcodes, BTreeMap::from([(0x5121, 0x204)]));
+        // 47 calls in 5 frames, only the first 15 fit onto the stack. That is more warnings
+        // than the emulator keeps, every one of them is counted anyway.
+        assert_eq!(report.stack_errors.count, 32);
+        assert_eq!(report.stack_errors.first, Some(Warning::StackOverflow { addr: 0x206 }));
+        assert_eq!(report.out_of_bounds.count, 0);
+    }
+
+    #[test]
+    fn chosen_platforms_are_not_detected() {
+        let rom = [0x12, 0x00];
+        let options = RunOptions { platform: Some(Platform::SuperChip), frames: Some(1), ..RunOptions::default() };
+
+        let report = run_program("test".to_string(), &rom, options, Some("given"));
+
+        assert_eq!(report.platform, "schip (given)");
+        assert!(!report.drew);
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::Path;
+
+use chip8::emulator::MAX_ROM_SIZE;
+use chip8::octo;
+use crate::cartridge::{is_cartridge, read_cartridge};
+
+
+// Reads a ROM and makes sure it fits into memory. Octo source (.8o) and cartridges are
+// compiled on the way.
+pub fn read_rom_file(path: &str) -> Result<Vec<u8>, String> {
+    let program = if is_cartridge(path) {
+        compile_octo(path, &read_cartridge(path)?.program)?
+    }
+    else if Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("8o")) {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("cannot read ROM '{}': {}", path, err))?;
+        compile_octo(path, &source)?
+    }
+    else {
+        fs::read(path)
+            .map_err(|err| format!("cannot read ROM '{}': {}", path, err))?
+    };
+
+    if program.is_empty() {
+        return Err(format!("ROM '{}' is empty", path));
+    }
+
+    if program.len() > MAX_ROM_SIZE {
+        return Err(format!("ROM '{}' is {} bytes, only {} fit into memory", path, program.len(), MAX_ROM_SIZE));
+    }
+
+    Ok(program)
+}
+
+fn compile_octo(path: &str, source: &str) -> Result<Vec<u8>, String> {
+    octo::compile(source).map_err(|err| format!("cannot compile '{}': {}", path, err))
+}
+
+// ROMs are identified by the SHA-1 of their contents, as lowercase hex
+pub fn rom_hash(program: &[u8]) -> String {
+    sha1_smol::Sha1::from(program).digest().to_string()
+}
+
+// The file name of a ROM without the directory and the extension, for titles
+pub fn rom_name(path: &str) -> String {
+    Path::new(path).file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::config::Profile;
+
+// The database is part of the binary, so known ROMs work without any setup
+const DATABASE: &str = include_str!("romdb.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<String>,
+    pub speed: Option<u32>,
+    // Which keypad keys the game uses, shown when it's loaded
+    pub keys: Option<String>,
+}
+
+impl RomInfo {
+    // The settings of the ROM, in the same shape as a profile of the config file
+    pub fn profile(&self) -> Profile {
+        Profile {
+            name: Some(self.title.clone()),
+            platform: self.platform.clone(),
+            quirks: self.quirks.clone(),
+            speed: self.speed,
+            ..Profile::default()
+        }
+    }
+}
+
+// Looks a ROM up by the SHA-1 of its contents
+pub fn lookup(hash: &str) -> Option<&'static RomInfo> {
+    find(database(), hash)
+}
+
+fn find<'a>(entries: &'a HashMap<String, RomInfo>, hash: &str) -> Option<&'a RomInfo> {
+    entries.get(&hash.to_ascii_lowercase())
+}
+
+fn parse(text: &str) -> Result<HashMap<String, RomInfo>, String> {
+    toml::from_str(text).map_err(|err| err.to_string())
+}
+
+// The database is parsed the first time a ROM is looked up. The tests make sure it parses,
+// should it still be broken no ROM is recognised.
+fn database() -> &'static HashMap<String, RomInfo> {
+    static ENTRIES: OnceLock<HashMap<String, RomInfo>> = OnceLock::new();
+
+    ENTRIES.get_or_init(|| match parse(DATABASE) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("warning: the built-in ROM database is invalid: {}", err);
+            HashMap::new()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chip8::emulator::quirks::{Platform, Quirks};
+    use crate::cli::RunOptions;
+    use crate::rom::rom_hash;
+
+    // LD V0, #0A; LD F, V0; DRW V0, V0, 5; JP #206
+    const PROGRAM: [u8; 8] = [0x60, 0x0A, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+
+    // Entries need a SHA-1 as key and names the options know
+    fn check(entries: &HashMap<String, RomInfo>) {
+        for (hash, info) in entries {
+            assert_eq!(hash.len(), 40, "{} is not a SHA-1", hash);
+            assert!(hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()),
+                "{} must be lowercase hex", hash);
+
+            if let Some(platform) = &info.platform {
+                assert!(Platform::from_name(platform).is_some(), "{}: unknown platform", hash);
+            }
+            if let Some(quirks) = &info.quirks {
+                assert!(Quirks::preset(quirks).is_some(), "{}: unknown quirk preset", hash);
+            }
+        }
+    }
+
+    #[test]
+    fn built_in_entries_are_valid() {
+        check(&parse(DATABASE).unwrap());
+    }
+
+    #[test]
+    fn known_roms_get_their_platform_and_quirks() {
+        let entries = parse(&format!("
+            [{}]
+            title = \"Digit\"
+            platform = \"schip\"
+            quirks = \"vip\"
+            speed = 15
+        ", rom_hash(&PROGRAM))).unwrap();
+        check(&entries);
+
+        // Hashes are looked up whatever their case
+        let info = find(&entries, &rom_hash(&PROGRAM).to_ascii_uppercase()).unwrap();
+        assert_eq!(info.title, "Digit");
+
+        let mut options = RunOptions::default();
+        info.profile().apply(&mut options).unwrap();
+        assert_eq!(options.platform, Some(Platform::SuperChip));
+        assert_eq!(options.quirks, Quirks::preset("vip"));
+        assert_eq!(options.speed, Some(15));
+    }
+
+    #[test]
+    fn unknown_hash_is_not_found() {
+        assert!(lookup(&"0".repeat(40)).is_none());
+        assert!(lookup(&rom_hash(&PROGRAM)).is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let text = format!("[{}]\ntitle = \"Digit\"\nplatfrom = \"schip\"\n", "0".repeat(40));
+        assert!(matches!(parse(&text), Err(err) if err.contains("platfrom")));
+    }
+}